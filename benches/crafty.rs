@@ -0,0 +1,134 @@
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::Criterion;
+use criterion::Throughput;
+use crafty::Interpreter;
+
+// A baseline for changes to how programs are scanned, parsed and run: `cargo bench`, or
+// `cargo bench --bench crafty -- run/` for one group. Criterion keeps the previous results in
+// target/criterion and reports the difference.
+
+// the same mix of declarations, loops, calls and literals over and over, about 1 MB
+fn large_file() -> String {
+    let chunk = r#"
+// a comment before each copy
+fun area_NUMBER(width: Integer, height: Integer) -> Integer {
+  var result = width * height;
+  if (result > 100 and width != height) {
+    print "large " + str(result);
+  } else {
+    print [width, height, 3.25, true];
+  }
+  return result;
+}
+for (var i = 0; i <= 10; i = i + 1) area_NUMBER(i, -i / 2);
+"#;
+    (0..4000).map(|number| chunk.replace("NUMBER", &number.to_string())).collect()
+}
+
+// statements of `depth` nested parentheses around alternating operators, as deep as the parser allows
+fn nested_expressions(depth: usize) -> String {
+    let operators = ["+", "*", "-", "/", "<", "==", "and", "or"];
+    let mut expression = "x".to_string();
+    for level in 0..depth {
+        expression = format!("({} {} {})", expression, operators[level % operators.len()], level);
+    }
+    (0..200).map(|_| format!("print {};\n", expression)).collect()
+}
+
+const FIB: &str = r#"
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+fib(20);
+"#;
+
+// There is no way to change a list's elements yet, so rather than a sieve the primes below 2000
+// are found by trial division.
+const PRIMES: &str = r#"
+var count = 0;
+for (var n = 2; n < 2000; n = n + 1) {
+  var prime = true;
+  for (var d = 2; prime and d * d <= n; d = d + 1) {
+    if (n % d == 0) prime = false;
+  }
+  if (prime) count = count + 1;
+}
+count;
+"#;
+
+// reading variables that hold a long string and a list of them, which only share the values
+const READS: &str = r#"
+var text = "abcdefghij" * 1000;
+var texts = [];
+for (var i = 0; i < 100; i = i + 1) texts = [...texts, text];
+var total = 0;
+for (var i = 0; i < 10000; i = i + 1) {
+  var copy = texts;
+  total = total + len(copy) + len(text);
+}
+total;
+"#;
+
+// loops whose bodies are blocks declaring variables, each time round a new scope
+const BLOCKS: &str = r#"
+var total = 0;
+for (var i = 0; i < 300; i = i + 1) {
+  var row = i * 2;
+  for (var j = 0; j < 100; j = j + 1) {
+    var cell = row + j;
+    total = total + cell;
+  }
+}
+total;
+"#;
+
+const LOOP: &str = r#"
+var total = 0;
+for (var i = 0; i < 100000; i = i + 1) total = total + i;
+total;
+"#;
+
+fn scan(c: &mut Criterion) {
+    let source = large_file();
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    // past PARALLEL_THRESHOLD, so scan_tokens splits it between threads
+    group.bench_function("large file", |b| b.iter(|| crafty::scanner::scan_tokens(&source)));
+    group.bench_function("large file on one thread", |b| b.iter(|| crafty::scanner::Scanner::new(&source).collect::<Vec<_>>()));
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in [("large file", large_file()), ("nested expressions", nested_expressions(200))] {
+        let tokens = crafty::scanner::scan_tokens(&source);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_function(name, |b| b.iter(|| {
+            let (statements, errors) = crafty::parser::parse(&tokens);
+            assert!(errors.is_empty());
+            statements
+        }));
+    }
+    group.finish();
+}
+
+// only the run is measured, each on a fresh interpreter with the program already parsed
+fn run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    group.sample_size(20);
+    for (name, source) in [("fib", FIB), ("primes", PRIMES), ("reads", READS), ("blocks", BLOCKS), ("loop", LOOP)] {
+        let statements = crafty::parse_source(source).unwrap();
+        group.bench_function(name, |b| b.iter_batched(
+            Interpreter::new,
+            |mut interpreter| interpreter.execute(&statements).unwrap(),
+            BatchSize::SmallInput,
+        ));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scan, parse, run);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use crafty::parser::Parser;
+use crafty::scanner::Scanner;
+
+// any input parses to statements and errors, never a panic or a stack overflow; both the
+// collected token stream and the one parsed straight from the scanner, statement by statement
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+    let tokens = crafty::scanner::scan_tokens(&source);
+    crafty::parser::parse(&tokens);
+
+    let mut parser = Parser::new(Scanner::new(&source));
+    while parser.next_statement().is_some() {}
+
+    Parser::new(Scanner::new(&source)).parse_expression().ok();
+});
@@ -0,0 +1,35 @@
+#ifndef CRAFTY_H
+#define CRAFTY_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Returned by crafty_eval */
+#define CRAFTY_OK 0
+#define CRAFTY_COMPILE_ERROR 1
+#define CRAFTY_RUNTIME_ERROR 2
+/* the script called exit(), the result holds the status */
+#define CRAFTY_EXIT 3
+#define CRAFTY_INVALID_ARGUMENT -1
+
+typedef struct Crafty Crafty;
+
+/* A fresh interpreter, release it with crafty_free. */
+Crafty *crafty_new(void);
+
+/* Runs NUL terminated UTF-8 source; globals persist between calls on the same handle. */
+int crafty_eval(Crafty *crafty, const char *source);
+
+/* The value of the last successful crafty_eval or the error message of a failed one.
+ * Owned by the handle, valid until the next crafty_eval or crafty_free. */
+const char *crafty_get_string_result(const Crafty *crafty);
+
+/* Releases the handle, NULL is ignored. */
+void crafty_free(Crafty *crafty);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
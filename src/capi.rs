@@ -0,0 +1,101 @@
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::os::raw::c_int;
+use std::panic;
+use std::ptr;
+use crate::CraftyError;
+use crate::Interpreter;
+
+// Status codes returned by crafty_eval, mirrored in include/crafty.h
+pub const CRAFTY_OK: c_int = 0;
+pub const CRAFTY_COMPILE_ERROR: c_int = 1;
+pub const CRAFTY_RUNTIME_ERROR: c_int = 2;
+// the script called exit(), the result holds the status
+pub const CRAFTY_EXIT: c_int = 3;
+pub const CRAFTY_INVALID_ARGUMENT: c_int = -1;
+
+// Opaque to C: an interpreter plus the text of its last result, kept alive so the pointer
+// handed out by crafty_get_string_result stays valid until the next call.
+pub struct Crafty {
+    interpreter: Interpreter,
+    result: CString,
+}
+
+impl Crafty {
+    fn set_result(&mut self, text: &str) {
+        // C strings can't hold interior NULs
+        self.result = CString::new(text.replace('\0', "")).unwrap_or_default();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn crafty_new() -> *mut Crafty {
+    Box::into_raw(Box::new(Crafty{
+        interpreter: Interpreter::new(),
+        result: CString::default(),
+    }))
+}
+
+/// Runs NUL terminated UTF-8 source. Globals persist between calls on the same handle.
+///
+/// # Safety
+///
+/// `crafty` must come from `crafty_new` and not have been freed, `source` must be a valid
+/// NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn crafty_eval(crafty: *mut Crafty, source: *const c_char) -> c_int {
+    let crafty = match crafty.as_mut() {
+        Some(crafty) => crafty,
+        None => return CRAFTY_INVALID_ARGUMENT,
+    };
+    if source.is_null() {
+        crafty.set_result("source is NULL");
+        return CRAFTY_INVALID_ARGUMENT;
+    }
+    let source = match CStr::from_ptr(source).to_str() {
+        Ok(source) => source,
+        Err(_) => {
+            crafty.set_result("source is not valid UTF-8");
+            return CRAFTY_INVALID_ARGUMENT;
+        },
+    };
+
+    // unwinding into C is undefined behaviour, so a panic becomes a runtime error
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| crafty.interpreter.interpret(source)));
+    let (status, text) = match result {
+        Ok(Ok(value)) => (CRAFTY_OK, value.to_string()),
+        Ok(Err(CraftyError::Exit(error))) => (CRAFTY_EXIT, error.exit.unwrap_or(0).to_string()),
+        Ok(Err(error @ (CraftyError::Runtime(_) | CraftyError::ResourceExhausted(_)))) => (CRAFTY_RUNTIME_ERROR, error.to_string()),
+        Ok(Err(error)) => (CRAFTY_COMPILE_ERROR, error.to_string()),
+        Err(_) => (CRAFTY_RUNTIME_ERROR, "internal interpreter error".to_string()),
+    };
+    crafty.set_result(&text);
+    status
+}
+
+/// The value of the last successful crafty_eval, or the error message when it failed. The
+/// string belongs to the handle and is only valid until the next crafty_eval or crafty_free.
+///
+/// # Safety
+///
+/// `crafty` must come from `crafty_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn crafty_get_string_result(crafty: *const Crafty) -> *const c_char {
+    match crafty.as_ref() {
+        Some(crafty) => crafty.result.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Releases the handle; passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `crafty` must come from `crafty_new` and must not be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn crafty_free(crafty: *mut Crafty) {
+    if !crafty.is_null() {
+        drop(Box::from_raw(crafty));
+    }
+}
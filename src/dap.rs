@@ -0,0 +1,444 @@
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::mem;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use crate::debugger::evaluate;
+use crate::json::Json;
+use crate::EXIT_NO_INPUT;
+use crate::Interpreter;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::runtime::ExprEvaluator;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+use crate::runtime::StatementHook;
+
+// The program runs on a single thread, so there is only ever one thread. Calls set the caller's
+// locals aside without keeping a stack of them, so there is only one frame too: the statement
+// that is running, with the scopes it can see.
+const THREAD_ID: f64 = 1.0;
+const FRAME_ID: f64 = 1.0;
+
+// far more than any request needs, a Content-Length above it is skipped rather than read in
+const MAX_MESSAGE_LENGTH: u64 = 8 << 20;
+
+// Debug Adapter Protocol server speaking Content-Length framed JSON over stdin/stdout.
+// Requests are handled synchronously: while the program is stopped, the statement hook
+// itself reads and answers requests until one of them resumes execution.
+pub fn serve() -> i32 {
+    let connection = Arc::new(Mutex::new(Connection{
+        reader: Box::new(io::BufReader::new(io::stdin())),
+        writer: Box::new(io::stdout()),
+        seq: 1,
+        disconnected: false,
+    }));
+    let mut debugger = Debugger::default();
+
+    loop {
+        let request = match lock(&connection).read_message() {
+            Some(request) => request,
+            None => return 0,
+        };
+
+        match handle_request(&connection, &mut debugger, &request, None) {
+            Action::Launch => run_program(&connection, mem::take(&mut debugger)),
+            Action::Disconnect => return 0,
+            Action::Wait | Action::Resume => {},
+        }
+
+        if lock(&connection).disconnected {
+            return 0;
+        }
+    }
+}
+
+struct Connection {
+    reader: Box<dyn BufRead + Send>,
+    writer: Box<dyn Write + Send>,
+    seq: u64,
+    disconnected: bool,
+}
+
+// a panic while holding the lock leaves the connection usable, the protocol state is all in the messages
+fn lock(connection: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    connection.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Connection {
+    fn read_message(&mut self) -> Option<Json> {
+        loop {
+            let mut content_length = None;
+            loop {
+                let mut header = String::new();
+                if self.reader.read_line(&mut header).ok()? == 0 {
+                    return None;
+                }
+                let header = header.trim();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some(length) = header.strip_prefix("Content-Length:") {
+                    content_length = length.trim().parse::<u64>().ok();
+                }
+            }
+
+            let length = content_length?;
+            if length > MAX_MESSAGE_LENGTH {
+                // dropped whole, so the next message is still read from its start
+                let skipped = io::copy(&mut self.reader.by_ref().take(length), &mut io::sink()).ok()?;
+                if skipped < length {
+                    return None;
+                }
+                continue;
+            }
+            let mut body = Vec::new();
+            self.reader.by_ref().take(length).read_to_end(&mut body).ok()?;
+            if (body.len() as u64) < length {
+                return None;
+            }
+            match Json::parse(&String::from_utf8_lossy(&body)) {
+                Ok(message) => return Some(message),
+                // answered like a request that failed, which one it was can't be known
+                Err(error) => self.respond_error(&Json::Null, &format!("Malformed message: {}", error)),
+            }
+        }
+    }
+
+    fn send(&mut self, message_type: &str, mut fields: Vec<(&str, Json)>) {
+        fields.insert(0, ("seq", Json::Number(self.seq as f64)));
+        fields.insert(1, ("type", Json::string(message_type)));
+        self.seq += 1;
+
+        let body = Json::object(fields).to_string();
+        // a client that went away will notice on its own, nothing sensible to do about it here
+        let _ = write!(self.writer, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.writer.flush();
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) {
+        self.send("response", vec![
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", Json::Bool(true)),
+            ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+            ("body", body),
+        ]);
+    }
+
+    fn respond_error(&mut self, request: &Json, message: &str) {
+        self.send("response", vec![
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", Json::Bool(false)),
+            ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+            ("message", Json::string(message)),
+        ]);
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send("event", vec![
+            ("event", Json::string(event)),
+            ("body", body),
+        ]);
+    }
+
+    fn output(&mut self, category: &str, text: &str) {
+        self.event("output", Json::object(vec![
+            ("category", Json::string(category)),
+            ("output", Json::string(text)),
+        ]));
+    }
+}
+
+#[derive(Default)]
+enum StepMode {
+    #[default]
+    Run,
+    Entry,
+    // stop at the next statement on another line
+    Step(u32),
+    // stop once the scope depth drops below the given one
+    StepOut(usize),
+}
+
+#[derive(Default)]
+struct Debugger {
+    program: String,
+    args: Vec<String>,
+    breakpoints: Vec<u32>,
+    mode: StepMode,
+    line: u32,
+    column: u32,
+    depth: usize,
+}
+
+enum Action {
+    Wait,
+    Resume,
+    Launch,
+    Disconnect,
+}
+
+fn handle_request(connection: &Mutex<Connection>, debugger: &mut Debugger, request: &Json, evaluator: Option<&mut ExprEvaluator>) -> Action {
+    let command = request.get("command").and_then(Json::as_str).unwrap_or("");
+    let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+
+    match command {
+        "initialize" => {
+            let mut connection = lock(connection);
+            connection.respond(request, Json::object(vec![
+                ("supportsConfigurationDoneRequest", Json::Bool(true)),
+                ("supportsEvaluateForHovers", Json::Bool(true)),
+            ]));
+            connection.event("initialized", Json::Null);
+            Action::Wait
+        },
+        "launch" => {
+            debugger.program = arguments.get("program").and_then(Json::as_str).unwrap_or("").to_string();
+            debugger.args = arguments.get("args").and_then(Json::as_array).unwrap_or(&[])
+                .iter()
+                .filter_map(|arg| arg.as_str().map(str::to_string))
+                .collect();
+            if arguments.get("stopOnEntry").and_then(Json::as_bool) == Some(true) {
+                debugger.mode = StepMode::Entry;
+            }
+            lock(connection).respond(request, Json::Null);
+            Action::Wait
+        },
+        "setBreakpoints" => {
+            debugger.breakpoints = arguments.get("breakpoints").and_then(Json::as_array).unwrap_or(&[])
+                .iter()
+                .filter_map(|breakpoint| breakpoint.get("line").and_then(Json::as_f64))
+                .map(|line| line as u32)
+                .collect();
+            let verified = debugger.breakpoints.iter()
+                .map(|line| Json::object(vec![("verified", Json::Bool(true)), ("line", Json::Number(*line as f64))]))
+                .collect();
+            lock(connection).respond(request, Json::object(vec![("breakpoints", Json::Array(verified))]));
+            Action::Wait
+        },
+        "configurationDone" => {
+            lock(connection).respond(request, Json::Null);
+            Action::Launch
+        },
+        "threads" => {
+            let thread = Json::object(vec![("id", Json::Number(THREAD_ID)), ("name", Json::string("main"))]);
+            lock(connection).respond(request, Json::object(vec![("threads", Json::Array(vec![thread]))]));
+            Action::Wait
+        },
+        "stackTrace" => {
+            let frames = match evaluator {
+                Some(_) => vec![Json::object(vec![
+                    ("id", Json::Number(FRAME_ID)),
+                    ("name", Json::string("main")),
+                    ("line", Json::Number(debugger.line as f64)),
+                    ("column", Json::Number(debugger.column as f64 + 1.0)),
+                    ("source", Json::object(vec![("path", Json::string(&debugger.program))])),
+                ])],
+                None => Vec::new(),
+            };
+            let total = frames.len() as f64;
+            lock(connection).respond(request, Json::object(vec![
+                ("stackFrames", Json::Array(frames)),
+                ("totalFrames", Json::Number(total)),
+            ]));
+            Action::Wait
+        },
+        "scopes" => {
+            // variablesReference 1 is the globals, n past that the environment at index n - 2,
+            // innermost first
+            let scopes = match evaluator {
+                Some(evaluator) => (0..=evaluator.environments().len()).rev()
+                    .map(|index| Json::object(vec![
+                        ("name", Json::string(if index == 0 { "Globals" } else { "Block" })),
+                        ("variablesReference", Json::Number(index as f64 + 1.0)),
+                        ("expensive", Json::Bool(false)),
+                    ]))
+                    .collect(),
+                None => Vec::new(),
+            };
+            lock(connection).respond(request, Json::object(vec![("scopes", Json::Array(scopes))]));
+            Action::Wait
+        },
+        "variables" => {
+            let reference = arguments.get("variablesReference").and_then(Json::as_f64).unwrap_or(0.0) as usize;
+            let scope: Option<Vec<(&String, &Object)>> = evaluator.and_then(|evaluator| match reference {
+                1 => Some(evaluator.globals().iter().collect()),
+                _ => evaluator.environments().get(reference.wrapping_sub(2)).map(|environment| environment.values.iter().collect()),
+            });
+            let variables = match scope {
+                Some(mut bindings) => {
+                    bindings.retain(|(_, value)| !matches!(value, Object::NativeFunction(_)));
+                    bindings.sort_by(|a, b| a.0.cmp(b.0));
+                    bindings.into_iter()
+                        .map(|(name, value)| variable_json(name, value))
+                        .collect()
+                },
+                None => Vec::new(),
+            };
+            lock(connection).respond(request, Json::object(vec![("variables", Json::Array(variables))]));
+            Action::Wait
+        },
+        "evaluate" => {
+            let expression = arguments.get("expression").and_then(Json::as_str).unwrap_or("");
+            let result = match evaluator {
+                Some(evaluator) => evaluate(evaluator, expression),
+                None => Err("the program is not running".to_string()),
+            };
+            match result {
+                Ok(object) => lock(connection).respond(request, Json::object(vec![
+                    ("result", Json::string(&object.to_string())),
+                    ("type", Json::string(object.type_name())),
+                    ("variablesReference", Json::Number(0.0)),
+                ])),
+                Err(message) => lock(connection).respond_error(request, &message),
+            }
+            Action::Wait
+        },
+        "continue" => {
+            debugger.mode = StepMode::Run;
+            lock(connection).respond(request, Json::object(vec![("allThreadsContinued", Json::Bool(true))]));
+            Action::Resume
+        },
+        "next" | "stepIn" => {
+            debugger.mode = StepMode::Step(debugger.line);
+            lock(connection).respond(request, Json::Null);
+            Action::Resume
+        },
+        "stepOut" => {
+            debugger.mode = StepMode::StepOut(debugger.depth);
+            lock(connection).respond(request, Json::Null);
+            Action::Resume
+        },
+        "disconnect" | "terminate" => {
+            let mut connection = lock(connection);
+            connection.disconnected = true;
+            connection.respond(request, Json::Null);
+            Action::Disconnect
+        },
+        "setExceptionBreakpoints" | "pause" => {
+            lock(connection).respond(request, Json::Null);
+            Action::Wait
+        },
+        _ => {
+            lock(connection).respond_error(request, &format!("unsupported request '{}'", command));
+            Action::Wait
+        },
+    }
+}
+
+fn variable_json(name: &str, value: &Object) -> Json {
+    Json::object(vec![
+        ("name", Json::string(name)),
+        ("value", Json::string(&value.to_string())),
+        ("type", Json::string(value.type_name())),
+        ("variablesReference", Json::Number(0.0)),
+    ])
+}
+
+fn run_program(connection: &Arc<Mutex<Connection>>, debugger: Debugger) {
+    let exit_code = match fs::read_to_string(&debugger.program) {
+        Ok(source) => {
+            let mut interpreter = Interpreter::new();
+            let mut script_args = vec![debugger.program.to_string()];
+            script_args.extend(debugger.args.iter().cloned());
+            interpreter.set_script_args(script_args);
+            // whole lines, so a single print doesn't turn into one output event per fragment
+            interpreter.set_output(Box::new(io::LineWriter::new(DebugOutput{connection: connection.clone(), category: "stdout"})));
+            interpreter.set_error_output(Box::new(io::LineWriter::new(DebugOutput{connection: connection.clone(), category: "stderr"})));
+            interpreter.set_hook(Box::new(DebugHook{connection: connection.clone(), debugger}));
+
+            match interpreter.interpret(&source) {
+                Ok(_) => 0,
+                Err(error) => {
+                    if error.exit_status().is_none() {
+                        interpreter.report(&error);
+                    }
+                    error.exit_code()
+                },
+            }
+        },
+        Err(error) => {
+            lock(connection).output("stderr", &format!("Could not read '{}': {}\n", debugger.program, error));
+            EXIT_NO_INPUT
+        },
+    };
+
+    let mut connection = lock(connection);
+    if !connection.disconnected {
+        connection.event("exited", Json::object(vec![("exitCode", Json::Number(exit_code as f64))]));
+        connection.event("terminated", Json::Null);
+    }
+}
+
+struct DebugOutput {
+    connection: Arc<Mutex<Connection>>,
+    category: &'static str,
+}
+
+impl Write for DebugOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        lock(&self.connection).output(self.category, &String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct DebugHook {
+    connection: Arc<Mutex<Connection>>,
+    debugger: Debugger,
+}
+
+impl StatementHook for DebugHook {
+    fn before_statement(&mut self, evaluator: &mut ExprEvaluator, statement: &Statement) -> Result<(), RuntimeError> {
+        // blocks are only containers, their first statement is where execution actually is
+        if let StatementKind::Block(_) = statement.kind {
+            return Ok(());
+        }
+
+        let line = statement.span.line_number;
+        let depth = evaluator.environments().len();
+        let reason = match self.debugger.mode {
+            StepMode::Entry => Some("entry"),
+            StepMode::Step(from) if line != from => Some("step"),
+            StepMode::StepOut(from) if depth < from => Some("step"),
+            _ if line != self.debugger.line && self.debugger.breakpoints.contains(&line) => Some("breakpoint"),
+            _ => None,
+        };
+        self.debugger.line = line;
+        self.debugger.column = statement.span.column_number;
+        self.debugger.depth = depth;
+
+        let reason = match reason {
+            Some(reason) => reason,
+            None => return Ok(()),
+        };
+
+        lock(&self.connection).event("stopped", Json::object(vec![
+            ("reason", Json::string(reason)),
+            ("threadId", Json::Number(THREAD_ID)),
+            ("allThreadsStopped", Json::Bool(true)),
+        ]));
+
+        loop {
+            let request = lock(&self.connection).read_message();
+            let action = match request {
+                Some(request) => handle_request(&self.connection, &mut self.debugger, &request, Some(&mut *evaluator)),
+                None => Action::Disconnect,
+            };
+
+            match action {
+                Action::Resume => return Ok(()),
+                Action::Disconnect => return Err(RuntimeError::new("Debug session terminated".to_string())),
+                Action::Wait | Action::Launch => {},
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use crate::parser;
+use crate::parser::ExprKind;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::parser::Visitor;
+use crate::runtime::ExprEvaluator;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+use crate::runtime::StatementHook;
+
+const HELP: &str = "Commands:
+  break <line>   stop when execution reaches the line (b)
+  step           run to the next statement (s)
+  next           run to the next statement, stepping over nested blocks (n)
+  continue       run until the next breakpoint (c)
+  print <expr>   evaluate an expression in the current scope (p)
+  quit           stop the program (q)";
+
+enum StepMode {
+    Step,
+    // stop at the next statement on another line that is not nested deeper than the given scope depth
+    Next(u32, usize),
+    Continue,
+}
+
+// Interactive command line debugger, asked by the evaluator before every statement whether to stop.
+pub struct Debugger {
+    lines: Vec<String>,
+    breakpoints: Vec<u32>,
+    mode: StepMode,
+    line: u32,
+    input: Box<dyn BufRead + Send>,
+}
+
+impl Debugger {
+    pub fn new(source: &str) -> Debugger {
+        Debugger{
+            lines: source.lines().map(str::to_string).collect(),
+            breakpoints: Vec::new(),
+            // stop before the first statement so breakpoints can be set
+            mode: StepMode::Step,
+            line: 0,
+            input: Box::new(io::BufReader::new(io::stdin())),
+        }
+    }
+
+    fn read_command(&mut self) -> Option<String> {
+        print!("(crafty) ");
+        io::stdout().flush().ok()?;
+
+        let mut command = String::new();
+        match self.input.read_line(&mut command) {
+            Ok(0) | Err(_) => {
+                println!();
+                None
+            },
+            Ok(_) => Some(command.trim().to_string()),
+        }
+    }
+
+    fn show_line(&self, line: u32) {
+        let text = self.lines.get(line as usize - 1).map(String::as_str).unwrap_or("");
+        println!("[line {}] {}", line, text.trim());
+    }
+}
+
+impl StatementHook for Debugger {
+    fn before_statement(&mut self, evaluator: &mut ExprEvaluator, statement: &Statement) -> Result<(), RuntimeError> {
+        // blocks are only containers, their first statement is where execution actually is
+        if let StatementKind::Block(_) = statement.kind {
+            return Ok(());
+        }
+
+        let line = statement.span.line_number;
+        let depth = evaluator.environments().len();
+        let stop = match self.mode {
+            StepMode::Step => true,
+            StepMode::Next(from, from_depth) if line != from && depth <= from_depth => true,
+            _ => line != self.line && self.breakpoints.contains(&line),
+        };
+        self.line = line;
+        if !stop {
+            return Ok(());
+        }
+
+        self.show_line(line);
+        loop {
+            let command = match self.read_command() {
+                Some(command) => command,
+                None => return Err(RuntimeError::new("Debug session terminated".to_string())),
+            };
+            let (name, argument) = match command.split_once(' ') {
+                Some((name, argument)) => (name, argument.trim()),
+                None => (command.as_str(), ""),
+            };
+
+            match name {
+                "" => {},
+                "b" | "break" => match argument.parse::<u32>() {
+                    Ok(line) if line >= 1 && line as usize <= self.lines.len() => {
+                        if !self.breakpoints.contains(&line) {
+                            self.breakpoints.push(line);
+                        }
+                        println!("Breakpoint set at line {}", line);
+                    },
+                    _ => println!("Expected a line number between 1 and {}", self.lines.len()),
+                },
+                "s" | "step" => {
+                    self.mode = StepMode::Step;
+                    return Ok(());
+                },
+                "n" | "next" => {
+                    self.mode = StepMode::Next(line, depth);
+                    return Ok(());
+                },
+                "c" | "continue" => {
+                    self.mode = StepMode::Continue;
+                    return Ok(());
+                },
+                "p" | "print" => match evaluate(evaluator, argument) {
+                    Ok(object) => println!("{}", object),
+                    Err(message) => println!("{}", message),
+                },
+                "q" | "quit" => return Err(RuntimeError::new("Debug session terminated".to_string())),
+                "h" | "help" => println!("{}", HELP),
+                _ => println!("Unknown command '{}', try 'help'", name),
+            }
+        }
+    }
+}
+
+// evaluates a side effect free expression against the paused program's current scope
+pub fn evaluate(evaluator: &mut ExprEvaluator, expression: &str) -> Result<Object, String> {
+    let tokens = crate::scan(expression).map_err(|error| error.message().to_string())?;
+    let expr = parser::parse_expression(&tokens).map_err(|error| error.message)?;
+    match expr.kind {
+        // inspecting the program shouldn't change it
+        ExprKind::Assign(..) => Err(format!("'{}' is not an expression", expression)),
+        _ => evaluator.visit_expr(&expr).map_err(|error| error.message),
+    }
+}
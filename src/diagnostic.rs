@@ -0,0 +1,209 @@
+mod render;
+use std::fmt;
+use crate::json::Json;
+use crate::printer::json::span_json;
+use crate::runtime::Resource;
+use crate::scanner::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+// Stable identifier for each kind of problem, so tools can match on the code instead of the
+// message text. Codes are never reused once published.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Code {
+    // scanning
+    UnexpectedCharacter,
+    UnterminatedString,
+    MalformedNumber,
+    IntegerOutOfRange,
+    // parsing
+    ExpectedToken,
+    ExpectedExpression,
+    InvalidAssignmentTarget,
+    TooManyArguments,
+    UnexpectedToken,
+    ReturnOutsideFunction,
+    TooDeeplyNested,
+    // running; RuntimeError is what host functions fail with unless they pick a code
+    RuntimeError,
+    UndefinedVariable,
+    InvalidOperand,
+    IntegerOverflow,
+    WrongArity,
+    NotCallable,
+    InvalidArgument,
+    StackOverflow,
+    CallDepthExceeded,
+    PrintDisabled,
+    OutputFailed,
+    Interrupted,
+    IndexOutOfRange,
+    ConversionFailed,
+    InputFailed,
+    Exited,
+    HostAccessDenied,
+    FileSystemError,
+    ModuleNotFound,
+    ImportFailed,
+    ImportCycle,
+    CoroutineError,
+    DivisionByZero,
+    // execution budget
+    StatementBudget,
+    ExpressionBudget,
+    MemoryBudget,
+    OutputBudget,
+    LoopIterationLimit,
+    StepLimit,
+    // type checking
+    TypeMismatch,
+    UnknownType,
+    // lints
+    UnusedVariable,
+    ShadowedVariable,
+    UnusedAssignment,
+    SelfComparison,
+    UnreachableCode,
+    AssignmentInCondition,
+    InfiniteLoop,
+    // a bug in crafty rather than in the program
+    Internal,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => "E0001",
+            Code::UnterminatedString => "E0002",
+            Code::MalformedNumber => "E0003",
+            Code::IntegerOutOfRange => "E0004",
+            Code::ExpectedToken => "E0100",
+            Code::ExpectedExpression => "E0101",
+            Code::InvalidAssignmentTarget => "E0102",
+            Code::TooManyArguments => "E0103",
+            Code::UnexpectedToken => "E0104",
+            Code::ReturnOutsideFunction => "E0105",
+            Code::TooDeeplyNested => "E0106",
+            Code::RuntimeError => "E0200",
+            Code::UndefinedVariable => "E0201",
+            Code::InvalidOperand => "E0202",
+            Code::IntegerOverflow => "E0203",
+            Code::WrongArity => "E0204",
+            Code::NotCallable => "E0205",
+            Code::InvalidArgument => "E0206",
+            Code::StackOverflow => "E0207",
+            Code::CallDepthExceeded => "E0208",
+            Code::PrintDisabled => "E0209",
+            Code::OutputFailed => "E0210",
+            Code::Interrupted => "E0211",
+            Code::IndexOutOfRange => "E0212",
+            Code::ConversionFailed => "E0213",
+            Code::InputFailed => "E0214",
+            Code::Exited => "E0215",
+            Code::HostAccessDenied => "E0216",
+            Code::FileSystemError => "E0217",
+            Code::ModuleNotFound => "E0218",
+            Code::ImportFailed => "E0219",
+            Code::ImportCycle => "E0220",
+            Code::CoroutineError => "E0221",
+            Code::DivisionByZero => "E0222",
+            Code::StatementBudget => "E0300",
+            Code::ExpressionBudget => "E0301",
+            Code::MemoryBudget => "E0302",
+            Code::OutputBudget => "E0303",
+            Code::LoopIterationLimit => "E0304",
+            Code::StepLimit => "E0305",
+            Code::TypeMismatch => "E0400",
+            Code::UnknownType => "E0401",
+            Code::UnusedVariable => "W0001",
+            Code::ShadowedVariable => "W0002",
+            Code::UnusedAssignment => "W0003",
+            Code::SelfComparison => "W0004",
+            Code::UnreachableCode => "W0005",
+            Code::AssignmentInCondition => "W0006",
+            Code::InfiniteLoop => "W0007",
+            Code::Internal => "E0900",
+        }
+    }
+
+    pub fn exhausted(resource: Resource) -> Code {
+        match resource {
+            Resource::Statements => Code::StatementBudget,
+            Resource::Expressions => Code::ExpressionBudget,
+            Resource::Memory => Code::MemoryBudget,
+            Resource::Output => Code::OutputBudget,
+            Resource::LoopIterations => Code::LoopIterationLimit,
+            Resource::Steps => Code::StepLimit,
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// One problem found in a program, in the same shape whichever stage found it.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    // what went wrong, without the location; that is in span
+    pub message: String,
+    pub span: Option<Span>,
+    // extra lines of explanation, printed after the message
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic{code, severity: Severity::Error, message, span, notes: Vec::new()}
+    }
+
+    pub fn warning(code: Code, message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic{code, severity: Severity::Warning, message, span, notes: Vec::new()}
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    // one object per diagnostic, spans shaped like --dump-ast=json
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("code", Json::string(self.code.as_str())),
+            ("severity", Json::string(self.severity.as_str())),
+            ("message", Json::string(&self.message)),
+            ("span", self.span.as_ref().map_or(Json::Null, span_json)),
+            ("notes", Json::Array(self.notes.iter().map(|note| Json::string(note)).collect())),
+        ])
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity.as_str(), self.code, self.message)?;
+        if let Some(span) = &self.span {
+            write!(f, " at line {}:{}", span.line_number, span.column_number)?;
+        }
+        for note in self.notes.iter() {
+            write!(f, "\n  note: {}", note)?;
+        }
+        Ok(())
+    }
+}
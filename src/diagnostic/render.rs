@@ -0,0 +1,86 @@
+use std::fmt::Write;
+use crate::diagnostic::Diagnostic;
+use crate::diagnostic::Severity;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+// ANSI escapes, or nothing when writing somewhere colors would end up as garbage
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+impl Diagnostic {
+    // Renders the diagnostic for a terminal, rustc style: the message, where it is, the
+    // offending line of `source` with the span underlined, then the notes. `path` names the
+    // source in the location line.
+    //
+    //     error[E0100]: expected ';' after value
+    //      --> script.crafty:1:9
+    //       |
+    //     1 | print 1 2
+    //       |         ^
+    pub fn render(&self, source: &str, path: &str, color: bool) -> String {
+        let style = Style{color};
+        let severity_color = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+
+        let mut output = String::new();
+        let header = format!("{}[{}]", self.severity.as_str(), self.code);
+        let _ = writeln!(output, "{}{}", style.paint(severity_color, &header), style.paint(BOLD, &format!(": {}", self.message)));
+
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                let _ = writeln!(output, "{} {}", style.paint(BLUE, "-->"), path);
+                self.render_notes(&mut output, &style, 0);
+                return output;
+            },
+        };
+
+        let line_number = span.line_number.to_string();
+        let gutter = " ".repeat(line_number.len());
+        // columns count from 0 in spans, from 1 for people
+        let _ = writeln!(output, "{}{} {}:{}:{}", gutter, style.paint(BLUE, "-->"), path, span.line_number, span.column_number + 1);
+
+        // the span may point just past the end of the source (end of file)
+        let line = source.split('\n').nth(span.line_number as usize - 1).unwrap_or("").trim_end_matches('\r');
+        let start = span.column_number as usize;
+        let end =
+            if span.end_line_number == span.line_number {
+                span.end_column_number as usize
+            } else {
+                line.chars().count()
+            };
+        // tabs are kept so the underline lines up however wide the terminal draws them
+        let indent: String = line.chars().take(start).map(|ch| if ch == '\t' { '\t' } else { ' ' }).collect();
+        let underline = "^".repeat(end.saturating_sub(start).max(1));
+
+        let _ = writeln!(output, "{} {}", gutter, style.paint(BLUE, "|"));
+        let _ = writeln!(output, "{} {}", style.paint(BLUE, &format!("{} |", line_number)), line);
+        let _ = writeln!(output, "{} {} {}{}", gutter, style.paint(BLUE, "|"), indent, style.paint(severity_color, &underline));
+        self.render_notes(&mut output, &style, gutter.len());
+        output
+    }
+
+    fn render_notes(&self, output: &mut String, style: &Style, width: usize) {
+        for note in self.notes.iter() {
+            let _ = writeln!(output, "{} {} {}", " ".repeat(width), style.paint(BLUE, "="), style.paint(BOLD, &format!("note: {}", note)));
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::formatter::Formatter;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::parser::Visitor;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+// A top-level statement that differs between two versions of a file. Each side is the line it
+// starts on and the statement as the formatter prints it, so layout and comments don't count.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    // `fun name`, `var name` or `import "name"` for declarations, `statement` for the rest
+    pub what: String,
+    pub old: Option<(u32, String)>,
+    pub new: Option<(u32, String)>,
+}
+
+// A declaration is matched with the one of the same name in the other version wherever either
+// is, so moving one around isn't a change. The other statements are matched in order, the
+// longest run the two versions have in common staying put.
+pub fn diff(old: &[Statement], new: &[Statement]) -> Vec<Change> {
+    let old = entries(old);
+    let new = entries(new);
+    let mut changes = Vec::new();
+
+    let declared: HashMap<&(String, usize), &Entry> = new.iter()
+        .filter_map(|entry| entry.key.as_ref().map(|key| (key, entry)))
+        .collect();
+    for entry in old.iter() {
+        let key = match &entry.key {
+            Some(key) => key,
+            None => continue,
+        };
+        match declared.get(key) {
+            Some(other) if other.text == entry.text => {},
+            Some(other) => changes.push(Change{kind: ChangeKind::Changed, what: key.0.clone(), old: Some(entry.side()), new: Some(other.side())}),
+            None => changes.push(Change{kind: ChangeKind::Removed, what: key.0.clone(), old: Some(entry.side()), new: None}),
+        }
+    }
+    let old_keys: Vec<&(String, usize)> = old.iter().filter_map(|entry| entry.key.as_ref()).collect();
+    for entry in new.iter() {
+        if let Some(key) = &entry.key {
+            if !old_keys.contains(&key) {
+                changes.push(Change{kind: ChangeKind::Added, what: key.0.clone(), old: None, new: Some(entry.side())});
+            }
+        }
+    }
+
+    let old_rest: Vec<&Entry> = old.iter().filter(|entry| entry.key.is_none()).collect();
+    let new_rest: Vec<&Entry> = new.iter().filter(|entry| entry.key.is_none()).collect();
+    for edit in edits(&old_rest, &new_rest, |a, b| a.text == b.text) {
+        match edit {
+            Edit::Removed(entry) => changes.push(Change{kind: ChangeKind::Removed, what: "statement".to_string(), old: Some(entry.side()), new: None}),
+            Edit::Added(entry) => changes.push(Change{kind: ChangeKind::Added, what: "statement".to_string(), old: None, new: Some(entry.side())}),
+            Edit::Kept(_) => {},
+        }
+    }
+
+    changes.sort_by_key(|change| change.new.as_ref().or(change.old.as_ref()).map_or(0, |side| side.0));
+    changes
+}
+
+struct Entry {
+    // what it declares and how many declarations of that name came before it, None when it
+    // declares nothing
+    key: Option<(String, usize)>,
+    line_number: u32,
+    text: String,
+}
+
+impl Entry {
+    fn side(&self) -> (u32, String) {
+        (self.line_number, self.text.clone())
+    }
+}
+
+fn entries(statements: &[Statement]) -> Vec<Entry> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    statements.iter()
+        .map(|statement| {
+            let key = declares(statement).map(|name| {
+                let count = seen.entry(name.clone()).or_insert(0);
+                *count += 1;
+                (name, *count - 1)
+            });
+            let text = Formatter::new(&[]).without_blank_lines().visit_statement(statement);
+            Entry{key, line_number: statement.span.line_number, text}
+        })
+        .collect()
+}
+
+fn declares(statement: &Statement) -> Option<String> {
+    match &statement.kind {
+        StatementKind::Function(declaration) => Some(format!("fun {}", declaration.name.lexeme)),
+        StatementKind::Var(name, _, _) => Some(format!("var {}", name.lexeme)),
+        StatementKind::VarTuple(names, _) => Some(format!("var ({})", names.iter().map(|name| name.lexeme.as_str()).collect::<Vec<&str>>().join(", "))),
+        StatementKind::Import(_, name) => Some(format!("import {}", name.lexeme)),
+        _ => None,
+    }
+}
+
+enum Edit<T> {
+    Kept(T),
+    Removed(T),
+    Added(T),
+}
+
+// the shortest way from old to new keeping a longest common subsequence
+fn edits<T: Copy>(old: &[T], new: &[T], same: impl Fn(&T, &T) -> bool) -> Vec<Edit<T>> {
+    // common[i][j] is how much old[i..] and new[j..] have in common
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if same(&old[i], &new[j]) {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && same(&old[i], &new[j]) {
+            edits.push(Edit::Kept(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            edits.push(Edit::Removed(old[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Added(new[j]));
+            j += 1;
+        }
+    }
+    edits
+}
+
+// One line saying what changed and where, then the statement: with `-` and `+` on the lines
+// that differ when it changed, `-` or `+` on all of it otherwise.
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.old, &self.new) {
+            (Some((old_line, old)), Some((new_line, new))) => {
+                writeln!(f, "changed {} (line {} -> {})", self.what, old_line, new_line)?;
+                let old: Vec<&str> = old.lines().collect();
+                let new: Vec<&str> = new.lines().collect();
+                for edit in edits(&old, &new, |a, b| a == b) {
+                    match edit {
+                        Edit::Kept(line) => writeln!(f, "    {}", line)?,
+                        Edit::Removed(line) => writeln!(f, "  - {}", line)?,
+                        Edit::Added(line) => writeln!(f, "  + {}", line)?,
+                    }
+                }
+                Ok(())
+            },
+            (Some((line, text)), None) => side(f, "removed", &self.what, *line, '-', text),
+            (None, Some((line, text))) => side(f, "added", &self.what, *line, '+', text),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn side(f: &mut fmt::Formatter<'_>, verb: &str, what: &str, line: u32, mark: char, text: &str) -> fmt::Result {
+    writeln!(f, "{} {} (line {})", verb, what, line)?;
+    for line in text.lines() {
+        writeln!(f, "  {} {}", mark, line)?;
+    }
+    Ok(())
+}
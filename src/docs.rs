@@ -0,0 +1,120 @@
+use std::fmt::Write;
+use crate::highlight::escape;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::scanner::token::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Function,
+    Variable,
+}
+
+// a declaration at the top of a module, which is what other modules can use
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub kind: Kind,
+    pub name: String,
+    // `fun name(a, b) -> Type` or `var name: Type`
+    pub signature: String,
+    pub doc: Option<String>,
+    pub line_number: u32,
+}
+
+// The top-level functions and variables of a module, each with its documentation: the `///`
+// lines right above it (a blank line in between detaches them), then for a function the string
+// its body starts with. `tokens` are the module's tokens with trivia attached, as from
+// scan_with_trivia, which is where the comments are.
+pub fn items(tokens: &[Token], statements: &[Statement]) -> Vec<Item> {
+    let mut items = Vec::new();
+    for statement in statements.iter() {
+        let comments = doc_comments(tokens, statement);
+        let (kind, names, signature, docstring) = match &statement.kind {
+            StatementKind::Function(declaration) => (Kind::Function, vec![declaration.name.lexeme.to_string()], declaration.signature(), declaration.doc()),
+            StatementKind::Var(name, annotation, _) => {
+                let signature = match annotation {
+                    Some(annotation) => format!("var {}: {}", name.lexeme, annotation.lexeme),
+                    None => format!("var {}", name.lexeme),
+                };
+                (Kind::Variable, vec![name.lexeme.to_string()], signature, None)
+            },
+            StatementKind::VarTuple(names, _) => {
+                let names: Vec<String> = names.iter().map(|name| name.lexeme.to_string()).collect();
+                let signature = format!("var ({})", names.join(", "));
+                (Kind::Variable, names, signature, None)
+            },
+            _ => continue,
+        };
+        let doc = match (comments, docstring) {
+            (Some(comments), Some(docstring)) => Some(format!("{}\n\n{}", comments, docstring)),
+            (comments, docstring) => comments.or(docstring),
+        };
+        for name in names {
+            items.push(Item{kind, name, signature: signature.clone(), doc: doc.clone(), line_number: statement.span.line_number});
+        }
+    }
+    items
+}
+
+// the `///` lines leading up to the statement's first token, without the slashes
+fn doc_comments(tokens: &[Token], statement: &Statement) -> Option<String> {
+    let start = (statement.span.line_number, statement.span.column_number);
+    let first = tokens.binary_search_by_key(&start, |token| (token.line_number, token.column_number)).ok()?;
+    let trivia: String = tokens[first].leading_trivia.iter().map(|token| token.lexeme.as_str()).collect();
+
+    // the last line is the declaration's own indentation
+    let mut lines: Vec<&str> = trivia.split('\n').collect();
+    lines.pop();
+    let mut comments: Vec<&str> = lines.iter().rev()
+        .map(|line| line.trim())
+        .take_while(|line| line.starts_with("///"))
+        .map(|line| {
+            let line = &line[3..];
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        })
+        .collect();
+    if comments.is_empty() {
+        return None;
+    }
+    comments.reverse();
+    Some(comments.join("\n"))
+}
+
+pub fn markdown(module: &str, items: &[Item]) -> String {
+    let mut out = format!("# {}\n", module);
+    for (kind, heading) in [(Kind::Function, "Functions"), (Kind::Variable, "Variables")] {
+        let items: Vec<&Item> = items.iter().filter(|item| item.kind == kind).collect();
+        if items.is_empty() {
+            continue;
+        }
+        let _ = write!(out, "\n## {}\n", heading);
+        for item in items {
+            let _ = write!(out, "\n### `{}`\n\n```\n{}\n```\n", item.name, item.signature);
+            if let Some(doc) = &item.doc {
+                let _ = write!(out, "\n{}\n", doc);
+            }
+        }
+    }
+    out
+}
+
+// a page of its own, paragraphs of the documentation separated by blank lines
+pub fn html(module: &str, items: &[Item]) -> String {
+    let mut out = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n", escape(module), escape(module));
+    for (kind, heading) in [(Kind::Function, "Functions"), (Kind::Variable, "Variables")] {
+        let items: Vec<&Item> = items.iter().filter(|item| item.kind == kind).collect();
+        if items.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "<h2>{}</h2>", heading);
+        for item in items {
+            let _ = writeln!(out, "<h3 id=\"{}\"><code>{}</code></h3>", escape(&item.name), escape(&item.name));
+            let _ = writeln!(out, "<pre><code>{}</code></pre>", escape(&item.signature));
+            for paragraph in item.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+                let _ = writeln!(out, "<p>{}</p>", escape(paragraph.trim()));
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
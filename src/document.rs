@@ -0,0 +1,269 @@
+use std::sync::Arc;
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::Parser;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::scanner;
+use crate::scanner::token::Span;
+use crate::scanner::token::Token;
+use crate::scanner::Position;
+use crate::CraftyError;
+
+// An open source file that is edited in place, for a language server to hold on to. Edits
+// re-lex only around the change and reparse only the top-level statements it touched, the
+// statements after it are kept with their positions moved.
+pub struct Document {
+    source: String,
+    // the raw stream, trivia included
+    tokens: Vec<Token>,
+    statements: Result<Vec<Statement>, CraftyError>,
+}
+
+impl Document {
+    pub fn new(source: String) -> Document {
+        let tokens = scanner::scan_tokens(&source);
+        let statements = crate::parse(&tokens);
+        Document{source, tokens, statements}
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    // the syntax tree, or the first error keeping the source from parsing
+    pub fn statements(&self) -> Result<&[Statement], &CraftyError> {
+        self.statements.as_deref()
+    }
+
+    // Replaces `range` (start inclusive, end exclusive, in the current source) with `text`.
+    // A range outside the source is ignored.
+    pub fn edit(&mut self, range: Span, text: &str) {
+        let start = scanner::byte_offset(&self.source, range.line_number, range.column_number);
+        let end = scanner::byte_offset(&self.source, range.end_line_number, range.end_column_number);
+        match (start, end) {
+            (Some(start), Some(end)) if start <= end => self.source.replace_range(start..end, text),
+            _ => return,
+        }
+
+        scanner::relex(&mut self.tokens, &self.source, range, text);
+        if scanner::check_tokens(&self.tokens).is_err() {
+            // a parse error ahead of the bad token is reported first, and only a full parse finds it
+            self.statements = crate::parse(&self.tokens);
+            return;
+        }
+
+        self.statements = match std::mem::replace(&mut self.statements, Ok(Vec::new())) {
+            Ok(statements) => reparse(statements, &self.tokens, range, text),
+            // nothing worth keeping from a file that didn't parse
+            Err(_) => crate::parse(&self.tokens),
+        };
+    }
+}
+
+fn reparse(mut statements: Vec<Statement>, tokens: &[Token], range: Span, text: &str) -> Result<Vec<Statement>, CraftyError> {
+    let edit_start = (range.line_number, range.column_number);
+    let edit_end = (range.end_line_number, range.end_column_number);
+    let new_end = scanner::end_of_insert(edit_start, text);
+
+    // the statement before the edit is parsed again too, the edit may extend it (`else`)
+    let first = statements.iter()
+        .position(|statement| end(&statement.span) >= edit_start)
+        .unwrap_or(statements.len())
+        .saturating_sub(1);
+    let from = match statements.get(first).map(|statement| start(&statement.span)) {
+        // the edit comes before any statement, start from the top
+        Some(restart) if restart >= edit_start => 0,
+        Some(restart) => match tokens.iter().position(|token| (token.line_number, token.column_number) == restart) {
+            Some(from) => from,
+            None => return crate::parse(tokens),
+        },
+        None => return crate::parse(tokens),
+    };
+
+    // old statements from `old` on come after the edit
+    let mut after = statements.split_off(first);
+    let mut old = after.iter().take_while(|statement| start(&statement.span) < edit_end).count();
+    let mut parser = Parser::new(tokens[from..].iter().cloned());
+
+    loop {
+        // text after the edit is unchanged, so once the parser is back at the start of a
+        // statement that came after it, the rest would parse exactly as before
+        if let Some(token) = parser.current_token() {
+            let position = (token.line_number, token.column_number);
+            while old < after.len() && scanner::moved(start(&after[old].span), edit_end, new_end) < position {
+                old += 1;
+            }
+            if old < after.len() && scanner::moved(start(&after[old].span), edit_end, new_end) == position {
+                for mut statement in after.drain(old..) {
+                    move_statement(&mut statement, edit_end, new_end);
+                    statements.push(statement);
+                }
+                return Ok(statements);
+            }
+        }
+
+        match parser.next_statement() {
+            Some(statement) => statements.push(statement?),
+            None => return Ok(statements),
+        }
+    }
+}
+
+fn start(span: &Span) -> Position {
+    (span.line_number, span.column_number)
+}
+
+fn end(span: &Span) -> Position {
+    (span.end_line_number, span.end_column_number)
+}
+
+fn move_span(span: &mut Span, edit_end: Position, new_end: Position) {
+    let (line_number, column_number) = scanner::moved(start(span), edit_end, new_end);
+    let (end_line_number, end_column_number) = scanner::moved(end(span), edit_end, new_end);
+    *span = Span{line_number, column_number, end_line_number, end_column_number};
+}
+
+fn move_token(token: &mut Token, edit_end: Position, new_end: Position) {
+    let (line_number, column_number) = scanner::moved((token.line_number, token.column_number), edit_end, new_end);
+    token.line_number = line_number;
+    token.column_number = column_number;
+}
+
+fn move_statement(statement: &mut Statement, edit_end: Position, new_end: Position) {
+    move_span(&mut statement.span, edit_end, new_end);
+    match &mut statement.kind {
+        StatementKind::Expression(expr) | StatementKind::Print(expr) => move_expr(expr, edit_end, new_end),
+        StatementKind::If(condition, then_branch, else_branch) => {
+            move_expr(condition, edit_end, new_end);
+            move_statement(then_branch, edit_end, new_end);
+            if let Some(else_branch) = else_branch {
+                move_statement(else_branch, edit_end, new_end);
+            }
+        },
+        StatementKind::Var(name, annotation, initializer) => {
+            move_token(name, edit_end, new_end);
+            if let Some(annotation) = annotation {
+                move_token(annotation, edit_end, new_end);
+            }
+            if let Some(initializer) = initializer {
+                move_expr(initializer, edit_end, new_end);
+            }
+        },
+        StatementKind::VarTuple(names, initializer) | StatementKind::AssignTuple(names, initializer) => {
+            for name in names.iter_mut() {
+                move_token(name, edit_end, new_end);
+            }
+            move_expr(initializer, edit_end, new_end);
+        },
+        StatementKind::While(condition, body) => {
+            move_expr(condition, edit_end, new_end);
+            move_statement(body, edit_end, new_end);
+        },
+        StatementKind::With(name, resource, body) => {
+            move_token(name, edit_end, new_end);
+            move_expr(resource, edit_end, new_end);
+            move_statement(body, edit_end, new_end);
+        },
+        StatementKind::For(initializer, condition, increment, body) => {
+            if let Some(initializer) = initializer {
+                move_statement(initializer, edit_end, new_end);
+            }
+            if let Some(condition) = condition {
+                move_expr(condition, edit_end, new_end);
+            }
+            if let Some(increment) = increment {
+                move_expr(increment, edit_end, new_end);
+            }
+            move_statement(body, edit_end, new_end);
+        },
+        StatementKind::Block(statements) => {
+            for statement in statements.iter_mut() {
+                move_statement(statement, edit_end, new_end);
+            }
+        },
+        StatementKind::Function(declaration) => {
+            // copied first if a function created from it still holds on to this one
+            let declaration = Arc::make_mut(declaration);
+            for decorator in declaration.decorators.iter_mut() {
+                move_expr(decorator, edit_end, new_end);
+            }
+            move_token(&mut declaration.name, edit_end, new_end);
+            for parameter in declaration.parameters.iter_mut() {
+                move_token(&mut parameter.name, edit_end, new_end);
+                if let Some(annotation) = &mut parameter.annotation {
+                    move_token(annotation, edit_end, new_end);
+                }
+            }
+            if let Some(return_type) = &mut declaration.return_type {
+                move_token(return_type, edit_end, new_end);
+            }
+            for statement in declaration.body.iter_mut() {
+                move_statement(statement, edit_end, new_end);
+            }
+        },
+        StatementKind::Return(keyword, value) => {
+            move_token(keyword, edit_end, new_end);
+            if let Some(value) = value {
+                move_expr(value, edit_end, new_end);
+            }
+        },
+        StatementKind::Import(keyword, name) => {
+            move_token(keyword, edit_end, new_end);
+            move_token(name, edit_end, new_end);
+        },
+    }
+}
+
+fn move_expr(expr: &mut Expr, edit_end: Position, new_end: Position) {
+    move_span(&mut expr.span, edit_end, new_end);
+    match &mut expr.kind {
+        ExprKind::Grouping(inner) => move_expr(inner, edit_end, new_end),
+        ExprKind::Binary(lhs, operator, rhs) => {
+            move_expr(lhs, edit_end, new_end);
+            move_expr(operator, edit_end, new_end);
+            move_expr(rhs, edit_end, new_end);
+        },
+        ExprKind::Unary(operator, rhs) => {
+            move_expr(operator, edit_end, new_end);
+            move_expr(rhs, edit_end, new_end);
+        },
+        ExprKind::Logical(lhs, _, rhs) => {
+            move_expr(lhs, edit_end, new_end);
+            move_expr(rhs, edit_end, new_end);
+        },
+        ExprKind::Variable(name) => move_token(name, edit_end, new_end),
+        ExprKind::Assign(name, value) => {
+            move_token(name, edit_end, new_end);
+            move_expr(value, edit_end, new_end);
+        },
+        ExprKind::Call(callee, paren, arguments) => {
+            move_expr(callee, edit_end, new_end);
+            move_token(paren, edit_end, new_end);
+            for argument in arguments.iter_mut() {
+                move_expr(argument, edit_end, new_end);
+            }
+        },
+        ExprKind::List(elements) => {
+            for element in elements.iter_mut() {
+                move_expr(element, edit_end, new_end);
+            }
+        },
+        ExprKind::Spread(list) => move_expr(list, edit_end, new_end),
+        ExprKind::Tuple(values) => {
+            for value in values.iter_mut() {
+                move_expr(value, edit_end, new_end);
+            }
+        },
+        ExprKind::Index(list, bracket, index) => {
+            move_expr(list, edit_end, new_end);
+            move_token(bracket, edit_end, new_end);
+            move_expr(index, edit_end, new_end);
+        },
+        ExprKind::Operator(..) | ExprKind::BoolLiteral(_) | ExprKind::NilLiteral | ExprKind::StringLiteral(_) | ExprKind::IntegerLiteral(_) | ExprKind::FloatLiteral(_) => {},
+    }
+}
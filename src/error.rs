@@ -0,0 +1,134 @@
+use std::error;
+use std::fmt;
+use crate::diagnostic::Code;
+use crate::diagnostic::Diagnostic;
+use crate::parser::ParseError;
+use crate::runtime::RuntimeError;
+use crate::scanner::ScanError;
+use crate::scanner::token::Span;
+
+// exit codes follow the BSD sysexits.h convention (same as jlox/clox)
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_DATA_ERROR: i32 = 65;
+pub const EXIT_NO_INPUT: i32 = 66;
+pub const EXIT_SOFTWARE: i32 = 70;
+pub const EXIT_IO_ERROR: i32 = 74;
+
+// Everything that can go wrong between source text and a value, one variant per stage.
+#[derive(Debug)]
+pub enum CraftyError {
+    Scan(ScanError),
+    Parse(ParseError),
+    Runtime(RuntimeError),
+    // the program hit one of the execution budget limits
+    ResourceExhausted(RuntimeError),
+    // the program called exit(); not a failure, but it stops everything the same way
+    Exit(RuntimeError),
+}
+
+impl CraftyError {
+    pub fn message(&self) -> &str {
+        match self {
+            CraftyError::Scan(error) => &error.message,
+            CraftyError::Parse(error) => &error.message,
+            CraftyError::Runtime(error) | CraftyError::ResourceExhausted(error) | CraftyError::Exit(error) => &error.message,
+        }
+    }
+
+    // where in the source it went wrong, when known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CraftyError::Scan(error) => Some(error.span),
+            CraftyError::Parse(error) => error.span,
+            CraftyError::Runtime(error) | CraftyError::ResourceExhausted(error) | CraftyError::Exit(error) => error.span,
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            CraftyError::Scan(error) => error.code(),
+            CraftyError::Parse(error) => error.code,
+            CraftyError::Runtime(error) | CraftyError::ResourceExhausted(error) | CraftyError::Exit(error) => error.code,
+        }
+    }
+
+    // the status the program asked to exit with
+    pub fn exit_status(&self) -> Option<i32> {
+        match self {
+            CraftyError::Exit(error) => error.exit,
+            _ => None,
+        }
+    }
+
+    // what a process running the program exits with: the status it asked for, or the code for
+    // the kind of error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CraftyError::Scan(_) | CraftyError::Parse(_) => EXIT_DATA_ERROR,
+            CraftyError::Runtime(_) | CraftyError::ResourceExhausted(_) => EXIT_SOFTWARE,
+            CraftyError::Exit(error) => error.exit.unwrap_or(0),
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(self.code(), self.message().to_string(), self.span());
+        if let CraftyError::Runtime(error) | CraftyError::ResourceExhausted(error) | CraftyError::Exit(error) = self {
+            for note in error.notes.iter() {
+                diagnostic = diagnostic.with_note(note.to_string());
+            }
+        }
+        match self {
+            CraftyError::ResourceExhausted(_) => diagnostic.with_note("limits are configured with InterpreterBuilder".to_string()),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl fmt::Display for CraftyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CraftyError::Scan(error) => write!(f, "Error scanning: {} at line {}:{}", error.message, error.span.line_number, error.span.column_number),
+            CraftyError::Parse(error) => match (error.span, &error.found) {
+                (Some(span), Some(lexeme)) => write!(f, "Error parsing: {} at '{}' line {}:{}", error.message, lexeme, span.line_number, span.column_number),
+                (Some(span), None) => write!(f, "Error parsing: {} at end of file {}:{}", error.message, span.line_number, span.column_number),
+                (None, _) => write!(f, "Error parsing: unexpected EOF: {}", error.message),
+            },
+            CraftyError::Runtime(error) => write_runtime(f, "Error evaluating", error),
+            CraftyError::ResourceExhausted(error) => write_runtime(f, "Resource exhausted", error),
+            CraftyError::Exit(error) => write_runtime(f, "Exited", error),
+        }
+    }
+}
+
+fn write_runtime(f: &mut fmt::Formatter<'_>, prefix: &str, error: &RuntimeError) -> fmt::Result {
+    match error.span {
+        Some(span) => write!(f, "{}: {} at line {}:{}", prefix, error.message, span.line_number, span.column_number),
+        None => write!(f, "{}: {}", prefix, error.message),
+    }
+}
+
+impl error::Error for CraftyError {}
+
+impl From<ScanError> for CraftyError {
+    fn from(error: ScanError) -> CraftyError {
+        CraftyError::Scan(error)
+    }
+}
+
+impl From<ParseError> for CraftyError {
+    fn from(error: ParseError) -> CraftyError {
+        CraftyError::Parse(error)
+    }
+}
+
+impl From<RuntimeError> for CraftyError {
+    fn from(error: RuntimeError) -> CraftyError {
+        if error.exit.is_some() {
+            return CraftyError::Exit(error);
+        }
+        match error.exhausted {
+            Some(_) => CraftyError::ResourceExhausted(error),
+            None => CraftyError::Runtime(error),
+        }
+    }
+}
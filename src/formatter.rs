@@ -0,0 +1,292 @@
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::parser::Visitor;
+use crate::parser::precedence;
+use crate::parser::precedence::Associativity;
+use crate::parser::precedence::Precedence;
+use crate::scanner::token::Token;
+use crate::scanner::token::TokenType;
+
+const INDENT: usize = 2;
+
+// Re-emits canonical source from the AST. Comments are not part of the AST, so they are
+// handed in separately (as trivia tokens or attached to tokens) and woven back in by position: a comment is emitted before the first
+// statement that starts after it, or at the end of the line of the statement it trails.
+pub struct Formatter {
+    indent: usize,
+    comments: Vec<Token>,
+    next_comment: usize,
+    // where every other token starts, in order, to tell which statement a comment trails
+    token_starts: Vec<(u32, u32)>,
+    last_line: Option<u32>,
+    // off, the output doesn't depend on where in the source anything was
+    blank_lines: bool,
+}
+
+impl Formatter {
+    pub fn new(tokens: &[Token]) -> Formatter {
+        Formatter{
+            indent: 0,
+            comments: tokens.iter()
+                .flat_map(Token::with_trivia)
+                .filter(|token| token.token_type == TokenType::Comment)
+                .cloned()
+                .collect(),
+            next_comment: 0,
+            token_starts: tokens.iter()
+                .filter(|token| !token.token_type.is_trivia() && token.token_type != TokenType::Eof)
+                .map(|token| (token.line_number, token.column_number))
+                .collect(),
+            last_line: None,
+            blank_lines: true,
+        }
+    }
+
+    // leaves out the blank lines the source had between statements
+    pub fn without_blank_lines(mut self) -> Formatter {
+        self.blank_lines = false;
+        self
+    }
+
+    pub fn format(&mut self, statements: &[Statement]) -> String {
+        let mut lines = self.format_statements(statements);
+        lines.extend(self.remaining_comments(u32::MAX, u32::MAX));
+        if lines.is_empty() {
+            return String::new();
+        }
+
+        let mut output = lines.join("\n");
+        output.push('\n');
+        output
+    }
+
+    fn format_statements(&mut self, statements: &[Statement]) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for statement in statements.iter() {
+            lines.extend(self.remaining_comments(statement.span.line_number, statement.span.column_number));
+            self.blank_line_before(statement.span.line_number, &mut lines);
+
+            let mut text = pad(self.indent, self.visit_statement(statement));
+            if let Some(comment) = self.trailing_comment(statement) {
+                text.push_str("  ");
+                text.push_str(&comment);
+            }
+            lines.push(text);
+            self.last_line = Some(statement.span.end_line_number);
+        }
+
+        lines
+    }
+
+    // comments positioned before line:column, each on its own line
+    fn remaining_comments(&mut self, line_number: u32, column_number: u32) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        while let Some(comment) = self.comments.get(self.next_comment).cloned() {
+            if (comment.line_number, comment.column_number) >= (line_number, column_number) {
+                break;
+            }
+            self.blank_line_before(comment.line_number, &mut lines);
+            lines.push(pad(self.indent, comment.lexeme.trim_end().to_string()));
+            self.last_line = Some(comment.line_number);
+            self.next_comment += 1;
+        }
+
+        lines
+    }
+
+    // the comment after the statement's last token, when nothing else comes between them; in
+    // `if (a) { b; } else c; // comment` it trails the if, not `b;`
+    fn trailing_comment(&mut self, statement: &Statement) -> Option<String> {
+        let end = (statement.span.end_line_number, statement.span.end_column_number);
+        let next_token = self.token_starts.get(self.token_starts.partition_point(|start| *start < end));
+        match self.comments.get(self.next_comment) {
+            Some(comment) if comment.line_number == end.0 && next_token.is_none_or(|start| *start > (comment.line_number, comment.column_number)) => {
+                self.next_comment += 1;
+                Some(comment.lexeme.trim_end().to_string())
+            },
+            _ => None,
+        }
+    }
+
+    // runs of blank lines in the source collapse into a single one
+    fn blank_line_before(&self, line_number: u32, lines: &mut Vec<String>) {
+        if let (true, Some(last_line)) = (self.blank_lines, self.last_line) {
+            if line_number > last_line + 1 && !lines.is_empty() {
+                lines.push(String::new());
+            }
+        }
+    }
+
+    // statement used as the body of if/while/for: blocks stay on the same line, anything else is indented on the next
+    fn body(&mut self, s: &Statement) -> String {
+        match &s.kind {
+            StatementKind::Block(_) => format!(" {}", self.visit_statement(s)),
+            _ => {
+                self.indent += INDENT;
+                let body = pad(self.indent, self.visit_statement(s));
+                self.indent -= INDENT;
+                format!("\n{}", body)
+            },
+        }
+    }
+
+    // e as the operand of something taking `minimum`, in parentheses when it binds more loosely;
+    // trees from the parser already have those as groupings, but built ones may not
+    fn operand(&mut self, e: &Expr, minimum: Precedence) -> String {
+        let text = self.visit_expr(e);
+        if precedence::binding(e) < minimum {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn infix(&mut self, lhs: &Expr, token_type: &TokenType, operator: &str, rhs: &Expr) -> String {
+        let (left, right) = match precedence::infix_rule(token_type) {
+            Some(rule) if rule.associativity == Associativity::Left => (rule.precedence, rule.precedence.next()),
+            Some(rule) => (rule.precedence.next(), rule.precedence),
+            None => (Precedence::Assignment, Precedence::Assignment),
+        };
+        format!("{} {} {}", self.operand(lhs, left), operator, self.operand(rhs, right))
+    }
+
+    fn block(&mut self, statements: &[Statement], end_line: u32) -> String {
+        self.indent += INDENT;
+        let mut lines = self.format_statements(statements);
+        lines.extend(self.remaining_comments(end_line, 0));
+        self.indent -= INDENT;
+        self.last_line = Some(end_line);
+
+        if lines.is_empty() {
+            return "{}".to_string();
+        }
+        format!("{{\n{}\n{}", lines.join("\n"), pad(self.indent, "}".to_string()))
+    }
+}
+
+impl Visitor<String> for Formatter {
+    fn visit_expr(&mut self, e: &Expr) -> String {
+        match &e.kind {
+            ExprKind::BoolLiteral(b) => format!("{}", b),
+            ExprKind::NilLiteral => "nil".to_string(),
+            ExprKind::Spread(ref list) => format!("...{}", self.visit_expr(list)),
+            ExprKind::Tuple(values) => values.iter().map(|value| self.visit_expr(value)).collect::<Vec<String>>().join(", "),
+            ExprKind::StringLiteral(n) => format!("\"{}\"", n),
+            ExprKind::IntegerLiteral(n) => n.to_string(),
+            ExprKind::FloatLiteral(n) => n.to_string(),
+            ExprKind::Logical(ref lhs, token_type, ref rhs) => {
+                let operator = match token_type {
+                    TokenType::And => "and",
+                    _ => "or",
+                };
+                self.infix(lhs, token_type, operator, rhs)
+            },
+            ExprKind::Operator(_token_type, n) => n.to_string(),
+            // a keyword needs a space before its operand
+            ExprKind::Unary(ref operator, ref rhs) if matches!(operator.kind, ExprKind::Operator(TokenType::Await, _)) => format!("{} {}", self.visit_expr(operator), self.operand(rhs, Precedence::Unary)),
+            ExprKind::Unary(ref operator, ref rhs) => format!("{}{}", self.visit_expr(operator), self.operand(rhs, Precedence::Unary)),
+            ExprKind::Binary(ref lhs, ref operator, ref rhs) => match &operator.kind {
+                ExprKind::Operator(token_type, lexeme) => self.infix(lhs, token_type, lexeme, rhs),
+                _ => format!("{} {} {}", self.visit_expr(lhs), self.visit_expr(operator), self.visit_expr(rhs)),
+            },
+            ExprKind::Grouping(ref expr) => format!("({})", self.visit_expr(expr)),
+            ExprKind::Variable(token) => token.lexeme.to_string(),
+            ExprKind::Assign(token, ref expr) => format!("{} = {}", token.lexeme, self.visit_expr(expr)),
+            ExprKind::Call(ref callee, _paren, arguments) => {
+                let arguments = arguments.iter()
+                    .map(|argument| self.visit_expr(argument))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("{}({})", self.operand(callee, Precedence::Call), arguments)
+            },
+            ExprKind::List(elements) => {
+                let elements = elements.iter()
+                    .map(|element| self.visit_expr(element))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("[{}]", elements)
+            },
+            ExprKind::Index(ref list, _bracket, ref index) => format!("{}[{}]", self.operand(list, Precedence::Call), self.visit_expr(index)),
+        }
+    }
+
+    fn visit_statement(&mut self, s: &Statement) -> String {
+        match &s.kind {
+            StatementKind::Expression(ref expr) => format!("{};", self.visit_expr(expr)),
+            StatementKind::If(ref expr, ref then_statement, ref else_branch) => {
+                let mut string = format!("if ({}){}", self.visit_expr(expr), self.body(then_statement));
+                if let Some(else_statement) = else_branch {
+                    match (&then_statement.kind, &else_statement.kind) {
+                        (StatementKind::Block(_), _) => string.push(' '),
+                        _ => {
+                            string.push('\n');
+                            string.push_str(&pad(self.indent, String::new()));
+                        },
+                    }
+                    match &else_statement.kind {
+                        StatementKind::If(..) => string.push_str(&format!("else {}", self.visit_statement(else_statement))),
+                        _ => string.push_str(&format!("else{}", self.body(else_statement))),
+                    }
+                }
+                string
+            },
+            StatementKind::Print(ref expr) => format!("print {};", self.visit_expr(expr)),
+            StatementKind::While(ref condition, ref body) => format!("while ({}){}", self.visit_expr(condition), self.body(body)),
+            StatementKind::With(name, resource, body) => format!("with (var {} = {}){}", name.lexeme, self.visit_expr(resource), self.body(body)),
+            StatementKind::For(initializer, condition, increment, body) => {
+                let initializer = match initializer {
+                    Some(statement) => self.visit_statement(statement),
+                    None => ";".to_string(),
+                };
+                let condition = match condition {
+                    Some(expr) => format!(" {};", self.visit_expr(expr)),
+                    None => ";".to_string(),
+                };
+                let increment = match increment {
+                    Some(expr) => format!(" {}", self.visit_expr(expr)),
+                    None => "".to_string(),
+                };
+                format!("for ({}{}{}){}", initializer, condition, increment, self.body(body))
+            },
+            StatementKind::Var(token, annotation, initializer) => {
+                let name = match annotation {
+                    Some(annotation) => format!("{}: {}", token.lexeme, annotation.lexeme),
+                    None => token.lexeme.to_string(),
+                };
+                match initializer {
+                    Some(expr) => format!("var {} = {};", name, self.visit_expr(expr)),
+                    None => format!("var {};", name),
+                }
+            },
+            StatementKind::VarTuple(names, initializer) => {
+                let names = names.iter().map(|name| name.lexeme.to_string()).collect::<Vec<String>>().join(", ");
+                format!("var ({}) = {};", names, self.visit_expr(initializer))
+            },
+            StatementKind::AssignTuple(names, value) => {
+                let names = names.iter().map(|name| name.lexeme.to_string()).collect::<Vec<String>>().join(", ");
+                format!("{} = {};", names, self.visit_expr(value))
+            },
+            StatementKind::Block(statements) => self.block(statements, s.span.end_line_number),
+            StatementKind::Function(declaration) => {
+                // each decorator on its own line above the function
+                let decorators: String = declaration.decorators.iter()
+                    .map(|decorator| format!("@{}\n{}", self.visit_expr(decorator), pad(self.indent, "".to_string())))
+                    .collect();
+                format!("{}{} {}", decorators, declaration.signature(), self.block(&declaration.body, s.span.end_line_number))
+            },
+            StatementKind::Return(_keyword, value) => match value {
+                Some(expr) => format!("return {};", self.visit_expr(expr)),
+                None => "return;".to_string(),
+            },
+            StatementKind::Import(_keyword, name) => format!("import {};", name.lexeme),
+        }
+    }
+}
+
+fn pad(amount: usize, string: String) -> String {
+    format!("{}{}", " ".repeat(amount), string)
+}
@@ -0,0 +1,94 @@
+use std::fmt::Write;
+use crate::scanner::scan_tokens;
+use crate::scanner::token_error;
+use crate::scanner::token::Token;
+use crate::scanner::token::TokenClass;
+
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // escapes for a terminal
+    Ansi,
+    // `<span class="...">`s inside a `<pre>`, for the page to style
+    Html,
+}
+
+// The source with every token colored by its class. Nothing is added or left out, so with the
+// colors taken away it is the source again, whether or not it scans cleanly: tokens the
+// scanner couldn't make sense of (unterminated strings, malformed numbers) are marked invalid.
+pub fn highlight(source: &str, format: Format) -> String {
+    let tokens = scan_tokens(source);
+    let mut out = String::new();
+    if format == Format::Html {
+        out.push_str("<pre class=\"crafty\"><code>");
+    }
+    for token in tokens.iter() {
+        let class = class(token);
+        match format {
+            Format::Ansi => match ansi_color(class) {
+                Some(color) => {
+                    let _ = write!(out, "{}{}{}", color, token.lexeme, RESET);
+                },
+                None => out.push_str(&token.lexeme),
+            },
+            Format::Html => match html_class(class) {
+                Some(name) => {
+                    let _ = write!(out, "<span class=\"{}\">{}</span>", name, escape(&token.lexeme));
+                },
+                None => out.push_str(&escape(&token.lexeme)),
+            },
+        }
+    }
+    if format == Format::Html {
+        out.push_str("</code></pre>\n");
+    }
+    out
+}
+
+fn class(token: &Token) -> TokenClass {
+    match token_error(token) {
+        Some(_) => TokenClass::Invalid,
+        None => token.token_type.class(),
+    }
+}
+
+fn ansi_color(class: TokenClass) -> Option<&'static str> {
+    match class {
+        TokenClass::Keyword => Some("\x1b[1;35m"),
+        TokenClass::String => Some("\x1b[32m"),
+        TokenClass::Number | TokenClass::Constant => Some("\x1b[36m"),
+        TokenClass::Comment => Some("\x1b[90m"),
+        TokenClass::Operator => Some("\x1b[33m"),
+        TokenClass::Invalid => Some("\x1b[1;4;31m"),
+        TokenClass::Identifier | TokenClass::Punctuation | TokenClass::Whitespace => None,
+    }
+}
+
+// identifiers and punctuation are left as plain text, there's too much of them to be worth a span
+fn html_class(class: TokenClass) -> Option<&'static str> {
+    match class {
+        TokenClass::Keyword => Some("keyword"),
+        TokenClass::String => Some("string"),
+        TokenClass::Number => Some("number"),
+        TokenClass::Constant => Some("constant"),
+        TokenClass::Comment => Some("comment"),
+        TokenClass::Operator => Some("operator"),
+        TokenClass::Invalid => Some("invalid"),
+        TokenClass::Identifier | TokenClass::Punctuation | TokenClass::Whitespace => None,
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,49 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+// Makes SIGINT (Ctrl-C) raise the returned flag instead of killing the process; hand it to
+// an interpreter with set_interrupt so a running program stops at its next statement.
+// Returns None where there is no SIGINT to catch.
+pub fn install_handler() -> Option<Arc<AtomicBool>> {
+    let flag = FLAG.get_or_init(|| Arc::new(AtomicBool::new(false))).clone();
+    if platform::install() {
+        Some(flag)
+    } else {
+        None
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+    use super::FLAG;
+
+    const SIGINT: c_int = 2;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    // only touches an atomic, which is all a signal handler may safely do
+    extern "C" fn handle_interrupt(_signal: c_int) {
+        if let Some(flag) = FLAG.get() {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn install() -> bool {
+        unsafe { signal(SIGINT, handle_interrupt) != SIG_ERR }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    pub fn install() -> bool {
+        false
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// Minimal JSON document model, just enough for machine readable output without pulling in serde
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn string(value: &str) -> Json {
+        Json::Str(value.to_string())
+    }
+
+    pub fn parse(source: &str) -> Result<Json, String> {
+        let mut chars = source.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(ch) => Err(format!("unexpected trailing character '{}'", ch)),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(name, _value)| name == key).map(|(_name, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars<'_>>) {
+    while let Some(ch) = chars.peek() {
+        if !ch.is_whitespace() {
+            break;
+        }
+        chars.next();
+    }
+}
+
+fn expect_word(chars: &mut Peekable<Chars<'_>>, word: &str, value: Json) -> Result<Json, String> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            return Err(format!("expected '{}'", word));
+        }
+    }
+    Ok(value)
+}
+
+fn parse_value(chars: &mut Peekable<Chars<'_>>) -> Result<Json, String> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        Some('n') => expect_word(chars, "null", Json::Null),
+        Some('t') => expect_word(chars, "true", Json::Bool(true)),
+        Some('f') => expect_word(chars, "false", Json::Bool(false)),
+        Some('"') => Ok(Json::Str(parse_string(chars)?)),
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => return Ok(Json::Array(values)),
+                    _ => return Err("expected ',' or ']' in array".to_string()),
+                }
+            }
+        },
+        Some('{') => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next() != Some(':') {
+                    return Err("expected ':' after object key".to_string());
+                }
+                fields.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => return Ok(Json::Object(fields)),
+                    _ => return Err("expected ',' or '}' in object".to_string()),
+                }
+            }
+        },
+        Some(ch) if *ch == '-' || ch.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(ch) = chars.peek() {
+                if !(ch.is_ascii_digit() || "+-.eE".contains(*ch)) {
+                    break;
+                }
+                number.push(*ch);
+                chars.next();
+            }
+            number.parse::<f64>().map(Json::Number).map_err(|_| format!("invalid number '{}'", number))
+        },
+        Some(ch) => Err(format!("unexpected character '{}'", ch)),
+        None => Err("unexpected end of input".to_string()),
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars<'_>>) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("expected string".to_string());
+    }
+
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some('n') => string.push('\n'),
+                Some('r') => string.push('\r'),
+                Some('t') => string.push('\t'),
+                Some('b') => string.push('\u{8}'),
+                Some('f') => string.push('\u{c}'),
+                Some('u') => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&code, 16).map_err(|_| format!("invalid unicode escape '{}'", code))?;
+                    string.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                },
+                Some(ch) => string.push(ch),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(ch) => string.push(ch),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => write!(f, "null"),
+            Json::Str(s) => write_escaped(f, s),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
@@ -0,0 +1,239 @@
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+pub mod scanner;
+use scanner::token::Token;
+use scanner::ScanError;
+use scanner::ScanErrorKind;
+
+pub mod parser;
+use parser::ParseError;
+use parser::Statement;
+
+pub mod runtime;
+use runtime::build_interpreter;
+use runtime::Arity;
+use runtime::ExprEvaluator;
+use runtime::StatementHook;
+
+pub mod printer;
+pub mod json;
+pub mod formatter;
+pub mod highlight;
+pub mod linter;
+pub mod typecheck;
+pub mod dap;
+pub mod debugger;
+pub mod diff;
+pub mod document;
+pub mod docs;
+pub mod diagnostic;
+pub mod interrupt;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "capi")]
+pub mod capi;
+
+mod error;
+pub use error::CraftyError;
+pub use error::EXIT_USAGE;
+pub use error::EXIT_DATA_ERROR;
+pub use error::EXIT_NO_INPUT;
+pub use error::EXIT_SOFTWARE;
+pub use error::EXIT_IO_ERROR;
+
+// what a program evaluates to
+pub use runtime::Object as Value;
+pub use runtime::RuntimeError;
+pub use runtime::InterpreterBuilder;
+pub use runtime::OverflowPolicy;
+pub use runtime::CoercionPolicy;
+pub use runtime::Resource;
+pub use runtime::ModuleCache;
+
+pub fn scan(source: &str) -> Result<Vec<Token>, CraftyError> {
+    let tokens = scanner::scan_tokens(source);
+    scanner::check_tokens(&tokens)?;
+    Ok(tokens)
+}
+
+// the first error parse_all would report
+pub fn parse(tokens: &[Token]) -> Result<Vec<Statement>, CraftyError> {
+    parse_all(tokens).map_err(|mut errors| errors.remove(0))
+}
+
+// Every scan and parse error, in source order. Parse errors that only happen
+// because of a bad token (at it, or anywhere after an unterminated string swallowed the
+// rest of the file) are left out.
+pub fn parse_all(tokens: &[Token]) -> Result<Vec<Statement>, Vec<CraftyError>> {
+    let scan_errors: Vec<ScanError> = tokens.iter().filter_map(scanner::token_error).collect();
+    let (statements, parse_errors) = parser::parse(tokens);
+
+    let caused_by_scan_error = |error: &ParseError| match error.span {
+        Some(span) => scan_errors.iter().any(|scan_error| match scan_error.kind {
+            ScanErrorKind::UnterminatedString => (span.line_number, span.column_number) >= (scan_error.span.line_number, scan_error.span.column_number),
+            _ => span.line_number == scan_error.span.line_number && span.column_number == scan_error.span.column_number,
+        }),
+        None => false,
+    };
+    let parse_errors: Vec<ParseError> = parse_errors.into_iter()
+        .filter(|error| !caused_by_scan_error(error))
+        .collect();
+
+    if scan_errors.is_empty() && parse_errors.is_empty() {
+        return Ok(statements);
+    }
+    let mut errors: Vec<CraftyError> = scan_errors.into_iter().map(CraftyError::from)
+        .chain(parse_errors.into_iter().map(CraftyError::from))
+        .collect();
+    // errors without a location happened at the end of the file
+    errors.sort_by_key(|error| error.span().map_or((u32::MAX, u32::MAX), |span| (span.line_number, span.column_number)));
+    Err(errors)
+}
+
+// Scans and parses in one pass, without holding on to the tokens. The first scan error stops
+// both, but a parse error earlier in the source is reported instead.
+pub fn parse_source(source: &str) -> Result<Vec<Statement>, CraftyError> {
+    let mut scan_error = None;
+    let tokens = scanner::Scanner::new(source).map_while(|token| match scanner::token_error(&token) {
+        Some(error) => {
+            scan_error = Some(error);
+            None
+        },
+        None => Some(token),
+    });
+    let (statements, mut errors) = parser::Parser::new(tokens).parse();
+
+    // the parser carries on past its errors, so whichever comes first in the source wins
+    let parse_error = if errors.is_empty() { None } else { Some(errors.remove(0)) };
+    match (scan_error, parse_error) {
+        (Some(scan_error), Some(parse_error)) => match parse_error.span {
+            Some(span) if (span.line_number, span.column_number) < (scan_error.span.line_number, scan_error.span.column_number) => Err(parse_error.into()),
+            _ => Err(scan_error.into()),
+        },
+        (Some(scan_error), None) => Err(scan_error.into()),
+        (None, Some(parse_error)) => Err(parse_error.into()),
+        (None, None) => Ok(statements),
+    }
+}
+
+// Embeddable interpreter. Globals persist between calls, so a host can feed it a program
+// piece by piece the way the REPL does.
+pub struct Interpreter {
+    evaluator: ExprEvaluator,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter{
+            evaluator: build_interpreter(),
+        }
+    }
+
+    // runs the source and returns the value of its last statement
+    pub fn interpret(&mut self, source: &str) -> Result<Value, CraftyError> {
+        let statements = parse_source(source)?;
+        self.execute(&statements)
+    }
+
+    pub fn execute(&mut self, statements: &[Statement]) -> Result<Value, CraftyError> {
+        Ok(self.evaluator.interpret(statements)?)
+    }
+
+    // the file being run, relative imports are resolved from its directory
+    pub fn set_script(&mut self, path: PathBuf) {
+        self.evaluator.set_script(path);
+    }
+
+    // arguments visible to the script through argc()/argv()
+    pub fn set_script_args(&mut self, args: Vec<String>) {
+        self.evaluator.set_script_args(args);
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.evaluator.set_output(output);
+    }
+
+    // where input() reads lines from, stdin unless replaced
+    pub fn set_input(&mut self, input: Box<dyn BufRead + Send>) {
+        self.evaluator.set_input(input);
+    }
+
+    // where report writes to, stderr unless replaced
+    pub fn set_error_output(&mut self, error_output: Box<dyn Write + Send>) {
+        self.evaluator.set_error_output(error_output);
+    }
+
+    // describes the error on the error output; if even that fails there is nowhere left to report to
+    pub fn report(&mut self, error: &CraftyError) {
+        let _ = writeln!(self.evaluator.error_output(), "{}", error);
+    }
+
+    // raising the flag stops the running program before its next statement with an "Interrupted" error
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) {
+        self.evaluator.set_interrupt(interrupt);
+    }
+
+    // every statement and expression evaluated from now on is logged here with its location and value
+    pub fn set_trace(&mut self, output: Box<dyn Write + Send>) {
+        self.evaluator.set_trace(output);
+    }
+
+    // statements plus expressions evaluated over the interpreter's whole life, for metering
+    pub fn steps_executed(&self) -> u64 {
+        self.evaluator.usage().steps()
+    }
+
+    // caps the steps each interpret/execute call may take, failing with ResourceExhausted
+    // past it; None removes the cap
+    pub fn set_step_limit(&mut self, steps: Option<u64>) {
+        self.evaluator.set_step_limit(steps);
+    }
+
+    // every variable in scope, innermost scope first (the globals, natives included, last) and
+    // each sorted by name
+    pub fn scopes(&self) -> Vec<Vec<(String, Value)>> {
+        let locals = self.evaluator.environments().iter().rev().map(|environment| environment.values.iter().collect::<Vec<_>>());
+        locals.chain(std::iter::once(self.evaluator.globals().iter().collect()))
+            .map(|scope| {
+                let mut bindings: Vec<(String, Value)> = scope.into_iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                bindings.sort_by(|a, b| a.0.cmp(&b.0));
+                bindings
+            })
+            .collect()
+    }
+
+    pub fn set_hook(&mut self, hook: Box<dyn StatementHook>) {
+        self.evaluator.set_hook(hook);
+    }
+
+    // exposes a host function to scripts as a global; it accepts any number of arguments
+    pub fn register_fn<F>(&mut self, name: &str, function: F)
+    where F: Fn(&[Value]) -> Result<Value, RuntimeError> + Send + Sync + 'static {
+        self.evaluator.define_native(name, Arity::Variadic, move |_evaluator, args| function(args));
+    }
+
+    // like register_fn, but calls with any other number of arguments fail before reaching the function
+    pub fn register_fn_with_arity<F>(&mut self, name: &str, arity: usize, function: F)
+    where F: Fn(&[Value]) -> Result<Value, RuntimeError> + Send + Sync + 'static {
+        self.evaluator.define_native(name, Arity::Fixed(arity), move |_evaluator, args| function(args));
+    }
+}
+
+// wraps an evaluator configured through InterpreterBuilder
+impl From<ExprEvaluator> for Interpreter {
+    fn from(evaluator: ExprEvaluator) -> Interpreter {
+        Interpreter{evaluator}
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+use crate::diagnostic::Code;
+use crate::diagnostic::Diagnostic;
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::FunctionDeclaration;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::parser::Visitor;
+use crate::printer::AstPrinter;
+use crate::scanner::token::Span;
+use crate::scanner::token::Token;
+use crate::scanner::token::TokenType;
+
+pub const UNUSED_VARIABLE: &str = "unused-variable";
+pub const SHADOWED_VARIABLE: &str = "shadowed-variable";
+pub const UNUSED_ASSIGNMENT: &str = "unused-assignment";
+pub const SELF_COMPARISON: &str = "self-comparison";
+pub const UNREACHABLE_CODE: &str = "unreachable-code";
+pub const ASSIGNMENT_IN_CONDITION: &str = "assignment-in-condition";
+pub const INFINITE_LOOP: &str = "infinite-loop";
+
+pub const RULES: [&str; 7] = [UNUSED_VARIABLE, SHADOWED_VARIABLE, UNUSED_ASSIGNMENT, SELF_COMPARISON, UNREACHABLE_CODE, ASSIGNMENT_IN_CONDITION, INFINITE_LOOP];
+
+const IGNORE_DIRECTIVE: &str = "crafty-ignore:";
+
+pub struct LintWarning {
+    pub rule: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+impl LintWarning {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let code = match self.rule {
+            UNUSED_VARIABLE => Code::UnusedVariable,
+            SHADOWED_VARIABLE => Code::ShadowedVariable,
+            UNUSED_ASSIGNMENT => Code::UnusedAssignment,
+            UNREACHABLE_CODE => Code::UnreachableCode,
+            ASSIGNMENT_IN_CONDITION => Code::AssignmentInCondition,
+            INFINITE_LOOP => Code::InfiniteLoop,
+            _ => Code::SelfComparison,
+        };
+        Diagnostic::warning(code, self.message.clone(), Some(self.span))
+            .with_note(format!("silence with `// crafty-ignore: {}`", self.rule))
+    }
+}
+
+// Rules silenced with `// crafty-ignore: rule, other-rule`. A comment after code covers its
+// own line, a comment on a line of its own covers the line below it.
+pub struct Suppressions {
+    lines: HashMap<u32, Vec<String>>,
+}
+
+impl Suppressions {
+    // takes tokens with their trivia, either as separate tokens or attached
+    pub fn new(tokens: &[Token]) -> Suppressions {
+        let mut lines: HashMap<u32, Vec<String>> = HashMap::new();
+        // the last line holding something other than trivia
+        let mut code_line = 0;
+
+        for token in tokens.iter().flat_map(Token::with_trivia) {
+            match token.token_type {
+                TokenType::Comment => {
+                    let rules = match token.lexeme.trim_start_matches('/').trim().strip_prefix(IGNORE_DIRECTIVE) {
+                        Some(rules) => rules,
+                        None => continue,
+                    };
+                    let line = if code_line == token.line_number { token.line_number } else { token.line_number + 1 };
+                    lines.entry(line).or_default().extend(rules.split(',').map(str::trim).filter(|rule| !rule.is_empty()).map(str::to_string));
+                },
+                TokenType::Whitespace | TokenType::Newline | TokenType::Eof => {},
+                _ => code_line = token.span().end_line_number,
+            }
+        }
+        Suppressions{lines}
+    }
+
+    pub fn is_suppressed(&self, warning: &LintWarning) -> bool {
+        match self.lines.get(&warning.span.line_number) {
+            Some(rules) => rules.iter().any(|rule| rule == warning.rule),
+            None => false,
+        }
+    }
+}
+
+// Every warning for a parsed program that a comment in its source doesn't silence.
+pub fn warnings(tokens: &[Token], statements: &[Statement]) -> Vec<LintWarning> {
+    let suppressions = Suppressions::new(tokens);
+    Linter::new().lint(statements).into_iter()
+        .filter(|warning| !suppressions.is_suppressed(warning))
+        .collect()
+}
+
+#[derive(Clone)]
+struct Variable {
+    // "variable", "parameter" or "function", for messages
+    kind: &'static str,
+    span: Span,
+    read: bool,
+    // assignments that have not been read (yet), more than one after branches merge
+    pending_assignments: Vec<Span>,
+}
+
+// Walks the AST mirroring the runtime's scoping rules, so it can tell which declaration a
+// name refers to without executing anything.
+pub struct Linter {
+    scopes: Vec<HashMap<String, Variable>>,
+    // function bodies still to check, once every global they could read has been declared
+    functions: Vec<Arc<FunctionDeclaration>>,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter {
+    pub fn new() -> Linter {
+        Linter{
+            scopes: vec![HashMap::new()],
+            functions: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn lint(mut self, statements: &[Statement]) -> Vec<LintWarning> {
+        self.visit_statements(statements);
+        // a body runs when called, by which time globals declared below the function exist too
+        let mut next = 0;
+        while next < self.functions.len() {
+            let declaration = self.functions[next].clone();
+            self.function_body(&declaration);
+            next += 1;
+        }
+        self.end_scope();
+
+        self.warnings.sort_by_key(|warning| (warning.span.line_number, warning.span.column_number));
+        self.warnings
+    }
+
+    fn warn(&mut self, rule: &'static str, message: String, span: Span) {
+        self.warnings.push(LintWarning{rule, message, span});
+    }
+
+    // a statement after one that never finishes can't run
+    fn visit_statements(&mut self, statements: &[Statement]) {
+        for (index, statement) in statements.iter().enumerate() {
+            self.visit_statement(statement);
+            if diverges(statement) {
+                if let (Some(first), Some(last)) = (statements.get(index + 1), statements.last()) {
+                    self.warn(UNREACHABLE_CODE, "unreachable code, the statement before it never finishes".to_string(), first.span.to(last.span));
+                }
+                // the rest is still checked, just not reported as unreachable again
+                for statement in statements[index + 1..].iter() {
+                    self.visit_statement(statement);
+                }
+                return;
+            }
+        }
+    }
+
+    // `if (a = b)` is far more often a typo for `==` than intended; `if ((a = b))` says it is
+    fn check_condition(&mut self, condition: &Expr) {
+        if let ExprKind::Assign(token, _) = &condition.kind {
+            self.warn(ASSIGNMENT_IN_CONDITION, format!("assignment to '{}' used as a condition, did you mean '=='?", token.lexeme), condition.span);
+        }
+    }
+
+    // `span` is where the assignment is, in case it's never read
+    fn assign(&mut self, name: &Token, span: Span) {
+        let overwritten = match self.lookup(&name.lexeme) {
+            Some(variable) => mem::replace(&mut variable.pending_assignments, vec![span]),
+            None => Vec::new(),
+        };
+        for span in overwritten {
+            self.warn(UNUSED_ASSIGNMENT, format!("value assigned to '{}' is overwritten before being read", name.lexeme), span);
+        }
+    }
+
+    // like the runtime, the body sees the globals but none of the enclosing locals
+    fn function_body(&mut self, declaration: &FunctionDeclaration) {
+        let enclosing = self.scopes.split_off(1.min(self.scopes.len()));
+        self.begin_scope();
+        for parameter in declaration.parameters.iter() {
+            self.declare("parameter", &parameter.name.lexeme, parameter.name.span());
+        }
+        self.visit_statements(&declaration.body);
+        self.end_scope();
+        // it may run at any point, reading or overwriting globals
+        self.forget_pending_assignments();
+        self.scopes.extend(enclosing);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        let mut variables: Vec<(String, Variable)> = scope.into_iter().collect();
+        variables.sort_by_key(|(_name, variable)| (variable.span.line_number, variable.span.column_number));
+        for (name, variable) in variables {
+            if !variable.read {
+                self.warn(UNUSED_VARIABLE, format!("{} '{}' is never read", variable.kind, name), variable.span);
+                continue;
+            }
+            for span in variable.pending_assignments {
+                self.warn(UNUSED_ASSIGNMENT, format!("value assigned to '{}' is never read", name), span);
+            }
+        }
+    }
+
+    fn declare(&mut self, kind: &'static str, name: &str, span: Span) {
+        let shadowed = self.scopes.iter().rev().skip(1).any(|scope| scope.contains_key(name));
+        if shadowed {
+            self.warn(SHADOWED_VARIABLE, format!("{} '{}' shadows a variable from an outer scope", kind, name), span);
+        }
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Variable{kind, span, read: false, pending_assignments: Vec::new()});
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Variable> {
+        self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name))
+    }
+
+    // a loop may read a value on its next iteration, so nothing pending can be called dead across one
+    fn forget_pending_assignments(&mut self) {
+        for scope in self.scopes.iter_mut() {
+            for variable in scope.values_mut() {
+                variable.pending_assignments.clear();
+            }
+        }
+    }
+
+    // Combine the state left behind by two alternative branches, `before` being the state they
+    // both started from. An assignment made in either branch is still pending after them, and
+    // one made before them only when neither branch reads it.
+    fn merge(&mut self, before: &[HashMap<String, Variable>], other: Vec<HashMap<String, Variable>>) {
+        for ((scope, before_scope), mut other_scope) in self.scopes.iter_mut().zip(before).zip(other) {
+            for (name, variable) in scope.iter_mut() {
+                let other_variable = match other_scope.remove(name) {
+                    Some(other_variable) => other_variable,
+                    None => continue,
+                };
+                let earlier = before_scope.get(name).map(|variable| variable.pending_assignments.as_slice()).unwrap_or(&[]);
+                variable.read = variable.read || other_variable.read;
+                variable.pending_assignments.retain(|span| other_variable.pending_assignments.contains(span) || !earlier.contains(span));
+                for span in other_variable.pending_assignments {
+                    if !variable.pending_assignments.contains(&span) && !earlier.contains(&span) {
+                        variable.pending_assignments.push(span);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Linter {
+        Linter::new()
+    }
+}
+
+impl Visitor<()> for Linter {
+    fn visit_expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::BoolLiteral(_) | ExprKind::NilLiteral | ExprKind::StringLiteral(_) | ExprKind::IntegerLiteral(_) | ExprKind::FloatLiteral(_) | ExprKind::Operator(_, _) => {},
+            ExprKind::Logical(ref lhs, _token_type, ref rhs) => {
+                self.visit_expr(lhs);
+                // the right hand side only runs sometimes
+                let before = self.scopes.clone();
+                self.visit_expr(rhs);
+                let after = mem::replace(&mut self.scopes, before.clone());
+                self.merge(&before, after);
+            },
+            ExprKind::Unary(_, ref rhs) | ExprKind::Spread(ref rhs) => self.visit_expr(rhs),
+            ExprKind::Binary(ref lhs, ref operator, ref rhs) => {
+                if is_comparison(operator) && is_place(lhs) {
+                    let mut printer = AstPrinter{indent: 0};
+                    if printer.visit_expr(lhs) == printer.visit_expr(rhs) {
+                        self.warn(SELF_COMPARISON, format!("comparing '{}' with itself", printer.visit_expr(lhs)), e.span);
+                    }
+                }
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+            },
+            ExprKind::Grouping(ref expr) => self.visit_expr(expr),
+            ExprKind::Variable(token) => {
+                if let Some(variable) = self.lookup(&token.lexeme) {
+                    variable.read = true;
+                    variable.pending_assignments.clear();
+                }
+            },
+            ExprKind::Assign(token, ref expr) => {
+                self.visit_expr(expr);
+                self.assign(token, e.span);
+            },
+            ExprKind::Call(ref callee, _paren, arguments) => {
+                self.visit_expr(callee);
+                for argument in arguments.iter() {
+                    self.visit_expr(argument);
+                }
+            },
+            ExprKind::List(elements) | ExprKind::Tuple(elements) => {
+                for element in elements.iter() {
+                    self.visit_expr(element);
+                }
+            },
+            ExprKind::Index(ref list, _bracket, ref index) => {
+                self.visit_expr(list);
+                self.visit_expr(index);
+            },
+        }
+    }
+
+    fn visit_statement(&mut self, s: &Statement) {
+        match &s.kind {
+            StatementKind::Expression(ref expr) => self.visit_expr(expr),
+            StatementKind::If(ref expr, ref then_statement, ref else_branch) => {
+                self.check_condition(expr);
+                match (constant_condition(expr), else_branch) {
+                    (Some(false), _) => self.warn(UNREACHABLE_CODE, "the condition is always false, this branch never runs".to_string(), then_statement.span),
+                    (Some(true), Some(else_statement)) => self.warn(UNREACHABLE_CODE, "the condition is always true, this branch never runs".to_string(), else_statement.span),
+                    _ => {},
+                }
+                self.visit_expr(expr);
+
+                let before = self.scopes.clone();
+                self.visit_statement(then_statement);
+                let after_then = mem::replace(&mut self.scopes, before.clone());
+                if let Some(else_statement) = else_branch {
+                    self.visit_statement(else_statement);
+                }
+                self.merge(&before, after_then);
+            },
+            StatementKind::Print(ref expr) => self.visit_expr(expr),
+            StatementKind::While(ref condition, ref body) => {
+                self.check_condition(condition);
+                match constant_condition(condition) {
+                    Some(false) => self.warn(UNREACHABLE_CODE, "the condition is always false, the loop body never runs".to_string(), body.span),
+                    Some(true) if !can_return(body) => self.warn(INFINITE_LOOP, "the condition is always true, this loop never ends".to_string(), s.span),
+                    _ => {},
+                }
+                self.forget_pending_assignments();
+                self.visit_expr(condition);
+                self.visit_statement(body);
+                self.forget_pending_assignments();
+            },
+            StatementKind::With(name, resource, body) => {
+                self.visit_expr(resource);
+                self.begin_scope();
+                self.declare("variable", &name.lexeme, name.span());
+                self.visit_statement(body);
+                self.end_scope();
+            },
+            StatementKind::For(initializer, condition, increment, body) => {
+                self.begin_scope();
+                if let Some(initializer) = initializer {
+                    self.visit_statement(initializer);
+                }
+                self.forget_pending_assignments();
+                match condition {
+                    Some(condition) => {
+                        self.check_condition(condition);
+                        match constant_condition(condition) {
+                            Some(false) => self.warn(UNREACHABLE_CODE, "the condition is always false, the loop body never runs".to_string(), body.span),
+                            Some(true) if !can_return(body) => self.warn(INFINITE_LOOP, "the condition is always true, this loop never ends".to_string(), s.span),
+                            _ => {},
+                        }
+                        self.visit_expr(condition);
+                    },
+                    None if !can_return(body) => self.warn(INFINITE_LOOP, "the loop has no condition, it never ends".to_string(), s.span),
+                    None => {},
+                }
+                self.visit_statement(body);
+                if let Some(increment) = increment {
+                    self.visit_expr(increment);
+                }
+                self.forget_pending_assignments();
+                self.end_scope();
+            },
+            StatementKind::Var(token, _annotation, initializer) => {
+                if let Some(expr) = initializer {
+                    self.visit_expr(expr);
+                }
+                self.declare("variable", &token.lexeme, token.span());
+            },
+            StatementKind::VarTuple(names, initializer) => {
+                self.visit_expr(initializer);
+                for name in names {
+                    self.declare("variable", &name.lexeme, name.span());
+                }
+            },
+            StatementKind::AssignTuple(names, value) => {
+                self.visit_expr(value);
+                for name in names {
+                    self.assign(name, name.span());
+                }
+            },
+            StatementKind::Block(statements) => {
+                self.begin_scope();
+                self.visit_statements(statements);
+                self.end_scope();
+            },
+            StatementKind::Function(declaration) => {
+                for decorator in declaration.decorators.iter() {
+                    self.visit_expr(decorator);
+                }
+                self.declare("function", &declaration.name.lexeme, declaration.name.span());
+                // it may run at any point after this, reading or overwriting globals
+                self.forget_pending_assignments();
+                self.functions.push(declaration.clone());
+            },
+            StatementKind::Return(_keyword, value) => {
+                if let Some(expr) = value {
+                    self.visit_expr(expr);
+                }
+            },
+            // the module's code may read any global
+            StatementKind::Import(..) => self.forget_pending_assignments(),
+        }
+    }
+}
+
+// Whether control can never get past the statement. Only `return` can leave a loop early, and
+// the code after a loop it returns from doesn't run either.
+fn diverges(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(..) => true,
+        StatementKind::While(condition, _) => constant_condition(condition) == Some(true),
+        StatementKind::For(_, condition, _, _) => condition.as_ref().is_none_or(|condition| constant_condition(condition) == Some(true)),
+        StatementKind::Block(statements) => statements.iter().any(diverges),
+        StatementKind::With(_, _, body) => diverges(body),
+        StatementKind::If(condition, then_branch, else_branch) => match (constant_condition(condition), else_branch) {
+            (Some(true), _) => diverges(then_branch),
+            (Some(false), Some(else_branch)) => diverges(else_branch),
+            (None, Some(else_branch)) => diverges(then_branch) && diverges(else_branch),
+            (_, None) => false,
+        },
+        StatementKind::Expression(_) | StatementKind::Print(_) | StatementKind::Var(..) | StatementKind::VarTuple(..) | StatementKind::AssignTuple(..) | StatementKind::Function(_) | StatementKind::Import(..) => false,
+    }
+}
+
+// whether a `return` somewhere in the statement can leave the function it is in
+fn can_return(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(..) => true,
+        StatementKind::If(_, then_branch, else_branch) => can_return(then_branch) || else_branch.as_deref().is_some_and(can_return),
+        StatementKind::While(_, body) | StatementKind::For(_, _, _, body) | StatementKind::With(_, _, body) => can_return(body),
+        StatementKind::Block(statements) => statements.iter().any(can_return),
+        // a nested function's returns leave that function
+        StatementKind::Expression(_) | StatementKind::Print(_) | StatementKind::Var(..) | StatementKind::VarTuple(..) | StatementKind::AssignTuple(..) | StatementKind::Function(_) | StatementKind::Import(..) => false,
+    }
+}
+
+// the value of a condition that can't change, like `false` or `!true`
+pub(crate) fn constant_condition(condition: &Expr) -> Option<bool> {
+    match &condition.kind {
+        ExprKind::BoolLiteral(value) => Some(*value),
+        ExprKind::NilLiteral => Some(false),
+        ExprKind::Grouping(ref expr) => constant_condition(expr),
+        ExprKind::Unary(ref operator, ref expr) if matches!(&operator.kind, ExprKind::Operator(TokenType::Bang, _)) => constant_condition(expr).map(|value| !value),
+        _ => None,
+    }
+}
+
+fn is_comparison(operator: &Expr) -> bool {
+    match &operator.kind {
+        ExprKind::Operator(token_type, _) => matches!(token_type,
+            TokenType::EqualEqual | TokenType::BangEqual | TokenType::Is |
+            TokenType::Greater | TokenType::GreaterEqual |
+            TokenType::Less | TokenType::LessEqual),
+        _ => false,
+    }
+}
+
+// a variable or an element of one, `xs[i]`: comparing one with itself is a slip, where the same
+// literal on both sides (`1 >= 1`, `true == true`) is more likely meant
+fn is_place(e: &Expr) -> bool {
+    match &e.kind {
+        ExprKind::Variable(_) => true,
+        ExprKind::Grouping(ref expr) => is_place(expr),
+        ExprKind::Index(ref list, _, ref index) => is_place(list) && (is_place(index) || matches!(index.kind, ExprKind::IntegerLiteral(_) | ExprKind::StringLiteral(_))),
+        _ => false,
+    }
+}
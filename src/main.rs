@@ -1,79 +1,945 @@
 use std::env;
 use std::fs;
 use std::io;
+use std::io::IsTerminal;
 use std::io::Write;
-mod scanner;
-use scanner::token::Token;
-use scanner::token::TokenType;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use crafty::scanner::scan_tokens;
+use crafty::scanner::scan_with_trivia;
+use crafty::parse_all;
+use crafty::parse_source;
+use crafty::parser::StatementKind;
+use crafty::CraftyError;
+use crafty::diagnostic::Diagnostic;
+use crafty::diagnostic::Severity;
+use crafty::Interpreter;
+use crafty::Value;
+use crafty::CoercionPolicy;
+use crafty::InterpreterBuilder;
+use crafty::ModuleCache;
+use crafty::runtime::stack_size;
+use crafty::runtime::MAX_FLOAT_PRECISION;
+use crafty::printer::AstPrinter;
+use crafty::printer::json::JsonPrinter;
+use crafty::printer::minify::minify;
+use crafty::formatter::Formatter;
+use crafty::docs;
+use crafty::diff;
+use crafty::highlight;
+use crafty::highlight::Format;
+use crafty::linter;
+use crafty::typecheck;
+use crafty::debugger::Debugger;
+use crafty::dap;
+use crafty::interrupt;
+use crafty::EXIT_USAGE;
+use crafty::EXIT_DATA_ERROR;
+use crafty::EXIT_NO_INPUT;
+use crafty::EXIT_SOFTWARE;
+use crafty::EXIT_IO_ERROR;
 
-mod parser;
-use parser::Parser;
-use parser::ParseError;
 
-mod runtime;
-use runtime::build_interpreter;
+const USAGE: &str = "Usage: crafty [--tokens] [--ast | --dump-ast=text|json] [--check] [--trace[=file]] [--error-format=human|json] [--no-color] [--deny-warnings] [--type-check] [--deterministic] [--sandbox] [--lox-compat] [--float-precision digits] [--max-recursion-depth levels] [--coercion=strict|stringify] [--module-path dir]... [--module-cache dir] [script | -e source] [args...]
+       crafty fmt [--check | --write] [--minify [--rename-locals]] [--no-color] files...
+       crafty lint [--allow rule]... [--no-color] files...
+       crafty highlight [--format=ansi|html] files...
+       crafty doc [--format=markdown|html] [--output dir] [--no-color] files...
+       crafty diff [--no-color] old new
+       crafty debug script [args...]
+       crafty dap";
 
-mod printer;
-use printer::AstPrinter;
+enum Input {
+    Prompt,
+    Eval(String),
+    File(String),
+}
+
+enum Trace {
+    Stderr,
+    File(String),
+}
+
+enum AstFormat {
+    Text,
+    Json,
+}
+
+#[derive(Default)]
+struct Options {
+    dump_tokens: bool,
+    dump_ast: Option<AstFormat>,
+    check_only: bool,
+    trace: Option<Trace>,
+    // one JSON diagnostic per line on stderr instead of messages meant for people
+    json_errors: bool,
+    no_color: bool,
+    deny_warnings: bool,
+    type_check: bool,
+    // clock(), now() and sleep() on a virtual clock
+    deterministic: bool,
+    // no access to the host environment
+    sandbox: bool,
+    // Lox's semantics instead of crafty's
+    lox_compat: bool,
+    // "x" + 1 is "x1" rather than an error
+    stringify_numbers: bool,
+    // decimals every float is written with, rather than the shortest exact text
+    float_precision: Option<usize>,
+    // nested statements and expressions before a "Stack overflow" error, the builder's default when None
+    max_recursion_depth: Option<usize>,
+    // searched for imports before CRAFTY_PATH
+    module_path: Vec<PathBuf>,
+    // where parsed modules are kept between runs, needs the serde feature
+    module_cache: Option<PathBuf>,
+    debug: bool,
+    script_args: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Option<(Input, Options)> {
+    let mut options = Options::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let input = match arg.as_str() {
+            "--tokens" => {
+                options.dump_tokens = true;
+                continue;
+            },
+            "--ast" | "--dump-ast=text" => {
+                options.dump_ast = Some(AstFormat::Text);
+                continue;
+            },
+            "--dump-ast=json" => {
+                options.dump_ast = Some(AstFormat::Json);
+                continue;
+            },
+            "--check" => {
+                options.check_only = true;
+                continue;
+            },
+            "--trace" => {
+                options.trace = Some(Trace::Stderr);
+                continue;
+            },
+            flag if flag.starts_with("--trace=") => {
+                options.trace = Some(Trace::File(flag["--trace=".len()..].to_string()));
+                continue;
+            },
+            "--error-format=human" => {
+                options.json_errors = false;
+                continue;
+            },
+            "--error-format=json" => {
+                options.json_errors = true;
+                continue;
+            },
+            "--no-color" => {
+                options.no_color = true;
+                continue;
+            },
+            "--deny-warnings" => {
+                options.deny_warnings = true;
+                continue;
+            },
+            "--type-check" => {
+                options.type_check = true;
+                continue;
+            },
+            "--deterministic" => {
+                options.deterministic = true;
+                continue;
+            },
+            "--sandbox" => {
+                options.sandbox = true;
+                continue;
+            },
+            "--lox-compat" => {
+                options.lox_compat = true;
+                continue;
+            },
+            "--float-precision" => {
+                options.float_precision = Some(iter.next()?.parse().ok().filter(|digits| *digits <= MAX_FLOAT_PRECISION)?);
+                continue;
+            },
+            flag if flag.starts_with("--float-precision=") => {
+                options.float_precision = Some(flag["--float-precision=".len()..].parse().ok().filter(|digits| *digits <= MAX_FLOAT_PRECISION)?);
+                continue;
+            },
+            "--max-recursion-depth" => {
+                options.max_recursion_depth = Some(iter.next()?.parse().ok()?);
+                continue;
+            },
+            flag if flag.starts_with("--max-recursion-depth=") => {
+                options.max_recursion_depth = Some(flag["--max-recursion-depth=".len()..].parse().ok()?);
+                continue;
+            },
+            "--coercion=strict" => {
+                options.stringify_numbers = false;
+                continue;
+            },
+            "--coercion=stringify" => {
+                options.stringify_numbers = true;
+                continue;
+            },
+            "--module-path" => {
+                options.module_path.push(PathBuf::from(iter.next()?));
+                continue;
+            },
+            flag if flag.starts_with("--module-path=") => {
+                options.module_path.push(PathBuf::from(&flag["--module-path=".len()..]));
+                continue;
+            },
+            "--module-cache" => {
+                options.module_cache = Some(PathBuf::from(iter.next()?));
+                continue;
+            },
+            "-e" | "--eval" => {
+                let source = iter.next()?;
+                options.script_args.push(arg.to_string());
+                Input::Eval(source.to_string())
+            },
+            flag if flag.starts_with('-') => return None,
+            filename => {
+                options.script_args.push(filename.to_string());
+                Input::File(filename.to_string())
+            },
+        };
+
+        // everything after the script belongs to the script
+        options.script_args.extend(iter.cloned());
+        return Some((input, options));
+    }
+
+    Some((Input::Prompt, options))
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 2 {
-        println!("Usage: crafty [script]");
-    } else if args.len() == 2 {
-        let filename = &args[1];
-        run_file(filename);
-    } else {
-        run_prompt();
+    let args: Vec<String> = env::args().skip(1).collect();
+    // scripts nest on the Rust stack, so they run on a thread with room for the recursion limit
+    let depth = match parse_args(&args) {
+        Some((_, options)) => options.max_recursion_depth,
+        None => None,
+    };
+    let cli = thread::Builder::new()
+        .name("main".to_string())
+        .stack_size(stack_size(depth))
+        .spawn(move || cli(args));
+    match cli {
+        Ok(cli) => if let Err(panic) = cli.join() {
+            std::panic::resume_unwind(panic);
+        },
+        Err(error) => {
+            eprintln!("Could not start the interpreter: {}", error);
+            process::exit(EXIT_SOFTWARE);
+        },
     }
 }
 
-fn run_file(filename: &String) {
-    let contents = fs::read_to_string(filename)
-        .expect("Something went wrong reading the file");
+fn cli(args: Vec<String>) {
+    match args.first().map(String::as_str) {
+        Some("fmt") => process::exit(fmt_command(&args[1..])),
+        Some("lint") => process::exit(lint_command(&args[1..])),
+        Some("highlight") => process::exit(highlight_command(&args[1..])),
+        Some("doc") => process::exit(doc_command(&args[1..])),
+        Some("diff") => process::exit(diff_command(&args[1..])),
+        Some("debug") => process::exit(debug_command(&args[1..])),
+        Some("dap") if args.len() == 1 => process::exit(dap::serve()),
+        _ => {},
+    }
 
-    run(&contents);
+    let exit_code = match parse_args(&args) {
+        Some((Input::Prompt, options)) => run_prompt(&options),
+        Some((Input::Eval(source), options)) => run_source(&source, "<eval>", None, &options),
+        Some((Input::File(filename), options)) => run_file(&filename, &options),
+        None => {
+            eprintln!("{}", USAGE);
+            EXIT_USAGE
+        }
+    };
+    process::exit(exit_code);
 }
 
-fn run_prompt() {
+fn run_file(filename: &str, options: &Options) -> i32 {
+    match fs::read_to_string(filename) {
+        Ok(contents) => run_source(&contents, filename, Some(filename), options),
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", filename, error);
+            EXIT_NO_INPUT
+        }
+    }
+}
+
+// --module-path directories first, then CRAFTY_PATH's, each in the order given
+fn new_interpreter(options: &Options) -> Interpreter {
+    let mut module_path = options.module_path.clone();
+    if let Some(crafty_path) = env::var_os("CRAFTY_PATH") {
+        module_path.extend(env::split_paths(&crafty_path).filter(|directory| !directory.as_os_str().is_empty()));
+    }
+    let mut builder = InterpreterBuilder::new()
+        .deterministic(options.deterministic)
+        .sandboxed(options.sandbox)
+        .lox_compat(options.lox_compat)
+        .coercion(if options.stringify_numbers { CoercionPolicy::Stringify } else { CoercionPolicy::Strict });
+    if let Some(digits) = options.float_precision {
+        builder = builder.float_precision(digits);
+    }
+    if let Some(depth) = options.max_recursion_depth {
+        builder = builder.max_recursion_depth(depth);
+    }
+    builder
+        .module_path(module_path)
+        .module_cache(module_cache(options))
+        .build()
+        .into()
+}
+
+#[cfg(feature = "serde")]
+fn module_cache(options: &Options) -> ModuleCache {
+    match &options.module_cache {
+        Some(directory) => ModuleCache::new().on_disk(directory.clone()),
+        None => ModuleCache::new(),
+    }
+}
+
+// without serde there is no way to write the trees out, so they are only cached in memory
+#[cfg(not(feature = "serde"))]
+fn module_cache(options: &Options) -> ModuleCache {
+    if options.module_cache.is_some() {
+        eprintln!("--module-cache needs crafty built with the serde feature, modules are cached in memory only");
+    }
+    ModuleCache::new()
+}
+
+// `path` names the source in error messages, `script` is the file it came from if any
+fn run_source(source: &str, path: &str, script: Option<&str>, options: &Options) -> i32 {
+    let mut interpreter = new_interpreter(options);
+    if let Some(script) = script {
+        interpreter.set_script(PathBuf::from(script));
+    }
+    if let Err(code) = set_trace(&mut interpreter, options) {
+        return code;
+    }
+    match run(&mut interpreter, source, path, options, true) {
+        Ok(None) => 0,
+        Ok(Some(status)) => status,
+        Err(code) => code,
+    }
+}
+
+// the exit code, 0 unless a line calls exit()
+fn run_prompt(options: &Options) -> i32 {
+    // Ctrl-C cancels the line being run instead of leaving the REPL
+    let interrupt = interrupt::install_handler();
+    // one interpreter for the whole session, so variables survive from line to line
+    let mut interpreter = match prompt_interpreter(options, &interrupt) {
+        Ok(interpreter) => interpreter,
+        Err(code) => return code,
+    };
+
+    let mut session = Session::default();
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
 
         let mut line = String::new();
 
-        io::stdin().read_line(&mut line)
-            .ok()
-            .expect("Failed to read line");
+        let bytes_read = io::stdin().read_line(&mut line).expect("Failed to read line");
+        if bytes_read == 0 {
+            println!();
+            return 0;
+        }
+
+        // a Ctrl-C pressed while waiting at the prompt shouldn't cancel the next line
+        if let Some(interrupt) = &interrupt {
+            interrupt.store(false, Ordering::SeqCst);
+        }
+        if let Some(command) = line.trim().strip_prefix(':') {
+            if let Some(status) = repl_command(&mut interpreter, &mut session, command, options, &interrupt) {
+                return status;
+            }
+            continue;
+        }
+        // every line would warn about the variables it declares for the lines after it
+        match run(&mut interpreter, &line, "<stdin>", options, false) {
+            Ok(Some(status)) => return status,
+            Ok(None) => session.record(&line),
+            Err(_) => {},
+        }
+    }
+}
 
-        run(&line);
+fn prompt_interpreter(options: &Options, interrupt: &Option<Arc<AtomicBool>>) -> Result<Interpreter, i32> {
+    let mut interpreter = new_interpreter(options);
+    set_trace(&mut interpreter, options)?;
+    if let Some(interrupt) = interrupt {
+        interpreter.set_interrupt(interrupt.clone());
     }
+    Ok(interpreter)
 }
 
+// what the REPL's commands remember between lines
+#[derive(Default)]
+struct Session {
+    // the file :reload runs again, kept by :reset
+    loaded: Option<String>,
+    // what :save writes: the declarations of every line and file that ran, formatted
+    declarations: Vec<String>,
+}
+
+impl Session {
+    // the line already ran, so it parses
+    fn record(&mut self, line: &str) {
+        let statements = parse_source(line).unwrap_or_default();
+        for statement in statements.iter() {
+            if matches!(statement.kind, StatementKind::Var(..) | StatementKind::VarTuple(..) | StatementKind::Function(_) | StatementKind::Import(..)) {
+                let text = Formatter::new(&[]).format(std::slice::from_ref(statement));
+                self.declarations.push(text.trim_end().to_string());
+            }
+        }
+    }
+
+    fn save(&self, filename: &str) {
+        let mut contents = self.declarations.join("\n");
+        contents.push('\n');
+        match fs::write(filename, contents) {
+            Ok(()) => println!("Saved {} declarations to {}", self.declarations.len(), filename),
+            Err(error) => eprintln!("Could not write '{}': {}", filename, error),
+        }
+    }
+}
 
-fn run(source: &String) {
-    let tokens: Vec<Token> =
-        scanner::scan_tokens(source)
-        .into_iter()
-        .filter(|tok| tok.token_type != TokenType::Whitespace)
-        .filter(|tok| tok.token_type != TokenType::Newline)
-        .inspect(|tok| println!("{:?}", tok))
-        .collect();
+const REPL_COMMANDS: &str = ":load file, :reload, :env [name], :save file, :reset, :paste";
 
-    let mut parser = Parser{
-        iter: tokens.iter().peekable(),
-        current: None,
-        previous: None,
+// Runs a line starting with ':', without the colon. Some(status) when the REPL has to stop: the
+// program called exit(), or a new interpreter couldn't be set up.
+fn repl_command(interpreter: &mut Interpreter, session: &mut Session, command: &str, options: &Options, interrupt: &Option<Arc<AtomicBool>>) -> Option<i32> {
+    let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    let argument = argument.trim();
+    let filename = match name {
+        "reset" => {
+            *interpreter = match prompt_interpreter(options, interrupt) {
+                Ok(interpreter) => interpreter,
+                Err(code) => return Some(code),
+            };
+            session.declarations.clear();
+            println!("Session reset");
+            return None;
+        },
+        "paste" => return paste(interpreter, session, options),
+        "env" => {
+            print_env(interpreter, if argument.is_empty() { None } else { Some(argument) });
+            return None;
+        },
+        "save" if argument.is_empty() => {
+            eprintln!("Usage: :save file");
+            return None;
+        },
+        "save" => {
+            session.save(argument);
+            return None;
+        },
+        "load" if argument.is_empty() => {
+            eprintln!("Usage: :load file");
+            return None;
+        },
+        "load" => argument.to_string(),
+        "reload" => match &session.loaded {
+            Some(filename) => filename.clone(),
+            None => {
+                eprintln!("Nothing to reload, :load a file first");
+                return None;
+            },
+        },
+        _ => {
+            eprintln!("Unknown command ':{}', the commands are {}", name, REPL_COMMANDS);
+            return None;
+        },
+    };
+    load(interpreter, session, &filename, options)
+}
+
+// Reads lines up to `:end` (or the end of the input) and runs them together, so a pasted snippet
+// whose statements span several lines isn't run, and rejected, a line at a time.
+fn paste(interpreter: &mut Interpreter, session: &mut Session, options: &Options) -> Option<i32> {
+    println!("Paste mode, :end on a line of its own runs what was pasted");
+    let mut snippet = String::new();
+    loop {
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if line.trim() == ":end" => break,
+            Ok(_) => snippet.push_str(&line),
+        }
+    }
+    match run(interpreter, &snippet, "<paste>", options, false) {
+        Ok(Some(status)) => Some(status),
+        Ok(None) => {
+            session.record(&snippet);
+            None
+        },
+        Err(_) => None,
+    }
+}
+
+// A table of the variables in scope, innermost first, or of the ones called `name` (natives are
+// only listed by name, there are too many of them).
+fn print_env(interpreter: &Interpreter, name: Option<&str>) {
+    let scopes = interpreter.scopes();
+    let mut rows = vec![["scope".to_string(), "name".to_string(), "type".to_string(), "value".to_string()]];
+    for (depth, bindings) in scopes.iter().enumerate() {
+        let scope = if depth + 1 == scopes.len() { "global".to_string() } else { format!("local {}", scopes.len() - 1 - depth) };
+        for (binding, value) in bindings.iter() {
+            let listed = match name {
+                Some(name) => binding == name,
+                None => !matches!(value, Value::NativeFunction(_)),
+            };
+            if listed {
+                rows.push([scope.clone(), binding.clone(), value.type_name().to_string(), describe_value(value)]);
+            }
+        }
+    }
+    if rows.len() == 1 {
+        match name {
+            Some(name) => println!("No variable named '{}'", name),
+            None => println!("No variables defined"),
+        }
+        return;
+    }
+
+    let widths: Vec<usize> = (0..3).map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+    for row in rows.iter() {
+        println!("{:<scope$}  {:<name$}  {:<kind$}  {}", row[0], row[1], row[2], row[3], scope = widths[0], name = widths[1], kind = widths[2]);
+    }
+}
+
+// strings quoted so "1" and 1 tell apart, anything long cut short to keep the table readable
+fn describe_value(value: &Value) -> String {
+    const LIMIT: usize = 60;
+    let text = format!("{:?}", value);
+    if text.chars().count() <= LIMIT {
+        return text;
+    }
+    let mut short: String = text.chars().take(LIMIT - 3).collect();
+    short.push_str("...");
+    short
+}
+
+// Runs the file in the session's interpreter, so what it defines joins the globals already
+// there, replacing the ones with the same names.
+fn load(interpreter: &mut Interpreter, session: &mut Session, filename: &str, options: &Options) -> Option<i32> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", filename, error);
+            return None;
+        },
     };
-    match parser.parse() {
-        Ok(statements) => {
-            println!("AST:");
-            AstPrinter{indent: 0}.print(&statements);
-            println!("\nEval:");
-            build_interpreter().interpret(&statements);
+    // remembered even when it fails to run, fixing it and reloading is the point
+    session.loaded = Some(filename.to_string());
+    interpreter.set_script(PathBuf::from(filename));
+    match run(interpreter, &contents, filename, options, false) {
+        Ok(Some(status)) => Some(status),
+        Ok(None) => {
+            println!("Loaded {}", filename);
+            session.record(&contents);
+            None
         },
-        Err(ParseError{message}) => {
-            println!("Error parsing: {}", message);
+        Err(_) => None,
+    }
+}
+
+fn report(diagnostics: &[Diagnostic], source: &str, path: &str, options: &Options) {
+    let color = use_color(options.no_color);
+    for (index, diagnostic) in diagnostics.iter().enumerate() {
+        if options.json_errors {
+            eprintln!("{}", diagnostic.to_json());
+        } else {
+            if index > 0 {
+                eprintln!();
+            }
+            eprint!("{}", diagnostic.render(source, path, color));
+        }
+    }
+}
+
+// reports the errors, returning the exit code they call for
+fn fail(errors: &[CraftyError], source: &str, path: &str, options: &Options) -> i32 {
+    let diagnostics: Vec<Diagnostic> = errors.iter().map(CraftyError::diagnostic).collect();
+    report(&diagnostics, source, path, options);
+    errors[0].exit_code()
+}
+
+// colors only make sense on a terminal, and https://no-color.org asks for a way to turn them off
+fn use_color(no_color: bool) -> bool {
+    !no_color && env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal()
+}
+
+// the trace is set up once per interpreter so a REPL session appends to the same file
+fn set_trace(interpreter: &mut Interpreter, options: &Options) -> Result<(), i32> {
+    match &options.trace {
+        Some(Trace::Stderr) => interpreter.set_trace(Box::new(io::stderr())),
+        Some(Trace::File(filename)) => match fs::File::create(filename) {
+            Ok(file) => interpreter.set_trace(Box::new(io::BufWriter::new(file))),
+            Err(error) => {
+                eprintln!("Could not create trace file '{}': {}", filename, error);
+                return Err(EXIT_IO_ERROR);
+            },
+        },
+        None => {},
+    }
+    Ok(())
+}
+
+// Runs a script or REPL line and reports whatever goes wrong, failing with the exit code. Every
+// scan and parse error is collected before giving up, so one run reports them all. Lint
+// warnings are reported before running, and stop it with --deny-warnings. Ok(Some(status)) when
+// the program called exit().
+fn run(interpreter: &mut Interpreter, source: &str, path: &str, options: &Options, lint: bool) -> Result<Option<i32>, i32> {
+    let tokens = scan_with_trivia(source);
+
+    if options.dump_tokens {
+        for token in tokens.iter() {
+            println!("{}:{}\t{:?}\t{:?}", token.line_number, token.column_number, token.token_type, token.lexeme);
+        }
+    }
+
+    let statements = parse_all(&tokens).map_err(|errors| fail(&errors, source, path, options))?;
+    match options.dump_ast {
+        Some(AstFormat::Text) => AstPrinter{indent: 0}.print(&statements),
+        Some(AstFormat::Json) => JsonPrinter{}.print(&statements),
+        None => {},
+    }
+
+    // --check only reports what is sure to fail, --type-check holds the program to its annotations too
+    if options.type_check || options.check_only {
+        let errors = if options.type_check { typecheck::check(&statements) } else { typecheck::infer(&statements) };
+        if !errors.is_empty() {
+            report(&errors, source, path, options);
+            return Err(EXIT_DATA_ERROR);
+        }
+    }
+    if lint {
+        let warnings: Vec<Diagnostic> = linter::warnings(&tokens, &statements).iter()
+            .map(|warning| if options.deny_warnings {
+                Diagnostic{severity: Severity::Error, ..warning.diagnostic()}.with_note("warnings are denied by --deny-warnings".to_string())
+            } else {
+                warning.diagnostic()
+            })
+            .collect();
+        report(&warnings, source, path, options);
+        if options.deny_warnings && !warnings.is_empty() {
+            return Err(EXIT_DATA_ERROR);
+        }
+    }
+    if options.check_only {
+        return Ok(None);
+    }
+
+    interpreter.set_script_args(options.script_args.clone());
+    if options.debug {
+        interpreter.set_hook(Box::new(Debugger::new(source)));
+    }
+    match interpreter.execute(&statements) {
+        Ok(_) => Ok(None),
+        Err(error) => match error.exit_status() {
+            Some(status) => Ok(Some(status)),
+            None => Err(fail(&[error], source, path, options)),
+        },
+    }
+}
+
+fn debug_command(args: &[String]) -> i32 {
+    match args.first() {
+        Some(filename) if !filename.starts_with('-') => {
+            let options = Options{
+                debug: true,
+                script_args: args.to_vec(),
+                ..Options::default()
+            };
+            run_file(filename, &options)
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            EXIT_USAGE
+        },
+    }
+}
+
+fn fmt_command(args: &[String]) -> i32 {
+    let mut check = false;
+    let mut write = false;
+    let mut minified = false;
+    let mut rename_locals = false;
+    let mut options = Options::default();
+    let mut filenames = Vec::new();
+
+    for arg in args.iter() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-w" | "--write" => write = true,
+            "--minify" => minified = true,
+            "--rename-locals" => rename_locals = true,
+            "--no-color" => options.no_color = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                return EXIT_USAGE;
+            },
+            filename => filenames.push(filename),
+        }
+    }
+    if filenames.is_empty() || (check && write) || (check && minified) || (rename_locals && !minified) {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    }
+
+    let mut exit_code = 0;
+    for filename in filenames {
+        let source = match fs::read_to_string(filename) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("Could not read '{}': {}", filename, error);
+                exit_code = EXIT_NO_INPUT;
+                continue;
+            }
+        };
+
+        let tokens = scan_with_trivia(&source);
+        let formatted = match parse_all(&tokens) {
+            Ok(statements) if minified => minify(&tokens, &statements, rename_locals),
+            Ok(statements) => Formatter::new(&tokens).format(&statements),
+            Err(errors) => {
+                exit_code = fail(&errors, &source, filename, &options);
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != source {
+                println!("{} is not formatted", filename);
+                exit_code = exit_code.max(1);
+            }
+        } else if write {
+            if formatted != source {
+                if let Err(error) = fs::write(filename, formatted) {
+                    eprintln!("Could not write '{}': {}", filename, error);
+                    exit_code = EXIT_IO_ERROR;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    exit_code
+}
+
+fn highlight_command(args: &[String]) -> i32 {
+    let mut format = Format::Ansi;
+    let mut filenames = Vec::new();
+
+    for arg in args.iter() {
+        match arg.as_str() {
+            "--format=ansi" => format = Format::Ansi,
+            "--format=html" => format = Format::Html,
+            flag if flag.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                return EXIT_USAGE;
+            },
+            filename => filenames.push(filename),
+        }
+    }
+    if filenames.is_empty() {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    }
+
+    let mut exit_code = 0;
+    for filename in filenames {
+        match fs::read_to_string(filename) {
+            Ok(source) => print!("{}", highlight::highlight(&source, format)),
+            Err(error) => {
+                eprintln!("Could not read '{}': {}", filename, error);
+                exit_code = EXIT_NO_INPUT;
+            }
+        }
+    }
+    exit_code
+}
+
+// Documents each file as a module, to stdout or to `<output>/<module>.md` (or `.html`).
+fn doc_command(args: &[String]) -> i32 {
+    let mut html = false;
+    let mut output = None;
+    let mut options = Options::default();
+    let mut filenames = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format=markdown" => html = false,
+            "--format=html" => html = true,
+            "--output" => match iter.next() {
+                Some(directory) => output = Some(PathBuf::from(directory)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return EXIT_USAGE;
+                },
+            },
+            "--no-color" => options.no_color = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                return EXIT_USAGE;
+            },
+            filename => filenames.push(filename),
+        }
+    }
+    if filenames.is_empty() {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    }
+
+    let mut exit_code = 0;
+    for filename in filenames {
+        let source = match fs::read_to_string(filename) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("Could not read '{}': {}", filename, error);
+                exit_code = EXIT_NO_INPUT;
+                continue;
+            }
+        };
+
+        let tokens = scan_with_trivia(&source);
+        let statements = match parse_all(&tokens) {
+            Ok(statements) => statements,
+            Err(errors) => {
+                exit_code = fail(&errors, &source, filename, &options);
+                continue;
+            }
+        };
+
+        let module = PathBuf::from(filename).file_stem().map_or(filename.to_string(), |stem| stem.to_string_lossy().to_string());
+        let items = docs::items(&tokens, &statements);
+        let page = if html { docs::html(&module, &items) } else { docs::markdown(&module, &items) };
+        match &output {
+            Some(directory) => {
+                let path = directory.join(format!("{}.{}", module, if html { "html" } else { "md" }));
+                if let Err(error) = fs::create_dir_all(directory).and_then(|_| fs::write(&path, page)) {
+                    eprintln!("Could not write '{}': {}", path.display(), error);
+                    exit_code = EXIT_IO_ERROR;
+                }
+            },
+            None => print!("{}", page),
+        }
+    }
+    exit_code
+}
+
+// Exits with 1 when the files differ, like diff(1).
+fn diff_command(args: &[String]) -> i32 {
+    let mut options = Options::default();
+    let mut filenames = Vec::new();
+
+    for arg in args.iter() {
+        match arg.as_str() {
+            "--no-color" => options.no_color = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                return EXIT_USAGE;
+            },
+            filename => filenames.push(filename),
+        }
+    }
+    if filenames.len() != 2 {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    }
+
+    let mut versions = Vec::new();
+    for filename in filenames {
+        let source = match fs::read_to_string(filename) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("Could not read '{}': {}", filename, error);
+                return EXIT_NO_INPUT;
+            }
+        };
+        match parse_all(&scan_tokens(&source)) {
+            Ok(statements) => versions.push(statements),
+            Err(errors) => return fail(&errors, &source, filename, &options),
+        }
+    }
+
+    let changes = diff::diff(&versions[0], &versions[1]);
+    for change in changes.iter() {
+        print!("{}", change);
+    }
+    if changes.is_empty() { 0 } else { 1 }
+}
+
+fn lint_command(args: &[String]) -> i32 {
+    let mut allowed = Vec::new();
+    let mut options = Options::default();
+    let mut filenames = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--allow" => match iter.next() {
+                Some(rule) if linter::RULES.contains(&rule.as_str()) => allowed.push(rule.to_string()),
+                Some(rule) => {
+                    eprintln!("Unknown lint rule '{}', expected one of: {}", rule, linter::RULES.join(", "));
+                    return EXIT_USAGE;
+                },
+                None => {
+                    eprintln!("{}", USAGE);
+                    return EXIT_USAGE;
+                },
+            },
+            "--no-color" => options.no_color = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                return EXIT_USAGE;
+            },
+            filename => filenames.push(filename),
+        }
+    }
+    if filenames.is_empty() {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    }
+
+    let mut exit_code = 0;
+    for filename in filenames {
+        let source = match fs::read_to_string(filename) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("Could not read '{}': {}", filename, error);
+                exit_code = EXIT_NO_INPUT;
+                continue;
+            }
+        };
+
+        let tokens = scan_tokens(&source);
+        let statements = match parse_all(&tokens) {
+            Ok(statements) => statements,
+            Err(errors) => {
+                exit_code = fail(&errors, &source, filename, &options);
+                continue;
+            }
+        };
+
+        for warning in linter::warnings(&tokens, &statements) {
+            if allowed.iter().any(|rule| rule == warning.rule) {
+                continue;
+            }
+            println!("{}:{}:{}: warning[{}]: {}", filename, warning.span.line_number, warning.span.column_number + 1, warning.rule, warning.message);
+            exit_code = exit_code.max(1);
         }
     }
+    exit_code
 }
@@ -1,40 +1,242 @@
+pub(crate) mod precedence;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use crate::diagnostic::Code;
+use crate::scanner::token::Span;
 use crate::scanner::token::Token;
 use crate::scanner::token::TokenType;
-
-use std::iter::Peekable;
-use core::slice::Iter;
-
+use precedence::Associativity;
+use precedence::Infix;
+use precedence::Precedence;
+
+const MAX_ARGUMENTS: usize = 255;
+// statements, blocks and expressions inside one another; the parser recurses for each, so
+// without a limit deep enough input overflows the stack
+const MAX_NESTING: usize = 256;
+// operators in a row like `1 + 2 + 3`, which parse without recursing but make a tree as deep as
+// they are long for everything walking it afterwards
+const MAX_OPERATORS: usize = 16384;
+
+#[derive(Debug, Clone)]
 pub struct ParseError {
+    pub code: Code,
+    // without the location, that is in span and found
     pub message: String,
+    // the token the parser choked on
+    pub span: Option<Span>,
+    // its lexeme, None at the end of the file
+    pub found: Option<String>,
+    // the tokens that would have been accepted there, empty when it isn't down to one token
+    pub expected: Vec<TokenType>,
+}
+
+// Pulls tokens one at a time, so it can parse straight from a Scanner without the whole
+// token stream being collected first. Trivia is skipped here.
+pub struct Parser<I: Iterator<Item = Token>> {
+    iter: I,
+    current: Option<Token>,
+    previous: Option<Token>,
+    // every error so far, the parser recovers from each and carries on
+    errors: Vec<ParseError>,
+    // blocks currently open, so recovery doesn't skip past the end of one
+    depth: usize,
+    // function bodies currently open, `return` is only allowed inside one
+    functions: usize,
+    // statements and expressions currently being parsed inside one another
+    nesting: usize,
+    // operators in a row the expression being parsed is under, counting those of the
+    // expressions it is inside
+    operators: usize,
+    // set once MAX_NESTING or MAX_OPERATORS is reached: the rest of the file is skipped, and the errors of the
+    // levels it was nested in aren't worth reporting
+    abandoned: bool,
+    // the string literals seen so far, so a repeated one isn't allocated again
+    constants: HashSet<Arc<str>>,
 }
 
-pub struct Parser<'a> {
-    pub iter: Peekable<Iter<'a, Token>>,
-    pub current: Option<&'a Token>,
-    pub previous: Option<&'a Token>,
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
 }
 
-pub enum Statement {
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatementKind {
     Expression(Box<Expr>),
     If(Box<Expr>, Box<Statement>, Option<Box<Statement>>),
     Print(Box<Expr>),
-    Var(Token, Option<Box<Expr>>),
+    // name, type annotation (`var x: Integer`), initializer
+    Var(Token, Option<Token>, Option<Box<Expr>>),
+    // `var (a, b) = value;`, unpacking a list into the names
+    VarTuple(Vec<Token>, Box<Expr>),
+    // `a, b = b, a;`, every value worked out before any name is assigned
+    AssignTuple(Vec<Token>, Box<Expr>),
     While(Box<Expr>, Box<Statement>),
+    // `with (var name = resource) body`, the resource is closed however the body ends
+    With(Token, Box<Expr>, Box<Statement>),
+    For(Option<Box<Statement>>, Option<Box<Expr>>, Option<Box<Expr>>, Box<Statement>),
     Block(Vec<Statement>),
+    // shared so the functions the runtime creates from it don't copy the body
+    Function(Arc<FunctionDeclaration>),
+    // the `return` keyword, the value
+    Return(Token, Option<Box<Expr>>),
+    // the `import` keyword, the module's name as a string literal
+    Import(Token, Token),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionDeclaration {
+    pub name: Token,
+    pub parameters: Vec<Parameter>,
+    // `-> Type`
+    pub return_type: Option<Token>,
+    pub body: Vec<Statement>,
+    // `async fun`, calling it starts a task instead of running the body
+    pub is_async: bool,
+    // the last parameter is `...name`, a list of the arguments past the others
+    pub variadic: bool,
+    // `@decorator` lines above the function, outermost first
+    pub decorators: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Parameter {
+    pub name: Token,
+    pub annotation: Option<Token>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    // for variables and assignments, filled in by the runtime before the program runs
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub resolution: Resolution,
 }
 
-pub enum Expr {
+// Whether a variable reference can only mean a global, and once it has been looked up, the slot
+// that global has in the interpreter that looked it up. Kept in one word so interpreters on
+// different threads sharing a program never see half of each other's update: 0 when it may be
+// a local, 1 when it's a global not looked up yet, otherwise the interpreter in the high half
+// and the slot in the low one.
+#[derive(Debug, Default)]
+pub struct Resolution(AtomicU64);
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExprKind {
     Grouping(Box<Expr>),
     Binary(Box<Expr>, Box<Expr>, Box<Expr>),
     Unary(Box<Expr>, Box<Expr>),
     Operator(TokenType, String),
     BoolLiteral(bool),
-    StringLiteral(String),
+    NilLiteral,
+    // literals with the same value in one file share the string
+    StringLiteral(Arc<str>),
     IntegerLiteral(String),
     FloatLiteral(String),
     Logical(Box<Expr>, TokenType, Box<Expr>),
     Variable(Token),
     Assign(Token, Box<Expr>),
+    Call(Box<Expr>, Token, Vec<Expr>),
+    // `[a, b]`
+    List(Vec<Expr>),
+    // `list[index]`, the token is the closing bracket
+    Index(Box<Expr>, Token, Box<Expr>),
+    // `...list` among a call's arguments or a list's elements, the list's values in its place
+    Spread(Box<Expr>),
+    // `return a, b;`, a list of the values
+    Tuple(Vec<Expr>),
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Statement {
+        Statement{kind, span}
+    }
+}
+
+impl FunctionDeclaration {
+    // A string literal standing alone as the first statement of the body documents the function.
+    // Each line is trimmed so the string can be indented along with the body.
+    pub fn doc(&self) -> Option<String> {
+        match self.body.first().map(|statement| &statement.kind) {
+            Some(StatementKind::Expression(expr)) => match &expr.kind {
+                ExprKind::StringLiteral(doc) => Some(doc.trim().lines().map(str::trim).collect::<Vec<&str>>().join("\n")),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // how it's declared, `fun name(a, ...rest) -> Type` without the body
+    pub fn signature(&self) -> String {
+        let rest = if self.variadic { self.parameters.len() } else { 0 };
+        let parameters = self.parameters.iter()
+            .enumerate()
+            .map(|(index, parameter)| match &parameter.annotation {
+                Some(annotation) => format!("{}: {}", parameter.name.lexeme, annotation.lexeme),
+                None if index + 1 == rest => format!("...{}", parameter.name.lexeme),
+                None => parameter.name.lexeme.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let return_type = match &self.return_type {
+            Some(return_type) => format!(" -> {}", return_type.lexeme),
+            None => "".to_string(),
+        };
+        let keyword = if self.is_async { "async fun" } else { "fun" };
+        format!("{} {}({}){}", keyword, self.name.lexeme, parameters, return_type)
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Box<Expr> {
+        Box::new(Expr{kind, span, resolution: Resolution::default()})
+    }
+}
+
+impl Resolution {
+    pub(crate) fn is_global(&self) -> bool {
+        self.0.load(Ordering::Relaxed) != 0
+    }
+
+    pub(crate) fn mark_global(&self) {
+        let _ = self.0.compare_exchange(0, 1, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    // the slot remembered for interpreter `owner`, 0 is never one
+    pub(crate) fn slot(&self, owner: u32) -> Option<usize> {
+        let resolution = self.0.load(Ordering::Relaxed);
+        match resolution >> 32 {
+            0 => None,
+            high if high == owner as u64 => Some(resolution as u32 as usize),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn remember(&self, owner: u32, slot: usize) {
+        if owner != 0 && slot <= u32::MAX as usize {
+            self.0.store((owner as u64) << 32 | slot as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Clone for Resolution {
+    fn clone(&self) -> Resolution {
+        Resolution(AtomicU64::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+// parses a whole token stream as produced by the scanner, trivia included
+pub fn parse(tokens: &[Token]) -> (Vec<Statement>, Vec<ParseError>) {
+    Parser::new(tokens.iter().cloned()).parse()
 }
 
 pub trait Visitor<T> {
@@ -42,60 +244,321 @@ pub trait Visitor<T> {
     fn visit_statement(&mut self, s: &Statement) -> T;
 }
 
-impl Parser<'_> {
-    pub fn parse(&mut self) -> Result<Vec<Statement>, ParseError> {
-        self.advance();
+// parses a token stream holding a single expression, with no semicolon after it
+pub fn parse_expression(tokens: &[Token]) -> Result<Box<Expr>, ParseError> {
+    Parser::new(tokens.iter().cloned()).parse_expression()
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    pub fn new(tokens: I) -> Parser<I> {
+        let mut parser = Parser{
+            iter: tokens,
+            current: None,
+            previous: None,
+            errors: Vec::new(),
+            depth: 0,
+            functions: 0,
+            nesting: 0,
+            operators: 0,
+            abandoned: false,
+            constants: HashSet::new(),
+        };
+        parser.advance();
+        parser
+    }
+
+    // Parses the whole file, recovering from errors. The statements that failed to parse are
+    // missing, so the tree is only complete when there are no errors.
+    pub fn parse(&mut self) -> (Vec<Statement>, Vec<ParseError>) {
         let mut statements: Vec<Statement> = Vec::new();
 
         while !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
         }
 
-        return Ok(statements);
+        (statements, std::mem::take(&mut self.errors))
+    }
+
+    // One top-level statement at a time, None once only Eof is left. A statement with errors
+    // anywhere inside it comes back as the first of them, the rest are in errors().
+    pub fn next_statement(&mut self) -> Option<Result<Statement, ParseError>> {
+        if self.is_at_end() {
+            return None;
+        }
+
+        let errors = self.errors.len();
+        match self.declaration() {
+            Some(statement) if self.errors.len() == errors => Some(Ok(statement)),
+            _ => Some(Err(self.errors[errors].clone())),
+        }
+    }
+
+    // A lone expression, as typed into the REPL or a debugger's `print`: no trailing semicolon,
+    // and nothing may follow it.
+    pub fn parse_expression(&mut self) -> Result<Box<Expr>, ParseError> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            return Err(self.error(Code::UnexpectedToken, "expected end of expression".to_string()));
+        }
+        Ok(expr)
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    // where the next statement starts
+    pub fn current_token(&self) -> Option<&Token> {
+        self.current.as_ref()
     }
 
     fn is_at_end(&mut self) -> bool {
-        match self.current {
+        match &self.current {
             Some(token) => token.token_type == TokenType::Eof,
             None => true
         }
     }
 
-    fn advance(&mut self) -> Option<&'_ Token> {
-        self.previous = self.current;
-        self.current = self.iter.next();
-        self.current
+    fn advance(&mut self) {
+        self.previous = self.current.take();
+        self.current = self.iter.by_ref().find(|token| !token.token_type.is_trivia());
     }
 
     fn previous_token(&mut self) -> Result<Box<Expr>, ParseError> {
-        return match &self.previous {
-            Some(token) => Ok(Box::new(Expr::Operator(token.token_type.clone(), token.lexeme.to_string()))),
-            None => Err(self.error("Internal Parser Error: No previous token found".to_string())),
+        match &self.previous {
+            Some(token) => Ok(Expr::new(ExprKind::Operator(token.token_type.clone(), token.lexeme.to_string()), token.span())),
+            None => Err(self.error(Code::Internal, "Internal Parser Error: No previous token found".to_string())),
         }
     }
 
-    // DEFER: synchronizaton on ParseError (8.2.2)
-    fn declaration(&mut self) -> Result<Statement, ParseError> {
-        if self.token_match(&[TokenType::Var]) {
-            return self.var_declaration();
+    fn current_span(&self) -> Span {
+        self.current.as_ref().map(Token::span).unwrap_or_default()
+    }
+
+    fn previous_span(&self) -> Span {
+        self.previous.as_ref().map(Token::span).unwrap_or_default()
+    }
+
+    // span running from start up to and including the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span())
+    }
+
+    // a statement that fails to parse is recorded and skipped (8.2.2), hence the Option
+    fn declaration(&mut self) -> Option<Statement> {
+        let result =
+            if self.token_match(&[TokenType::Var]) {
+                self.var_declaration()
+            } else if self.token_match(&[TokenType::Fun]) {
+                self.function_declaration(self.previous_span(), false)
+            } else if self.token_match(&[TokenType::Async]) {
+                let start = self.previous_span();
+                self.consume(TokenType::Fun, "after 'async'")
+                    .and_then(|_| self.function_declaration(start, true))
+            } else if self.check(&TokenType::At) {
+                self.decorated_function()
+            } else {
+                self.statement()
+            };
+
+        match result {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                if !self.abandoned {
+                    self.errors.push(error);
+                }
+                self.synchronize();
+                None
+            },
         }
+    }
 
-        self.statement()
+    // Skips ahead to where the next statement likely starts: just past a semicolon, or at a
+    // keyword that begins one. Inside a block it also stops at the closing brace, which the
+    // block still needs.
+    fn synchronize(&mut self) {
+        if !self.at_block_end() {
+            self.advance();
+        }
+
+        while !self.is_at_end() && !self.at_block_end() {
+            if matches!(&self.previous, Some(token) if token.token_type == TokenType::Semicolon) {
+                return;
+            }
+            match &self.current {
+                Some(token) => match token.token_type {
+                    TokenType::Class | TokenType::Fun | TokenType::Async | TokenType::At | TokenType::Var | TokenType::For | TokenType::If
+                    | TokenType::While | TokenType::With | TokenType::Print | TokenType::Return | TokenType::Import | TokenType::LeftBrace => return,
+                    _ => {},
+                },
+                None => return,
+            }
+            self.advance();
+        }
+    }
+
+    fn at_block_end(&mut self) -> bool {
+        self.depth > 0 && self.check(&TokenType::RightBrace)
     }
 
     fn var_declaration(&mut self) -> Result<Statement, ParseError> {
-        let name = self.consume(TokenType::Identifier)?; // TODO: error message different
+        let start = self.previous_span();
+        if self.token_match(&[TokenType::LeftParen]) {
+            return self.var_tuple(start);
+        }
+        let name = self.consume(TokenType::Identifier, "after 'var'")?;
+
+        let mut annotation = None;
+        if self.token_match(&[TokenType::Colon]) {
+            annotation = Some(self.consume(TokenType::Identifier, "after ':'")?);
+        }
 
         let mut initializer = None;
         if self.token_match(&[TokenType::Equal]) {
             initializer = Some(self.expression()?);
+            self.consume(TokenType::Semicolon, "after variable initializer")?;
+        } else if annotation.is_some() {
+            self.consume_any(&[TokenType::Equal, TokenType::Semicolon], "after type annotation")?;
+        } else {
+            self.consume_any(&[TokenType::Colon, TokenType::Equal, TokenType::Semicolon], "after variable name")?;
+        }
+
+        Ok(Statement::new(StatementKind::Var(name, annotation, initializer), self.span_from(start)))
+    }
+
+    fn var_tuple(&mut self, start: Span) -> Result<Statement, ParseError> {
+        let mut names = Vec::new();
+        loop {
+            names.push(self.consume(TokenType::Identifier, "for variable name")?);
+            let token = self.consume_any(&[TokenType::RightParen, TokenType::Comma], "after variable name")?;
+            if token.token_type == TokenType::RightParen {
+                break;
+            }
+        }
+        self.consume(TokenType::Equal, "after ')'")?;
+        let initializer = self.expression()?;
+        self.consume(TokenType::Semicolon, "after variable initializer")?;
+        Ok(Statement::new(StatementKind::VarTuple(names, initializer), self.span_from(start)))
+    }
+
+    fn decorated_function(&mut self) -> Result<Statement, ParseError> {
+        let start = self.current_span();
+        let mut decorators = Vec::new();
+        while self.token_match(&[TokenType::At]) {
+            decorators.push(*self.parse_precedence(Precedence::Call)?);
+        }
+        let is_async = self.token_match(&[TokenType::Async]);
+        self.consume(TokenType::Fun, "after decorator")?;
+        let mut statement = self.function_declaration(start, is_async)?;
+        if let StatementKind::Function(declaration) = &mut statement.kind {
+            Arc::make_mut(declaration).decorators = decorators;
         }
+        Ok(statement)
+    }
 
-        self.consume(TokenType::Semicolon)?;
-        Ok(Statement::Var(name, initializer))
+    // `start` is the span of the first keyword, `async` or `fun`
+    fn function_declaration(&mut self, start: Span, is_async: bool) -> Result<Statement, ParseError> {
+        let name = self.consume(TokenType::Identifier, "after 'fun'")?;
+        self.consume(TokenType::LeftParen, "after function name")?;
+
+        let mut parameters = Vec::new();
+        let mut variadic = false;
+        if !self.token_match(&[TokenType::RightParen]) {
+            loop {
+                if parameters.len() >= MAX_ARGUMENTS {
+                    return Err(self.error(Code::TooManyArguments, format!("Cannot have more than {} parameters.", MAX_ARGUMENTS)));
+                }
+                if self.token_match(&[TokenType::Ellipsis]) {
+                    let name = self.consume(TokenType::Identifier, "after '...'")?;
+                    parameters.push(Parameter{name, annotation: None});
+                    variadic = true;
+                    self.consume(TokenType::RightParen, "after the rest parameter, which has to be the last")?;
+                    break;
+                }
+                let name = self.consume(TokenType::Identifier, "for parameter name")?;
+                let mut annotation = None;
+                if self.token_match(&[TokenType::Colon]) {
+                    annotation = Some(self.consume(TokenType::Identifier, "after ':'")?);
+                }
+                parameters.push(Parameter{name, annotation});
+
+                let token = self.consume_any(&[TokenType::RightParen, TokenType::Comma], "after parameter")?;
+                if token.token_type == TokenType::RightParen {
+                    break;
+                }
+            }
+        }
+
+        let mut return_type = None;
+        if self.token_match(&[TokenType::Arrow]) {
+            return_type = Some(self.consume(TokenType::Identifier, "after '->'")?);
+        }
+
+        self.consume(TokenType::LeftBrace, "before function body")?;
+        self.functions += 1;
+        let body = self.block();
+        self.functions -= 1;
+
+        let declaration = FunctionDeclaration{name, parameters, return_type, body: body?, is_async, variadic, decorators: Vec::new()};
+        Ok(Statement::new(StatementKind::Function(Arc::new(declaration)), self.span_from(start)))
+    }
+
+    fn return_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = match self.previous.clone() {
+            Some(token) => token,
+            None => return Err(self.error(Code::Internal, "Internal Parser Error: No previous token found".to_string())),
+        };
+        if self.functions == 0 {
+            return Err(ParseError{
+                code: Code::ReturnOutsideFunction,
+                message: "can't return from top-level code".to_string(),
+                span: Some(keyword.span()),
+                found: Some(keyword.lexeme.clone()),
+                expected: Vec::new(),
+            });
+        }
+
+        let value =
+            if !self.check(&TokenType::Semicolon) {
+                Some(self.values()?)
+            } else {
+                None
+            };
+        self.consume(TokenType::Semicolon, "after return value")?;
+        Ok(Statement::new(StatementKind::Return(keyword.clone(), value), self.span_from(keyword.span())))
+    }
+
+    // `a` or `a, b, ...`, the second a Tuple; what a return or a parallel assignment gives
+    fn values(&mut self) -> Result<Box<Expr>, ParseError> {
+        let first = self.expression()?;
+        if !self.check(&TokenType::Comma) {
+            return Ok(first);
+        }
+        let start = first.span;
+        let mut values = vec![*first];
+        while self.token_match(&[TokenType::Comma]) {
+            values.push(*self.expression()?);
+        }
+        Ok(Expr::new(ExprKind::Tuple(values), self.span_from(start)))
+    }
+
+    fn import_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = match self.previous.clone() {
+            Some(token) => token,
+            None => return Err(self.error(Code::Internal, "Internal Parser Error: No previous token found".to_string())),
+        };
+        let name = self.consume(TokenType::Str, "after 'import'")?;
+        self.consume(TokenType::Semicolon, "after module name")?;
+        Ok(Statement::new(StatementKind::Import(keyword.clone(), name), self.span_from(keyword.span())))
     }
 
     fn statement(&mut self) -> Result<Statement, ParseError> {
+        self.nested(Parser::nested_statement)
+    }
+
+    fn nested_statement(&mut self) -> Result<Statement, ParseError> {
         if self.token_match(&[TokenType::For]) {
             return self.for_statement();
         }
@@ -105,36 +568,47 @@ impl Parser<'_> {
         if self.token_match(&[TokenType::Print]) {
             return self.print_statement();
         }
+        if self.token_match(&[TokenType::Return]) {
+            return self.return_statement();
+        }
         if self.token_match(&[TokenType::While]) {
             return self.while_statement();
         }
+        if self.token_match(&[TokenType::With]) {
+            return self.with_statement();
+        }
+        if self.token_match(&[TokenType::Import]) {
+            return self.import_statement();
+        }
         if self.token_match(&[TokenType::LeftBrace]) {
-            return Ok(Statement::Block(self.block()?));
+            let start = self.previous_span();
+            let statements = self.block()?;
+            return Ok(Statement::new(StatementKind::Block(statements), self.span_from(start)));
         }
 
-        return self.expression_statement();
+        self.expression_statement()
     }
 
     fn for_statement(&mut self) -> Result<Statement, ParseError> {
-        // Desugared while loop
-        self.consume(TokenType::LeftParen)?;
+        let start = self.previous_span();
+        self.consume(TokenType::LeftParen, "after 'for'")?;
 
         let initializer =
             if self.token_match(&[TokenType::Semicolon]) {
                 None
             } else if self.token_match(&[TokenType::Var]) {
-                Some(self.var_declaration()?)
+                Some(Box::new(self.var_declaration()?))
             } else {
-                Some(self.expression_statement()?)
+                Some(Box::new(self.expression_statement()?))
             };
 
         let condition =
             if !self.check(&TokenType::Semicolon) {
-                self.expression()?
+                Some(self.expression()?)
             } else {
-                Box::new(Expr::BoolLiteral(true))
+                None
             };
-        self.consume(TokenType::Semicolon)?;
+        self.consume(TokenType::Semicolon, "after loop condition")?;
 
         let increment =
             if !self.check(&TokenType::RightParen) {
@@ -142,40 +616,30 @@ impl Parser<'_> {
             } else {
                 None
             };
-        self.consume(TokenType::RightParen)?;
+        self.consume(TokenType::RightParen, "after for clauses")?;
 
-        let mut body = self.statement()?;
-
-        match increment {
-            Some(expr) => {
-                let mut statements = Vec::new();
-                statements.push(body);
-                statements.push(Statement::Expression(expr));
-
-                body = Statement::Block(statements);
-            }
-            None => {}
-        }
-
-        body = Statement::While(condition, Box::new(body));
+        let body = self.statement()?;
 
-        match initializer {
-            Some(statement) => {
-                let mut statements = Vec::new();
-                statements.push(statement);
-                statements.push(body);
-                body = Statement::Block(statements);
-            },
-            None => {}
-        }
+        Ok(Statement::new(StatementKind::For(initializer, condition, increment, Box::new(body)), self.span_from(start)))
+    }
 
-        Ok(body)
+    fn with_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous_span();
+        self.consume(TokenType::LeftParen, "after 'with'")?;
+        self.consume(TokenType::Var, "after '('")?;
+        let name = self.consume(TokenType::Identifier, "after 'var'")?;
+        self.consume(TokenType::Equal, "after variable name")?;
+        let resource = self.expression()?;
+        self.consume(TokenType::RightParen, "after the resource")?;
+        let body = self.statement()?;
+        Ok(Statement::new(StatementKind::With(name, resource, Box::new(body)), self.span_from(start)))
     }
 
     fn if_statement(&mut self) -> Result<Statement, ParseError> {
-        self.consume(TokenType::LeftParen)?;
+        let start = self.previous_span();
+        self.consume(TokenType::LeftParen, "after 'if'")?;
         let condition = self.expression()?;
-        self.consume(TokenType::RightParen)?;
+        self.consume(TokenType::RightParen, "after if condition")?;
 
         let then_branch = Box::new(self.statement()?);
         let else_branch =
@@ -185,226 +649,336 @@ impl Parser<'_> {
                 None
             };
 
-        Ok(Statement::If(condition, then_branch, else_branch))
+        Ok(Statement::new(StatementKind::If(condition, then_branch, else_branch), self.span_from(start)))
     }
 
     fn while_statement(&mut self) -> Result<Statement, ParseError> {
-        self.consume(TokenType::LeftParen)?;
+        let start = self.previous_span();
+        self.consume(TokenType::LeftParen, "after 'while'")?;
         let condition = self.expression()?;
-        self.consume(TokenType::RightParen)?;
+        self.consume(TokenType::RightParen, "after while condition")?;
 
         let body = self.statement()?;
 
-        Ok(Statement::While(condition, Box::new(body)))
+        Ok(Statement::new(StatementKind::While(condition, Box::new(body)), self.span_from(start)))
     }
 
     fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        self.nested(Parser::nested_block)
+    }
+
+    fn nested_block(&mut self) -> Result<Vec<Statement>, ParseError> {
         let mut statements = Vec::new();
 
+        self.depth += 1;
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
         }
+        self.depth -= 1;
 
-        self.consume(TokenType::RightBrace)?; // TODO: handle error message: Expect '}' after block."
+        self.consume(TokenType::RightBrace, "to close block")?;
         Ok(statements)
     }
 
     fn print_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous_span();
         let value = self.expression()?;
-        self.consume(TokenType::Semicolon)?;
-        Ok(Statement::Print(value))
+        self.consume(TokenType::Semicolon, "after value")?;
+        Ok(Statement::new(StatementKind::Print(value), self.span_from(start)))
     }
 
     fn expression_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.current_span();
         let value = self.expression()?;
-        self.consume(TokenType::Semicolon)?;
-        Ok(Statement::Expression(value))
-    }
-
-    fn expression(&mut self) -> Result<Box<Expr>, ParseError> {
-        self.assignment()
-    }
-
-    fn assignment(&mut self) -> Result<Box<Expr>, ParseError> {
-        let expr = self.or()?;
-
-        if self.token_match(&[TokenType::Equal]) {
-            let value = self.assignment()?;
-
-            match *expr {
-                Expr::Variable(token) => return Ok(Box::new(Expr::Assign(token.clone(), value))),
-                _ => return Err(self.error(format!("Invalid assignment target."))),
-            }
+        if let (true, ExprKind::Variable(first)) = (self.check(&TokenType::Comma), &value.kind) {
+            return self.assign_tuple(start, first.clone());
         }
-
-        Ok(expr)
+        self.consume(TokenType::Semicolon, "after expression")?;
+        Ok(Statement::new(StatementKind::Expression(value), self.span_from(start)))
     }
 
-    fn or(&mut self) -> Result<Box<Expr>, ParseError> {
-        let mut expr = self.and()?;
-
-        while self.token_match(&[TokenType::Or]) {
-            let right = self.and()?;
-            expr = Box::new(Expr::Logical(expr, TokenType::Or, right));
+    fn assign_tuple(&mut self, start: Span, first: Token) -> Result<Statement, ParseError> {
+        let mut names = vec![first];
+        while self.token_match(&[TokenType::Comma]) {
+            names.push(self.consume(TokenType::Identifier, "after ','")?);
         }
-
-        Ok(expr)
+        self.consume(TokenType::Equal, "after the variables being assigned")?;
+        let value = self.values()?;
+        self.consume(TokenType::Semicolon, "after expression")?;
+        Ok(Statement::new(StatementKind::AssignTuple(names, value), self.span_from(start)))
     }
 
-    fn and(&mut self) -> Result<Box<Expr>, ParseError> {
-        let mut expr = self.equality()?;
-
-        while self.token_match(&[TokenType::And]) {
-            let right = self.equality()?;
-            expr = Box::new(Expr::Logical(expr, TokenType::And, right));
-        }
+    fn expression(&mut self) -> Result<Box<Expr>, ParseError> {
+        self.parse_precedence(Precedence::Assignment)
+    }
 
-        Ok(expr)
+    // Pratt parsing: a prefix expression, then as many infix operators as bind at least as
+    // tightly as `minimum`, each taking its right operand at its own level (one higher when
+    // left associative).
+    fn parse_precedence(&mut self, minimum: Precedence) -> Result<Box<Expr>, ParseError> {
+        self.nested(|parser| parser.nested_precedence(minimum))
     }
 
-    fn equality(&mut self) -> Result<Box<Expr>, ParseError> {
-        let mut expr = self.comparison()?;
+    fn nested_precedence(&mut self, minimum: Precedence) -> Result<Box<Expr>, ParseError> {
+        let mut expr = self.prefix()?;
 
-        while self.token_match(&[TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous_token()?;
-            let right = self.comparison()?;
-            expr = Box::new(Expr::Binary(expr, operator, right));
+        let outer = self.operators;
+        while let Some(rule) = self.current.as_ref().and_then(|token| precedence::infix_rule(&token.token_type)) {
+            if rule.precedence < minimum {
+                break;
+            }
+            self.advance();
+            let right_precedence = match rule.associativity {
+                Associativity::Left => rule.precedence.next(),
+                Associativity::Right => rule.precedence,
+            };
+            // a call or index puts the expression so far one level further down the tree; a run
+            // of operators like `1 + 2 + 3` is flat source and isn't counted
+            if matches!(rule.infix, Infix::Call | Infix::Index) {
+                self.deeper()?;
+            } else if self.operators >= MAX_OPERATORS {
+                return Err(self.give_up(format!("Cannot have more than {} operators in a row.", MAX_OPERATORS)));
+            } else {
+                self.operators += 1;
+            }
+            expr = self.infix(rule.infix, expr, right_precedence)?;
         }
+        self.operators = outer;
 
-        return Ok(expr);
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Box<Expr>, ParseError> {
-        let mut expr = self.addition()?;
-
-        while self.token_match(&[TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual]) {
+    fn prefix(&mut self) -> Result<Box<Expr>, ParseError> {
+        if matches!(&self.current, Some(token) if precedence::is_prefix_operator(&token.token_type)) {
+            self.advance();
             let operator = self.previous_token()?;
-            let right = self.addition()?;
-            expr = Box::new(Expr::Binary(expr, operator, right));
+            let right = self.parse_precedence(Precedence::Unary)?;
+            let span = operator.span.to(right.span);
+            return Ok(Expr::new(ExprKind::Unary(operator, right), span));
         }
 
-        return Ok(expr);
+        self.primary()
     }
 
-    fn addition(&mut self) -> Result<Box<Expr>, ParseError> {
-        let mut expr = self.multiplication()?;
-
-        while self.token_match(&[TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous_token()?;
-            let right = self.multiplication()?;
-            expr = Box::new(Expr::Binary(expr, operator, right));
+    // the operator has just been consumed
+    fn infix(&mut self, infix: Infix, left: Box<Expr>, right_precedence: Precedence) -> Result<Box<Expr>, ParseError> {
+        if infix == Infix::Call {
+            return self.finish_call(left);
+        }
+        if infix == Infix::Index {
+            let index = self.expression()?;
+            let bracket = self.consume(TokenType::RightBracket, "after index")?;
+            let span = left.span.to(bracket.span());
+            return Ok(Expr::new(ExprKind::Index(left, bracket, index), span));
+        }
+
+        let operator = self.previous_token()?;
+        let right = self.parse_precedence(right_precedence)?;
+        let span = left.span.to(right.span);
+        match infix {
+            Infix::Assign => match left.kind {
+                ExprKind::Variable(token) => Ok(Expr::new(ExprKind::Assign(token, right), span)),
+                _ => Err(self.error(Code::InvalidAssignmentTarget, "Invalid assignment target.".to_string())),
+            },
+            Infix::Logical => match operator.kind {
+                ExprKind::Operator(token_type, _) => Ok(Expr::new(ExprKind::Logical(left, token_type, right), span)),
+                _ => Err(self.error(Code::Internal, "Internal Parser Error: operator is not a token".to_string())),
+            },
+            Infix::Binary | Infix::Call | Infix::Index => Ok(Expr::new(ExprKind::Binary(left, operator, right), span)),
         }
-
-        return Ok(expr);
     }
 
-    fn multiplication(&mut self) -> Result<Box<Expr>, ParseError> {
-        let mut expr = self.unary()?;
+    fn finish_call(&mut self, callee: Box<Expr>) -> Result<Box<Expr>, ParseError> {
+        let mut arguments = Vec::new();
 
-        while self.token_match(&[TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous_token()?;
-            let right = self.unary()?;
-            expr = Box::new(Expr::Binary(expr, operator, right));
-        }
+        let paren =
+            if self.check(&TokenType::RightParen) {
+                self.consume(TokenType::RightParen, "after '('")?
+            } else {
+                loop {
+                    if arguments.len() >= MAX_ARGUMENTS {
+                        return Err(self.error(Code::TooManyArguments, format!("Cannot have more than {} arguments.", MAX_ARGUMENTS)));
+                    }
+                    arguments.push(*self.element()?);
 
-        return Ok(expr);
+                    let token = self.consume_any(&[TokenType::RightParen, TokenType::Comma], "after argument")?;
+                    if token.token_type == TokenType::RightParen {
+                        break token;
+                    }
+                }
+            };
+        let span = callee.span.to(paren.span());
+        Ok(Expr::new(ExprKind::Call(callee, paren, arguments), span))
     }
 
-    fn unary(&mut self) -> Result<Box<Expr>, ParseError> {
-        if self.token_match(&[TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous_token()?;
-            let right = self.unary()?;
-            return Ok(Box::new(Expr::Unary(operator, right)));
+    // an argument or list element, which may be spread
+    fn element(&mut self) -> Result<Box<Expr>, ParseError> {
+        if self.token_match(&[TokenType::Ellipsis]) {
+            let start = self.previous_span();
+            let list = self.expression()?;
+            return Ok(Expr::new(ExprKind::Spread(list), self.span_from(start)));
         }
-
-        return self.primary();
+        self.expression()
     }
 
     fn primary(&mut self) -> Result<Box<Expr>, ParseError> {
         if self.token_match(&[TokenType::False]) {
-            return Ok(Box::new(Expr::BoolLiteral(false)));
+            return Ok(Expr::new(ExprKind::BoolLiteral(false), self.previous_span()));
         }
         if self.token_match(&[TokenType::True]) {
-            return Ok(Box::new(Expr::BoolLiteral(true)));
+            return Ok(Expr::new(ExprKind::BoolLiteral(true), self.previous_span()));
+        }
+        if self.token_match(&[TokenType::Nil]) {
+            return Ok(Expr::new(ExprKind::NilLiteral, self.previous_span()));
         }
         if self.token_match(&[TokenType::Integer]) {
             match &self.previous {
-                Some(token) => return Ok(Box::new(Expr::IntegerLiteral(token.lexeme.to_string()))),
-                None => return Err(self.error("I DONT KNOW WHAT HAPPENED".to_string()))
+                Some(token) => return Ok(Expr::new(ExprKind::IntegerLiteral(token.lexeme.to_string()), token.span())),
+                None => return Err(self.error(Code::Internal, "I DONT KNOW WHAT HAPPENED".to_string()))
             }
         }
         if self.token_match(&[TokenType::Float]) {
             match &self.previous {
-                Some(token) => return Ok(Box::new(Expr::FloatLiteral(token.lexeme.to_string()))),
-                None => return Err(self.error("I DONT KNOW WHAT HAPPENED".to_string()))
+                Some(token) => return Ok(Expr::new(ExprKind::FloatLiteral(token.lexeme.to_string()), token.span())),
+                None => return Err(self.error(Code::Internal, "I DONT KNOW WHAT HAPPENED".to_string()))
             }
         }
         if self.token_match(&[TokenType::Str]) {
             match &self.previous {
-                Some(token) => return Ok(Box::new(Expr::StringLiteral(token.lexeme.to_string()))),
-                None => return Err(self.error("I DONT KNOW WHAT HAPPENED".to_string()))
+                // the value, without the quotes around it
+                Some(token) => {
+                    let value = token.lexeme.strip_prefix('"').unwrap_or(&token.lexeme);
+                    let value = value.strip_suffix('"').unwrap_or(value);
+                    let value = match self.constants.get(value) {
+                        Some(constant) => constant.clone(),
+                        None => {
+                            let constant: Arc<str> = value.into();
+                            self.constants.insert(constant.clone());
+                            constant
+                        },
+                    };
+                    return Ok(Expr::new(ExprKind::StringLiteral(value), token.span()));
+                },
+                None => return Err(self.error(Code::Internal, "I DONT KNOW WHAT HAPPENED".to_string()))
             }
         }
         if self.token_match(&[TokenType::Identifier]) {
             match &self.previous {
-                Some(token) => return Ok(Box::new(Expr::Variable((**token).clone()))),
-                None => return Err(self.error("I DONT KNOW WHAT HAPPENED".to_string()))
+                Some(token) => return Ok(Expr::new(ExprKind::Variable(token.clone()), token.span())),
+                None => return Err(self.error(Code::Internal, "I DONT KNOW WHAT HAPPENED".to_string()))
             }
         }
 
+        if self.token_match(&[TokenType::LeftBracket]) {
+            let start = self.previous_span();
+            let mut elements = Vec::new();
+            if !self.token_match(&[TokenType::RightBracket]) {
+                loop {
+                    elements.push(*self.element()?);
+                    let token = self.consume_any(&[TokenType::RightBracket, TokenType::Comma], "after list element")?;
+                    if token.token_type == TokenType::RightBracket {
+                        break;
+                    }
+                }
+            }
+            return Ok(Expr::new(ExprKind::List(elements), self.span_from(start)));
+        }
+
         if self.token_match(&[TokenType::LeftParen]) {
+            let start = self.previous_span();
             let expr = self.expression()?;
-            self.consume(TokenType::RightParen)?;
-            return Ok(Box::new(Expr::Grouping(expr)));
+            self.consume(TokenType::RightParen, "after grouped expression")?;
+            return Ok(Expr::new(ExprKind::Grouping(expr), self.span_from(start)));
         }
 
-        Err(self.error("Expected literal".to_string()))
+        Err(self.error(Code::ExpectedExpression, "expected expression".to_string()))
     }
 
     fn token_match(&mut self, token_types: &[TokenType]) -> bool {
-        match self.current {
-            Some(token) => {
-                for token_type in token_types {
-                    if token.token_type == *token_type {
-                        self.advance();
-                        return true;
-                    }
-                }
-                return false
-            }
-            None => return false
+        let matched = match &self.current {
+            Some(token) => token_types.contains(&token.token_type),
+            None => false
+        };
+        if matched {
+            self.advance();
         }
+        matched
     }
 
-    fn consume(&mut self, token_type: TokenType) -> Result<Token, ParseError> {
-        if self.check(&token_type) {
-            let result =
-                match self.current {
-                    Some(token) => Ok(token.clone()),
-                    None => Err(self.error(format!("advanced past end on token check"))) // should be unreachable
-                };
-            self.advance();
-            result
-        } else {
-            Err(self.error(format!("expected {:?} after expression", token_type)))
+    fn consume(&mut self, token_type: TokenType, context: &str) -> Result<Token, ParseError> {
+        self.consume_any(&[token_type], context)
+    }
+
+    // takes the current token if it is any of `expected`, or fails with "expected <one of them> <context>"
+    fn consume_any(&mut self, expected: &[TokenType], context: &str) -> Result<Token, ParseError> {
+        match &self.current {
+            Some(token) if expected.contains(&token.token_type) => {
+                let token = token.clone();
+                self.advance();
+                Ok(token)
+            },
+            _ => {
+                let mut error = self.error(Code::ExpectedToken, format!("expected {} {}", describe_all(expected), context));
+                error.expected = expected.to_vec();
+                Err(error)
+            },
         }
     }
 
     fn check(&mut self, token_type: &TokenType) -> bool {
-        match self.current {
+        match &self.current {
             Some(token) => &token.token_type == token_type,
             None => false
         }
     }
 
-    fn error(&mut self, message: String) -> ParseError {
-        match self.current {
-            Some(token) =>
-                match token.token_type {
-                    TokenType::Eof => ParseError{message: format!("{} at end of file {}:{}", message, token.line_number, token.column_number) },
-                    _ => ParseError{message: format!("{} at '{}' line {}:{}", message, token.lexeme, token.line_number, token.column_number) },
-                }
-            None => ParseError{message: format!("unexpected EOF: {}", message)}
+    // runs `parse` one level deeper
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        let outer = self.nesting;
+        self.deeper()?;
+        let result = parse(self);
+        self.nesting = outer;
+        result
+    }
+
+    // one more level, or giving up on the file when that is past MAX_NESTING
+    fn deeper(&mut self) -> Result<(), ParseError> {
+        if self.nesting >= MAX_NESTING {
+            return Err(self.give_up(format!("Cannot nest more than {} levels deep.", MAX_NESTING)));
         }
+        self.nesting += 1;
+        Ok(())
+    }
+
+    // the error for input too deep to parse, skipping the rest of the file
+    fn give_up(&mut self, message: String) -> ParseError {
+        let error = self.error(Code::TooDeeplyNested, message);
+        if !self.abandoned {
+            self.errors.push(error.clone());
+            self.abandoned = true;
+        }
+        while !self.is_at_end() {
+            self.advance();
+        }
+        error
+    }
+
+    fn error(&mut self, code: Code, message: String) -> ParseError {
+        let found = self.current.as_ref()
+            .filter(|token| token.token_type != TokenType::Eof)
+            .map(|token| token.lexeme.clone());
+        ParseError{code, message, span: self.current.as_ref().map(Token::span), found, expected: Vec::new()}
+    }
+}
+
+// "a", "a or b", "a, b or c"
+fn describe_all(token_types: &[TokenType]) -> String {
+    let names: Vec<&str> = token_types.iter().map(TokenType::describe).collect();
+    match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
     }
 }
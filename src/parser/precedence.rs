@@ -0,0 +1,110 @@
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::scanner::token::TokenType;
+
+// Binding power, weakest first. Each level binds tighter than the ones above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+}
+
+impl Precedence {
+    // the level a left associative operator's right operand is parsed at
+    pub fn next(self) -> Precedence {
+        match self {
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary | Precedence::Call => Precedence::Call,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+// which tree an infix operator builds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Infix {
+    Assign,
+    Logical,
+    Binary,
+    Call,
+    Index,
+}
+
+pub struct Rule {
+    pub token_type: TokenType,
+    pub precedence: Precedence,
+    pub associativity: Associativity,
+    pub infix: Infix,
+}
+
+const fn rule(token_type: TokenType, precedence: Precedence, associativity: Associativity, infix: Infix) -> Rule {
+    Rule{token_type, precedence, associativity, infix}
+}
+
+// Every operator that goes between (or after) operands. A new operator needs an entry here
+// and, for Binary, an arm in the evaluator.
+const INFIX_RULES: &[Rule] = &[
+    rule(TokenType::Equal, Precedence::Assignment, Associativity::Right, Infix::Assign),
+    rule(TokenType::Or, Precedence::Or, Associativity::Left, Infix::Logical),
+    rule(TokenType::And, Precedence::And, Associativity::Left, Infix::Logical),
+    rule(TokenType::BangEqual, Precedence::Equality, Associativity::Left, Infix::Binary),
+    rule(TokenType::EqualEqual, Precedence::Equality, Associativity::Left, Infix::Binary),
+    rule(TokenType::Is, Precedence::Equality, Associativity::Left, Infix::Binary),
+    rule(TokenType::Greater, Precedence::Comparison, Associativity::Left, Infix::Binary),
+    rule(TokenType::GreaterEqual, Precedence::Comparison, Associativity::Left, Infix::Binary),
+    rule(TokenType::Less, Precedence::Comparison, Associativity::Left, Infix::Binary),
+    rule(TokenType::LessEqual, Precedence::Comparison, Associativity::Left, Infix::Binary),
+    rule(TokenType::In, Precedence::Comparison, Associativity::Left, Infix::Binary),
+    rule(TokenType::Minus, Precedence::Term, Associativity::Left, Infix::Binary),
+    rule(TokenType::Plus, Precedence::Term, Associativity::Left, Infix::Binary),
+    rule(TokenType::Slash, Precedence::Factor, Associativity::Left, Infix::Binary),
+    rule(TokenType::TildeSlash, Precedence::Factor, Associativity::Left, Infix::Binary),
+    rule(TokenType::Star, Precedence::Factor, Associativity::Left, Infix::Binary),
+    rule(TokenType::Percent, Precedence::Factor, Associativity::Left, Infix::Binary),
+    rule(TokenType::LeftParen, Precedence::Call, Associativity::Left, Infix::Call),
+    rule(TokenType::LeftBracket, Precedence::Call, Associativity::Left, Infix::Index),
+];
+
+// operators that come before their operand, all parsed as Unary
+const PREFIX_OPERATORS: &[TokenType] = &[TokenType::Bang, TokenType::Minus, TokenType::Await];
+
+pub fn infix_rule(token_type: &TokenType) -> Option<&'static Rule> {
+    INFIX_RULES.iter().find(|rule| rule.token_type == *token_type)
+}
+
+pub fn is_prefix_operator(token_type: &TokenType) -> bool {
+    PREFIX_OPERATORS.contains(token_type)
+}
+
+// How tightly an expression holds together: as an operand somewhere that takes a higher level,
+// it only parses back the same inside parentheses.
+pub fn binding(expr: &Expr) -> Precedence {
+    match &expr.kind {
+        ExprKind::Assign(..) => Precedence::Assignment,
+        ExprKind::Logical(_, token_type, _) => infix_rule(token_type).map_or(Precedence::Call, |rule| rule.precedence),
+        ExprKind::Binary(_, operator, _) => match &operator.kind {
+            ExprKind::Operator(token_type, _) => infix_rule(token_type).map_or(Precedence::Call, |rule| rule.precedence),
+            _ => Precedence::Call,
+        },
+        ExprKind::Unary(..) => Precedence::Unary,
+        _ => Precedence::Call,
+    }
+}
@@ -1,12 +1,17 @@
+pub mod json;
+pub mod minify;
+
 use crate::parser::Visitor;
 use crate::parser::Expr;
+use crate::parser::ExprKind;
 use crate::parser::Statement;
+use crate::parser::StatementKind;
 
 pub struct AstPrinter {
     pub indent: u32,
 }
 impl AstPrinter {
-    pub fn print(&mut self, statements: &Vec<Statement>) {
+    pub fn print(&mut self, statements: &[Statement]) {
         for statement in statements.iter() {
             let string = self.visit_statement(statement);
             println!("{}", string);
@@ -16,54 +21,115 @@ impl AstPrinter {
 
 impl Visitor<String> for AstPrinter {
     fn visit_expr(&mut self, e: &Expr) -> String {
-        match &*e {
-            Expr::BoolLiteral(b) => format!("{}", b),
-            Expr::StringLiteral(n) => n.to_string(),
-            Expr::IntegerLiteral(n) => n.to_string(),
-            Expr::FloatLiteral(n) => n.to_string(),
-            Expr::Logical(ref lhs, token_type, ref rhs) => format!("{} {:?} {}", self.visit_expr(lhs), token_type, self.visit_expr(rhs)),
-            Expr::Operator(_token_type, n) => n.to_string(),
-            Expr::Unary(ref operator, ref rhs) => format!("({} {})", self.visit_expr(operator), self.visit_expr(rhs)),
-            Expr::Binary(ref lhs, ref operator, ref rhs) => format!("({} {} {})", self.visit_expr(operator), self.visit_expr(lhs), self.visit_expr(rhs)),
-            Expr::Grouping(ref expr) => format!("{}", self.visit_expr(expr)),
-            Expr::Variable(token) => format!("{}", token.lexeme.to_string()),
-            Expr::Assign(token, ref expr) => format!("{} = {}", token.lexeme.to_string(), self.visit_expr(expr)),
+        match &e.kind {
+            ExprKind::BoolLiteral(b) => format!("{}", b),
+            ExprKind::NilLiteral => "nil".to_string(),
+            ExprKind::Spread(ref list) => format!("(... {})", self.visit_expr(list)),
+            ExprKind::Tuple(values) => format!("(tuple {})", values.iter().map(|value| self.visit_expr(value)).collect::<Vec<String>>().join(" ")),
+            ExprKind::StringLiteral(n) => format!("\"{}\"", n),
+            ExprKind::IntegerLiteral(n) => n.to_string(),
+            ExprKind::FloatLiteral(n) => n.to_string(),
+            ExprKind::Logical(ref lhs, token_type, ref rhs) => format!("{} {:?} {}", self.visit_expr(lhs), token_type, self.visit_expr(rhs)),
+            ExprKind::Operator(_token_type, n) => n.to_string(),
+            ExprKind::Unary(ref operator, ref rhs) => format!("({} {})", self.visit_expr(operator), self.visit_expr(rhs)),
+            ExprKind::Binary(ref lhs, ref operator, ref rhs) => format!("({} {} {})", self.visit_expr(operator), self.visit_expr(lhs), self.visit_expr(rhs)),
+            ExprKind::Grouping(ref expr) => self.visit_expr(expr).to_string(),
+            ExprKind::Variable(token) => token.lexeme.to_string(),
+            ExprKind::Assign(token, ref expr) => format!("{} = {}", token.lexeme, self.visit_expr(expr)),
+            ExprKind::Call(ref callee, _paren, arguments) => {
+                let arguments = arguments.iter()
+                    .map(|argument| self.visit_expr(argument))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("{}({})", self.visit_expr(callee), arguments)
+            },
+            ExprKind::List(elements) => {
+                let elements = elements.iter()
+                    .map(|element| self.visit_expr(element))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("[{}]", elements)
+            },
+            ExprKind::Index(ref list, _bracket, ref index) => format!("{}[{}]", self.visit_expr(list), self.visit_expr(index)),
         }
     }
 
     fn visit_statement(&mut self, s: &Statement) -> String {
-        match &*s {
-            Statement::Expression(ref expr) => self.visit_expr(expr),
-            Statement::If(ref expr, ref then_statement, ref else_branch) => match else_branch {
+        match &s.kind {
+            StatementKind::Expression(ref expr) => self.visit_expr(expr),
+            StatementKind::If(ref expr, ref then_statement, ref else_branch) => match else_branch {
                 Some(else_statement) => format!("if {} then {} else {}", self.visit_expr(expr), self.visit_statement(then_statement), self.visit_statement(else_statement)),
                 None => format!("if {} then {}", self.visit_expr(expr), self.visit_statement(then_statement)),
             },
-            Statement::Print(ref expr) => format!("print {};", self.visit_expr(expr)),
-            Statement::While(ref condition, ref body) => format!("while {} {}", self.visit_expr(condition), self.visit_statement(body)),
-            Statement::Var(token, initializer) => {
+            StatementKind::Print(ref expr) => format!("print {};", self.visit_expr(expr)),
+            StatementKind::While(ref condition, ref body) => format!("while {} {}", self.visit_expr(condition), self.visit_statement(body)),
+            StatementKind::With(name, resource, body) => format!("with {} {} {}", name.lexeme, self.visit_expr(resource), self.visit_statement(body)),
+            StatementKind::For(initializer, condition, increment, body) => {
+                let initializer = match initializer {
+                    Some(statement) => self.visit_statement(statement),
+                    None => ";".to_string(),
+                };
+                let condition = match condition {
+                    Some(expr) => self.visit_expr(expr),
+                    None => "".to_string(),
+                };
+                let increment = match increment {
+                    Some(expr) => self.visit_expr(expr),
+                    None => "".to_string(),
+                };
+                format!("for {} {}; {} {}", initializer, condition, increment, self.visit_statement(body))
+            },
+            StatementKind::Var(token, annotation, initializer) => {
+                let name = match annotation {
+                    Some(annotation) => format!("{}: {}", token.lexeme, annotation.lexeme),
+                    None => token.lexeme.to_string(),
+                };
                 match initializer {
-                    Some(expr) => format!("var {} = {};", token.lexeme.to_string(), self.visit_expr(expr)),
-                    None => format!("var {};", token.lexeme.to_string()),
+                    Some(expr) => format!("var {} = {};", name, self.visit_expr(expr)),
+                    None => format!("var {};", name),
                 }
             },
-            Statement::Block(statements) => {
-                let mut s = String::new();
-                s.push('{');
-                s.push('\n');
+            StatementKind::VarTuple(names, initializer) => {
+                let names = names.iter().map(|name| name.lexeme.to_string()).collect::<Vec<String>>().join(", ");
+                format!("var ({}) = {};", names, self.visit_expr(initializer))
+            },
+            StatementKind::AssignTuple(names, value) => {
+                let names = names.iter().map(|name| name.lexeme.to_string()).collect::<Vec<String>>().join(", ");
+                format!("{} = {};", names, self.visit_expr(value))
+            },
+            StatementKind::Block(statements) => self.block(statements),
+            StatementKind::Function(declaration) => {
+                let decorators: String = declaration.decorators.iter()
+                    .map(|decorator| format!("@{}\n{}", self.visit_expr(decorator), left_pad(self.indent, "".to_string())))
+                    .collect();
+                format!("{}{} {}", decorators, declaration.signature(), self.block(&declaration.body))
+            },
+            StatementKind::Return(_keyword, value) => match value {
+                Some(expr) => format!("return {};", self.visit_expr(expr)),
+                None => "return;".to_string(),
+            },
+            StatementKind::Import(_keyword, name) => format!("import {};", name.lexeme),
+        }
+    }
+}
 
-                self.indent += 2;
-                let string = statements.iter()
-                    .map(|statement| left_pad(self.indent, self.visit_statement(statement)))
-                    .collect::<Vec<String>>()
-                    .join("\n");
-                s.push_str(&string);
-                s.push('\n');
-                self.indent -= 2;
+impl AstPrinter {
+    fn block(&mut self, statements: &[Statement]) -> String {
+        let mut s = String::new();
+        s.push('{');
+        s.push('\n');
 
-                s.push_str(&left_pad(self.indent, "}".to_string()));
-                s
-            }
-        }
+        self.indent += 2;
+        let string = statements.iter()
+            .map(|statement| left_pad(self.indent, self.visit_statement(statement)))
+            .collect::<Vec<String>>()
+            .join("\n");
+        s.push_str(&string);
+        s.push('\n');
+        self.indent -= 2;
+
+        s.push_str(&left_pad(self.indent, "}".to_string()));
+        s
     }
 }
 
@@ -0,0 +1,233 @@
+use crate::json::Json;
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::parser::Visitor;
+use crate::scanner::token::Span;
+
+pub struct JsonPrinter {}
+
+impl JsonPrinter {
+    pub fn print(&mut self, statements: &[Statement]) {
+        println!("{}", self.visit_statements(statements));
+    }
+
+    pub fn visit_statements(&mut self, statements: &[Statement]) -> Json {
+        Json::Array(statements.iter().map(|statement| self.visit_statement(statement)).collect())
+    }
+
+    fn node(&self, node_type: &str, span: &Span, mut fields: Vec<(&str, Json)>) -> Json {
+        fields.insert(0, ("type", Json::string(node_type)));
+        fields.insert(1, ("span", span_json(span)));
+        Json::object(fields)
+    }
+
+    fn operator(&mut self, e: &Expr) -> Json {
+        match &e.kind {
+            ExprKind::Operator(_token_type, lexeme) => Json::string(lexeme),
+            _ => self.visit_expr(e),
+        }
+    }
+}
+
+pub(crate) fn span_json(span: &Span) -> Json {
+    Json::object(vec![
+        ("line", Json::Number(span.line_number as f64)),
+        ("column", Json::Number(span.column_number as f64)),
+        ("end_line", Json::Number(span.end_line_number as f64)),
+        ("end_column", Json::Number(span.end_column_number as f64)),
+    ])
+}
+
+impl Visitor<Json> for JsonPrinter {
+    fn visit_expr(&mut self, e: &Expr) -> Json {
+        match &e.kind {
+            ExprKind::BoolLiteral(b) => self.node("BoolLiteral", &e.span, vec![("value", Json::Bool(*b))]),
+            ExprKind::NilLiteral => self.node("NilLiteral", &e.span, vec![]),
+            ExprKind::Spread(ref list) => {
+                let fields = vec![("list", self.visit_expr(list))];
+                self.node("Spread", &e.span, fields)
+            },
+            ExprKind::Tuple(values) => {
+                let fields = vec![("values", Json::Array(values.iter().map(|value| self.visit_expr(value)).collect()))];
+                self.node("Tuple", &e.span, fields)
+            },
+            ExprKind::StringLiteral(n) => self.node("StringLiteral", &e.span, vec![("value", Json::string(n))]),
+            ExprKind::IntegerLiteral(n) => self.node("IntegerLiteral", &e.span, vec![("value", Json::string(n))]),
+            ExprKind::FloatLiteral(n) => self.node("FloatLiteral", &e.span, vec![("value", Json::string(n))]),
+            ExprKind::Logical(ref lhs, token_type, ref rhs) => {
+                let fields = vec![
+                    ("operator", Json::string(&format!("{:?}", token_type).to_lowercase())),
+                    ("left", self.visit_expr(lhs)),
+                    ("right", self.visit_expr(rhs)),
+                ];
+                self.node("Logical", &e.span, fields)
+            },
+            ExprKind::Operator(_token_type, n) => self.node("Operator", &e.span, vec![("operator", Json::string(n))]),
+            ExprKind::Unary(ref operator, ref rhs) => {
+                let fields = vec![
+                    ("operator", self.operator(operator)),
+                    ("right", self.visit_expr(rhs)),
+                ];
+                self.node("Unary", &e.span, fields)
+            },
+            ExprKind::Binary(ref lhs, ref operator, ref rhs) => {
+                let fields = vec![
+                    ("operator", self.operator(operator)),
+                    ("left", self.visit_expr(lhs)),
+                    ("right", self.visit_expr(rhs)),
+                ];
+                self.node("Binary", &e.span, fields)
+            },
+            ExprKind::Grouping(ref expr) => {
+                let fields = vec![("expression", self.visit_expr(expr))];
+                self.node("Grouping", &e.span, fields)
+            },
+            ExprKind::Variable(token) => self.node("Variable", &e.span, vec![("name", Json::string(&token.lexeme))]),
+            ExprKind::Assign(token, ref expr) => {
+                let fields = vec![
+                    ("name", Json::string(&token.lexeme)),
+                    ("value", self.visit_expr(expr)),
+                ];
+                self.node("Assign", &e.span, fields)
+            },
+            ExprKind::Call(ref callee, _paren, arguments) => {
+                let fields = vec![
+                    ("callee", self.visit_expr(callee)),
+                    ("arguments", Json::Array(arguments.iter().map(|argument| self.visit_expr(argument)).collect())),
+                ];
+                self.node("Call", &e.span, fields)
+            },
+            ExprKind::List(elements) => {
+                let fields = vec![("elements", Json::Array(elements.iter().map(|element| self.visit_expr(element)).collect()))];
+                self.node("List", &e.span, fields)
+            },
+            ExprKind::Index(ref list, _bracket, ref index) => {
+                let fields = vec![
+                    ("list", self.visit_expr(list)),
+                    ("index", self.visit_expr(index)),
+                ];
+                self.node("Index", &e.span, fields)
+            },
+        }
+    }
+
+    fn visit_statement(&mut self, s: &Statement) -> Json {
+        match &s.kind {
+            StatementKind::Expression(ref expr) => {
+                let fields = vec![("expression", self.visit_expr(expr))];
+                self.node("Expression", &s.span, fields)
+            },
+            StatementKind::If(ref expr, ref then_statement, ref else_branch) => {
+                let else_json = match else_branch {
+                    Some(else_statement) => self.visit_statement(else_statement),
+                    None => Json::Null,
+                };
+                let fields = vec![
+                    ("condition", self.visit_expr(expr)),
+                    ("then", self.visit_statement(then_statement)),
+                    ("else", else_json),
+                ];
+                self.node("If", &s.span, fields)
+            },
+            StatementKind::Print(ref expr) => {
+                let fields = vec![("expression", self.visit_expr(expr))];
+                self.node("Print", &s.span, fields)
+            },
+            StatementKind::While(ref condition, ref body) => {
+                let fields = vec![
+                    ("condition", self.visit_expr(condition)),
+                    ("body", self.visit_statement(body)),
+                ];
+                self.node("While", &s.span, fields)
+            },
+            StatementKind::With(name, resource, body) => {
+                let fields = vec![
+                    ("name", Json::string(&name.lexeme)),
+                    ("resource", self.visit_expr(resource)),
+                    ("body", self.visit_statement(body)),
+                ];
+                self.node("With", &s.span, fields)
+            },
+            StatementKind::For(initializer, condition, increment, body) => {
+                let initializer_json = match initializer {
+                    Some(statement) => self.visit_statement(statement),
+                    None => Json::Null,
+                };
+                let condition_json = match condition {
+                    Some(expr) => self.visit_expr(expr),
+                    None => Json::Null,
+                };
+                let increment_json = match increment {
+                    Some(expr) => self.visit_expr(expr),
+                    None => Json::Null,
+                };
+                let fields = vec![
+                    ("initializer", initializer_json),
+                    ("condition", condition_json),
+                    ("increment", increment_json),
+                    ("body", self.visit_statement(body)),
+                ];
+                self.node("For", &s.span, fields)
+            },
+            StatementKind::Var(token, annotation, initializer) => {
+                let initializer_json = match initializer {
+                    Some(expr) => self.visit_expr(expr),
+                    None => Json::Null,
+                };
+                let fields = vec![
+                    ("name", Json::string(&token.lexeme)),
+                    ("annotation", annotation.as_ref().map_or(Json::Null, |annotation| Json::string(&annotation.lexeme))),
+                    ("initializer", initializer_json),
+                ];
+                self.node("Var", &s.span, fields)
+            },
+            StatementKind::VarTuple(names, initializer) => {
+                let fields = vec![
+                    ("names", Json::Array(names.iter().map(|name| Json::string(&name.lexeme)).collect())),
+                    ("initializer", self.visit_expr(initializer)),
+                ];
+                self.node("VarTuple", &s.span, fields)
+            },
+            StatementKind::AssignTuple(names, value) => {
+                let fields = vec![
+                    ("names", Json::Array(names.iter().map(|name| Json::string(&name.lexeme)).collect())),
+                    ("value", self.visit_expr(value)),
+                ];
+                self.node("AssignTuple", &s.span, fields)
+            },
+            StatementKind::Block(statements) => {
+                let fields = vec![("statements", self.visit_statements(statements))];
+                self.node("Block", &s.span, fields)
+            },
+            StatementKind::Function(declaration) => {
+                let parameters = declaration.parameters.iter()
+                    .map(|parameter| Json::object(vec![
+                        ("name", Json::string(&parameter.name.lexeme)),
+                        ("annotation", parameter.annotation.as_ref().map_or(Json::Null, |annotation| Json::string(&annotation.lexeme))),
+                    ]))
+                    .collect();
+                let fields = vec![
+                    ("name", Json::string(&declaration.name.lexeme)),
+                    ("parameters", Json::Array(parameters)),
+                    ("return_type", declaration.return_type.as_ref().map_or(Json::Null, |return_type| Json::string(&return_type.lexeme))),
+                    ("body", self.visit_statements(&declaration.body)),
+                    ("async", Json::Bool(declaration.is_async)),
+                    ("variadic", Json::Bool(declaration.variadic)),
+                    ("doc", declaration.doc().map_or(Json::Null, |doc| Json::string(&doc))),
+                    ("decorators", Json::Array(declaration.decorators.iter().map(|decorator| self.visit_expr(decorator)).collect())),
+                ];
+                self.node("Function", &s.span, fields)
+            },
+            StatementKind::Return(_keyword, value) => {
+                let fields = vec![("value", value.as_ref().map_or(Json::Null, |expr| self.visit_expr(expr)))];
+                self.node("Return", &s.span, fields)
+            },
+            StatementKind::Import(_keyword, name) => {
+                let fields = vec![("module", Json::string(&name.lexeme))];
+                self.node("Import", &s.span, fields)
+            },
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::runtime::build_interpreter;
+use crate::runtime::resolve::declarations;
+use crate::scanner::Scanner;
+use crate::scanner::token::Token;
+use crate::scanner::token::TokenType;
+
+// The program on one line with as little whitespace as still scans the same: no comments, and
+// a space only between tokens that would otherwise run together. `tokens` are the program's raw
+// tokens (as from scan_tokens) and `statements` what they parse into.
+//
+// With `rename_locals` the parameters and variables of functions and blocks get the shortest
+// names nothing else uses. Globals and natives keep theirs, and so does everything in a program
+// that imports modules, whose globals can't be known from here. Messages and help() show the
+// new names.
+pub fn minify(tokens: &[Token], statements: &[Statement], rename_locals: bool) -> String {
+    let renames = if rename_locals && !imports(statements) { renames(tokens, statements) } else { HashMap::new() };
+
+    let mut out = String::new();
+    let mut previous: Option<&str> = None;
+    for token in tokens.iter().filter(|token| !token.token_type.is_trivia() && token.token_type != TokenType::Eof) {
+        let lexeme = renames.get(&(token.line_number, token.column_number)).unwrap_or(&token.lexeme);
+        if let Some(previous) = previous {
+            if runs_together(previous, lexeme) {
+                out.push(' ');
+            }
+        }
+        out.push_str(lexeme);
+        previous = Some(lexeme);
+    }
+    out.push('\n');
+    out
+}
+
+// whether the two tokens written next to each other scan as something else
+fn runs_together(left: &str, right: &str) -> bool {
+    let joined = format!("{}{}", left, right);
+    let mut scanned = Scanner::new(&joined);
+    !matches!((scanned.next(), scanned.next()), (Some(first), Some(second)) if first.lexeme == left && second.lexeme == right)
+}
+
+fn imports(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StatementKind::Import(..) => true,
+        StatementKind::Block(statements) => imports(statements),
+        StatementKind::If(_, then_branch, else_branch) => imports(std::slice::from_ref(then_branch)) || else_branch.as_ref().is_some_and(|branch| imports(std::slice::from_ref(branch))),
+        StatementKind::While(_, body) | StatementKind::With(_, _, body) | StatementKind::For(_, _, _, body) => imports(std::slice::from_ref(body)),
+        StatementKind::Function(declaration) => imports(&declaration.body),
+        _ => false,
+    })
+}
+
+// The new name of every local, by where the name is in the source. Scopes are the ones the
+// interpreter's resolver sees: everything a function declares shares the function's names, and
+// so does everything in a block, loop or `with` at the top of the program.
+fn renames(tokens: &[Token], statements: &[Statement]) -> HashMap<(u32, u32), String> {
+    let natives: HashSet<String> = build_interpreter().globals().iter().map(|(name, _)| name.clone()).collect();
+    let mut fixed = natives.clone();
+    for statement in statements.iter() {
+        globals(statement, &mut fixed);
+    }
+    let mut taken = natives;
+    taken.extend(tokens.iter().filter(|token| token.token_type == TokenType::Identifier).map(|token| token.lexeme.to_string()));
+
+    let mut renamer = Renamer{fixed, taken, renames: HashMap::new()};
+    for statement in statements.iter() {
+        renamer.top(statement);
+    }
+    renamer.renames
+}
+
+// what the statement declares outside every scope
+fn globals(statement: &Statement, names: &mut HashSet<String>) {
+    match &statement.kind {
+        StatementKind::Var(name, _, _) => {
+            names.insert(name.lexeme.to_string());
+        },
+        StatementKind::VarTuple(tokens, _) => names.extend(tokens.iter().map(|name| name.lexeme.to_string())),
+        StatementKind::Function(declaration) => {
+            names.insert(declaration.name.lexeme.to_string());
+        },
+        StatementKind::If(_, then_branch, else_branch) => {
+            globals(then_branch, names);
+            if let Some(else_branch) = else_branch {
+                globals(else_branch, names);
+            }
+        },
+        StatementKind::While(_, body) => globals(body, names),
+        _ => {},
+    }
+}
+
+struct Renamer {
+    // globals and natives, which stay as they are
+    fixed: HashSet<String>,
+    // names new ones mustn't be: every identifier in the program and every native
+    taken: HashSet<String>,
+    renames: HashMap<(u32, u32), String>,
+}
+
+type Names = HashMap<String, String>;
+
+impl Renamer {
+    fn top(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Block(_) | StatementKind::For(..) | StatementKind::With(..) => {
+                let mut declared = HashSet::new();
+                declarations(statement, &mut declared);
+                let names = self.names(declared);
+                self.statement(statement, &names);
+            },
+            StatementKind::If(_, then_branch, else_branch) => {
+                self.top(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.top(else_branch);
+                }
+            },
+            StatementKind::While(_, body) => self.top(body),
+            _ => self.statement(statement, &Names::new()),
+        }
+    }
+
+    // the shortest names that are free, in order, for the ones that can change
+    fn names(&self, declared: HashSet<String>) -> Names {
+        let mut declared: Vec<String> = declared.into_iter().filter(|name| !self.fixed.contains(name)).collect();
+        declared.sort();
+        let mut candidates = (0..).map(short_name).filter(|name| !self.taken.contains(name) && is_identifier(name));
+        declared.into_iter().filter_map(|name| candidates.next().map(|short| (name, short))).collect()
+    }
+
+    fn rename(&mut self, token: &Token, names: &Names) {
+        if let Some(name) = names.get(&token.lexeme) {
+            self.renames.insert((token.line_number, token.column_number), name.clone());
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, names: &Names) {
+        match &statement.kind {
+            StatementKind::Expression(value) | StatementKind::Print(value) => self.expr(value, names),
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expr(condition, names);
+                self.statement(then_branch, names);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch, names);
+                }
+            },
+            StatementKind::Var(name, _, value) => {
+                self.rename(name, names);
+                if let Some(value) = value {
+                    self.expr(value, names);
+                }
+            },
+            StatementKind::Return(_, value) => {
+                if let Some(value) = value {
+                    self.expr(value, names);
+                }
+            },
+            StatementKind::VarTuple(tokens, value) | StatementKind::AssignTuple(tokens, value) => {
+                tokens.iter().for_each(|name| self.rename(name, names));
+                self.expr(value, names);
+            },
+            StatementKind::While(condition, body) => {
+                self.expr(condition, names);
+                self.statement(body, names);
+            },
+            StatementKind::With(name, resource, body) => {
+                self.rename(name, names);
+                self.expr(resource, names);
+                self.statement(body, names);
+            },
+            StatementKind::For(initializer, condition, increment, body) => {
+                if let Some(initializer) = initializer {
+                    self.statement(initializer, names);
+                }
+                if let Some(condition) = condition {
+                    self.expr(condition, names);
+                }
+                if let Some(increment) = increment {
+                    self.expr(increment, names);
+                }
+                self.statement(body, names);
+            },
+            StatementKind::Block(statements) => statements.iter().for_each(|statement| self.statement(statement, names)),
+            StatementKind::Function(declaration) => {
+                self.rename(&declaration.name, names);
+                declaration.decorators.iter().for_each(|decorator| self.expr(decorator, names));
+                // the body only sees its own names
+                let mut declared: HashSet<String> = declaration.parameters.iter().map(|parameter| parameter.name.lexeme.to_string()).collect();
+                declaration.body.iter().for_each(|statement| declarations(statement, &mut declared));
+                let body_names = self.names(declared);
+                declaration.parameters.iter().for_each(|parameter| self.rename(&parameter.name, &body_names));
+                declaration.body.iter().for_each(|statement| self.statement(statement, &body_names));
+            },
+            StatementKind::Import(..) => {},
+        }
+    }
+
+    fn expr(&mut self, e: &Expr, names: &Names) {
+        match &e.kind {
+            ExprKind::Variable(name) => self.rename(name, names),
+            ExprKind::Assign(name, value) => {
+                self.rename(name, names);
+                self.expr(value, names);
+            },
+            ExprKind::Grouping(inner) | ExprKind::Unary(_, inner) | ExprKind::Spread(inner) => self.expr(inner, names),
+            ExprKind::Binary(lhs, _, rhs) | ExprKind::Logical(lhs, _, rhs) | ExprKind::Index(lhs, _, rhs) => {
+                self.expr(lhs, names);
+                self.expr(rhs, names);
+            },
+            ExprKind::Call(callee, _, arguments) => {
+                self.expr(callee, names);
+                arguments.iter().for_each(|argument| self.expr(argument, names));
+            },
+            ExprKind::List(elements) | ExprKind::Tuple(elements) => elements.iter().for_each(|element| self.expr(element, names)),
+            ExprKind::Operator(..) | ExprKind::BoolLiteral(_) | ExprKind::NilLiteral | ExprKind::StringLiteral(_)
+                | ExprKind::IntegerLiteral(_) | ExprKind::FloatLiteral(_) => {},
+        }
+    }
+}
+
+// a, b, ... z, aa, ab, ...
+fn short_name(index: usize) -> String {
+    let mut index = index + 1;
+    let mut name = Vec::new();
+    while index > 0 {
+        index -= 1;
+        name.push(b'a' + (index % 26) as u8);
+        index /= 26;
+    }
+    name.iter().rev().map(|&letter| letter as char).collect()
+}
+
+// not a keyword
+fn is_identifier(name: &str) -> bool {
+    matches!(Scanner::new(name).next(), Some(token) if token.token_type == TokenType::Identifier)
+}
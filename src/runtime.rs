@@ -1,11 +1,146 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use crate::diagnostic::Code;
 use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::FunctionDeclaration;
 use crate::parser::Statement;
+use crate::parser::StatementKind;
 use crate::parser::Visitor;
+use crate::printer::AstPrinter;
+use crate::scanner::token::Span;
 use crate::scanner::token::TokenType;
 
+mod builder;
+mod clock;
+mod convert;
+mod coroutine;
+mod files;
+mod format;
+mod globals;
+mod key;
+mod modules;
+mod natives;
+pub(crate) mod resolve;
+mod tasks;
+mod trace;
+
+pub use builder::Config;
+pub use builder::DEFAULT_RECURSION_DEPTH;
+pub use builder::MAX_FLOAT_PRECISION;
+pub use builder::STACK_PER_LEVEL;
+pub use builder::stack_size;
+pub use builder::InterpreterBuilder;
+pub use builder::OverflowPolicy;
+pub use builder::CoercionPolicy;
+pub use coroutine::Coroutine;
+pub use coroutine::Status as CoroutineStatus;
+pub use files::File;
+pub use modules::ModuleCache;
+pub use tasks::Task;
+use clock::Clock;
+use coroutine::Resumer;
+pub use globals::Globals;
+use modules::LoadError;
+use modules::Modules;
+use modules::Running;
+use tasks::EventLoop;
+use trace::Tracer;
+
+// as deep as blocks usually nest, past that ended scopes are freed
+const MAX_SPARE_SCOPES: usize = 32;
+
+#[derive(Debug, Clone)]
 pub struct RuntimeError {
+    pub code: Code,
     pub message: String,
+    // innermost expression or statement that failed, filled in as the error leaves the evaluator
+    pub span: Option<Span>,
+    // set when the program ran out of a budgeted resource rather than doing something wrong
+    pub exhausted: Option<Resource>,
+    // set by exit(), which ends the program with this status rather than failing it
+    pub exit: Option<i32>,
+    // more about what happened, shown under the message
+    pub notes: Vec<String>,
+}
+
+impl RuntimeError {
+    pub fn new(message: String) -> RuntimeError {
+        RuntimeError::with_code(Code::RuntimeError, message)
+    }
+
+    pub fn with_code(code: Code, message: String) -> RuntimeError {
+        RuntimeError{code, message, span: None, exhausted: None, exit: None, notes: Vec::new()}
+    }
+
+    pub fn exhausted(resource: Resource, message: String) -> RuntimeError {
+        RuntimeError{code: Code::exhausted(resource), message, span: None, exhausted: Some(resource), exit: None, notes: Vec::new()}
+    }
+
+    pub fn exit(status: i32) -> RuntimeError {
+        RuntimeError{code: Code::Exited, message: format!("exited with status {}", status), span: None, exhausted: None, exit: Some(status), notes: Vec::new()}
+    }
+
+    pub fn with_note(mut self, note: String) -> RuntimeError {
+        self.notes.push(note);
+        self
+    }
+
+    fn at(mut self, span: Span) -> RuntimeError {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    // The location is in another file than the one being reported on, so it moves into the
+    // message and the error gets the location of whatever led into the module instead.
+    fn in_module(mut self, module: &Path) -> RuntimeError {
+        self.message = match self.span.take() {
+            Some(span) => format!("{} at {}:{}:{}", self.message, module.display(), span.line_number, span.column_number + 1),
+            None => format!("{} in {}", self.message, module.display()),
+        };
+        self
+    }
+}
+
+// What an untrusted script can be limited on, see Config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resource {
+    Statements,
+    Expressions,
+    // bytes of string (and later collection) values created
+    Memory,
+    // bytes written by print
+    Output,
+    LoopIterations,
+    // statements plus expressions evaluated within a single interpret call
+    Steps,
+}
+
+// what the evaluator has used up so far, across every interpret call
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub statements: u64,
+    pub expressions: u64,
+    pub memory: u64,
+    pub output: u64,
+}
+
+impl Usage {
+    // a step is one statement or expression evaluated
+    pub fn steps(&self) -> u64 {
+        self.statements + self.expressions
+    }
 }
 
 #[derive(Debug)]
@@ -18,60 +153,335 @@ pub enum Operator {
     GreaterEqual,
     Less,
     LessEqual,
+    In,
+    Is,
     Add,
     Subtract,
     Divide,
-    Multiply
+    FloorDivide,
+    Modulo,
+    Multiply,
+    Await,
 }
 
-#[derive(Debug, Clone)]
+// Strings and lists are shared rather than copied, so reading a variable or passing one to a
+// function is cheap however long it is. Neither changes once made: + and the natives build new
+// ones.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Object {
     Nil(),
     Float(f64),
     Integer(i64),
     Boolean(bool),
-    StringLiteral(String),
+    StringLiteral(Arc<str>),
+    List(Arc<Vec<Object>>),
+    // host code can't be written out, snapshots holding natives fail to serialize
+    #[cfg_attr(feature = "serde", serde(skip))]
+    NativeFunction(NativeFunction),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Function(Function),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Coroutine(Coroutine),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Task(Task),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    File(File),
 }
 
-pub fn build_interpreter() -> ExprEvaluator {
-    let mut environments = Vec::new();
-    environments.push(Environment{
-        values: HashMap::new()
-    });
+impl Object {
+    // what type() returns
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Nil() => "nil",
+            Object::Float(_) => "float",
+            Object::Integer(_) => "integer",
+            Object::Boolean(_) => "boolean",
+            Object::StringLiteral(_) => "string",
+            Object::List(_) => "list",
+            Object::NativeFunction(_) | Object::Function(_) => "function",
+            Object::Coroutine(_) => "coroutine",
+            Object::Task(_) => "task",
+            Object::File(_) => "file",
+        }
+    }
+}
+
+// What print, str() and format() show. A float always has a fractional part, so 2.0 prints as
+// 2.0 and not as the integer 2; NaN and the infinities print as NaN, inf and -inf. A precision
+// (`{:.2}`) gives every float in the value that many digits after the point.
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Nil() => write!(f, "nil"),
+            Object::Float(float) => match f.precision() {
+                Some(precision) => write!(f, "{:.*}", precision, float),
+                None if float.is_finite() && float.fract() == 0.0 => write!(f, "{:.1}", float),
+                None => write!(f, "{}", float),
+            },
+            Object::Integer(integer) => write!(f, "{}", integer),
+            Object::Boolean(boolean) => write!(f, "{}", boolean),
+            Object::StringLiteral(string) => write!(f, "{}", string),
+            Object::List(values) => match f.precision() {
+                Some(precision) => write_list(f, values, |f, value| write!(f, "{:.*}", precision, value)),
+                None => write_list(f, values, |f, value| write!(f, "{}", value)),
+            },
+            Object::NativeFunction(native) => write!(f, "{:?}", native),
+            Object::Function(function) => write!(f, "{:?}", function),
+            Object::Coroutine(coroutine) => write!(f, "{:?}", coroutine),
+            Object::Task(task) => write!(f, "{:?}", task),
+            Object::File(file) => write!(f, "{:?}", file),
+        }
+    }
+}
+
+// What error messages show: the same, but with strings quoted so "1" and 1 tell apart.
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::StringLiteral(string) => write!(f, "{:?}", string),
+            Object::List(values) => write_list(f, values, |f, value| write!(f, "{:?}", value)),
+            other => write!(f, "{}", other),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, values: &[Object], write_value: impl Fn(&mut fmt::Formatter<'_>, &Object) -> fmt::Result) -> fmt::Result {
+    write!(f, "[")?;
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write_value(f, value)?;
+    }
+    write!(f, "]")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Fixed(usize),
+    // any number of arguments, the function checks them itself
+    Variadic,
+}
+
+// shared and thread safe so values holding natives can be cloned and sent to other threads
+pub type NativeFn = Arc<dyn Fn(&mut ExprEvaluator, &[Object]) -> Result<Object, RuntimeError> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    pub function: NativeFn,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+// Declared with `fun`. Functions see the globals and their own parameters and locals, not the
+// locals of the blocks they were declared in.
+#[derive(Clone)]
+pub struct Function {
+    pub declaration: Arc<FunctionDeclaration>,
+    // the imported file it was declared in, None for the program itself
+    pub module: Option<Arc<Path>>,
+}
+
+impl Function {
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    // the docstring, see FunctionDeclaration::doc
+    pub fn doc(&self) -> Option<String> {
+        self.declaration.doc()
+    }
+
+    fn check_arity(&self, count: usize) -> Result<(), RuntimeError> {
+        let parameters = self.declaration.parameters.len();
+        if self.declaration.variadic && count + 1 < parameters {
+            return Err(RuntimeError::with_code(Code::WrongArity, format!("Expected at least {} arguments but got {}", parameters - 1, count)));
+        }
+        if !self.declaration.variadic && count != parameters {
+            return Err(RuntimeError::with_code(Code::WrongArity, format!("Expected {} arguments but got {}", parameters, count)));
+        }
+        Ok(())
+    }
+}
 
-    ExprEvaluator{
-        environments
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
     }
 }
 
+pub fn build_interpreter() -> ExprEvaluator {
+    InterpreterBuilder::new().build()
+}
+
+// Every variable read hashes its name, so with the fxhash feature they use FxHash, several times
+// faster on short names than the standard library's SipHash. It gives up SipHash's protection
+// against keys picked to collide, which only matters for names an attacker chooses.
+#[cfg(feature = "fxhash")]
+pub type Variables = HashMap<String, Object, rustc_hash::FxBuildHasher>;
+#[cfg(not(feature = "fxhash"))]
+pub type Variables = HashMap<String, Object>;
+
 pub struct Environment {
-    pub values: HashMap<String, Object>,
+    pub values: Variables,
 }
 
 impl Environment {
 }
 
+// Called before every statement executes, e.g. to stop on breakpoints. Returning an error
+// aborts the program with that error.
+pub trait StatementHook: Send {
+    fn before_statement(&mut self, evaluator: &mut ExprEvaluator, statement: &Statement) -> Result<(), RuntimeError>;
+}
+
 pub struct ExprEvaluator {
+    globals: Globals,
+    // the scopes of the blocks and the call being run, innermost last
     environments: Vec<Environment>,
+    // tables of scopes that have ended, emptied for the next scope to reuse so a loop whose body
+    // is a block doesn't allocate one every time round
+    spare_scopes: Vec<Variables>,
+    script_args: Vec<String>,
+    output: Box<dyn Write + Send>,
+    error_output: Box<dyn Write + Send>,
+    // where input() reads lines from, stdin when None
+    input: Option<Box<dyn BufRead + Send>>,
+    hook: Option<Box<dyn StatementHook>>,
+    config: Config,
+    // calls currently being evaluated
+    call_depth: usize,
+    // statements and expressions currently being evaluated, each one a level of Rust recursion
+    depth: usize,
+    usage: Usage,
+    // steps already taken when the current interpret call started
+    call_start: u64,
+    // raised from outside (e.g. on Ctrl-C) to stop the program at its next statement
+    interrupt: Option<Arc<AtomicBool>>,
+    tracer: Option<Tracer>,
+    // set by `return` until the function it returns from hands the value back
+    returning: Option<Object>,
+    // the call a `return` ends with, left for the function it returns from to make
+    tail_call: Option<(Function, Vec<Object>)>,
+    clock: Clock,
+    modules: Modules,
+    // the coroutines being run, innermost last
+    resumers: Vec<Resumer>,
+    event_loop: EventLoop,
 }
 
 impl ExprEvaluator {
-    pub fn interpret(&mut self, statements: &Vec<Statement>) {
+    // where print writes to, stdout unless replaced
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output = output;
+    }
+
+    // where diagnostics go, stderr unless replaced
+    pub fn set_error_output(&mut self, error_output: Box<dyn Write + Send>) {
+        self.error_output = error_output;
+    }
+
+    // where input() reads from, stdin unless replaced
+    pub fn set_input(&mut self, input: Box<dyn BufRead + Send>) {
+        self.input = Some(input);
+    }
+
+    pub fn error_output(&mut self) -> &mut dyn Write {
+        &mut *self.error_output
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) {
+        self.interrupt = Some(interrupt);
+    }
+
+    // logs every statement and expression with its location and value, see --trace
+    pub fn set_trace(&mut self, output: Box<dyn Write + Send>) {
+        self.tracer = Some(Tracer::new(output));
+    }
+
+    // None lifts the limit
+    pub fn set_step_limit(&mut self, steps: Option<u64>) {
+        self.config.max_steps_per_call = steps;
+    }
+
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    pub fn set_hook(&mut self, hook: Box<dyn StatementHook>) {
+        self.hook = Some(hook);
+    }
+
+    // the scopes inside the globals, innermost last
+    pub fn environments(&self) -> &[Environment] {
+        &self.environments
+    }
+
+    pub fn globals(&self) -> &Globals {
+        &self.globals
+    }
+
+    // the file being run, relative imports are resolved from its directory
+    pub fn set_script(&mut self, path: PathBuf) {
+        self.modules.script = Some(path);
+    }
+
+    // arguments visible to the script through argc()/argv(), argv(0) is the script itself
+    pub fn set_script_args(&mut self, args: Vec<String>) {
+        self.script_args = args;
+    }
+
+    pub fn define_native<F>(&mut self, name: &str, arity: Arity, function: F)
+    where F: Fn(&mut ExprEvaluator, &[Object]) -> Result<Object, RuntimeError> + Send + Sync + 'static {
+        let native = NativeFunction{
+            name: name.to_string(),
+            arity,
+            function: Arc::new(function),
+        };
+        self.define_variable(name.to_string(), Object::NativeFunction(native));
+    }
+
+    // the value of the last statement, so embedders can evaluate snippets like `1 + 2;`
+    pub fn interpret(&mut self, statements: &[Statement]) -> Result<Object, RuntimeError> {
+        // statements run from inside a scope (by a debugger, say) are left to look everything up
+        if self.environments.is_empty() {
+            resolve::resolve(statements);
+        }
+        self.call_start = self.usage.steps();
+        let mut last_value = Object::Nil();
         for statement in statements.iter() {
-            let result = self.visit_statement(statement);
-            match result {
-                Ok(_object) => {},
-                Err(RuntimeError{message}) => {
-                    println!("Error evaluating: {}", message);
-                }
+            last_value = self.execute(statement)?;
+        }
+        Ok(last_value)
+    }
+
+    fn push_scope(&mut self) {
+        let values = self.spare_scopes.pop().unwrap_or_default();
+        self.environments.push(Environment{values});
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(mut environment) = self.environments.pop() {
+            if self.spare_scopes.len() < MAX_SPARE_SCOPES {
+                environment.values.clear();
+                self.spare_scopes.push(environment.values);
             }
         }
     }
 
-    fn execute_block(&mut self, statements: &Vec<Statement>) -> Result<Object, RuntimeError> {
-        self.environments.push(Environment{
-            values: HashMap::new()
-        });
+    fn execute_block(&mut self, statements: &[Statement]) -> Result<Object, RuntimeError> {
+        self.push_scope();
 
         let mut last_value = Object::Nil();
 
@@ -79,253 +489,844 @@ impl ExprEvaluator {
             match self.execute(statement) {
                 Ok(object) => last_value = object,
                 error => {
-                    self.environments.pop();
+                    self.pop_scope();
                     return error;
                 }
             }
+            if self.returning.is_some() {
+                break;
+            }
         }
 
-        self.environments.pop();
+        self.pop_scope();
         Ok(last_value)
     }
 
+    fn execute_for(&mut self, initializer: &Option<Box<Statement>>, condition: &Option<Box<Expr>>, increment: &Option<Box<Expr>>, body: &Statement) -> Result<Object, RuntimeError> {
+        if let Some(initializer) = initializer {
+            self.execute(initializer)?;
+        }
+
+        let mut iterations = 0;
+        loop {
+            if let Some(condition) = condition {
+                let value = self.evaluate(condition)?;
+                if !self.is_truthy(&value) {
+                    break;
+                }
+            }
+
+            self.count_iteration(&mut iterations)?;
+            self.execute(body)?;
+            if self.returning.is_some() {
+                break;
+            }
+
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+        Ok(Object::Nil())
+    }
+
     fn execute(&mut self, statement: &Statement) -> Result<Object, RuntimeError> {
-        self.visit_statement(statement)
+        if let Some(interrupt) = &self.interrupt {
+            if interrupt.swap(false, Ordering::SeqCst) {
+                return Err(RuntimeError::with_code(Code::Interrupted, "Interrupted".to_string()).at(statement.span));
+            }
+        }
+        self.spend(Resource::Statements, 1).map_err(|error| error.at(statement.span))?;
+        // the hook is taken out while it runs so it can be handed the evaluator itself
+        if let Some(mut hook) = self.hook.take() {
+            let result = hook.before_statement(self, statement);
+            self.hook = Some(hook);
+            result.map_err(|error| error.at(statement.span))?;
+        }
+        if self.tracer.is_none() {
+            return self.execute_nested(statement);
+        }
+
+        self.trace_enter();
+        let result = self.execute_nested(statement);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.leave(&trace::describe_statement(statement), statement.span, &result);
+        }
+        result
+    }
+
+    fn execute_nested(&mut self, statement: &Statement) -> Result<Object, RuntimeError> {
+        self.descend().map_err(|error| error.at(statement.span))?;
+        let result = self.visit_statement(statement).map_err(|error| error.at(statement.span));
+        self.depth -= 1;
+        result
+    }
+
+    fn evaluate_nested(&mut self, e: &Expr) -> Result<Object, RuntimeError> {
+        self.descend().map_err(|error| error.at(e.span))?;
+        let result = self.evaluate_kind(e).map_err(|error| error.at(e.span));
+        self.depth -= 1;
+        result
+    }
+
+    // Every nested statement or expression is another evaluator frame on the Rust stack, so
+    // the program fails with an error here instead of the process aborting on a real overflow.
+    fn descend(&mut self) -> Result<(), RuntimeError> {
+        if let Some(max) = self.config.max_recursion_depth {
+            if self.depth >= max {
+                return Err(RuntimeError::with_code(Code::StackOverflow, format!("Stack overflow: exceeded the maximum recursion depth of {}", max)));
+            }
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn trace_enter(&mut self) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.enter();
+        }
     }
 
     fn evaluate(&mut self, expr: &Expr) -> Result<Object, RuntimeError> {
         self.visit_expr(expr)
     }
 
+    // a call's arguments or a list's elements, with spread lists flattened into them
+    fn evaluate_elements(&mut self, elements: &[Expr]) -> Result<Vec<Object>, RuntimeError> {
+        let mut values = Vec::new();
+        for element in elements {
+            match &element.kind {
+                ExprKind::Spread(list) => match self.evaluate(list)? {
+                    Object::List(list) => values.extend(list.iter().cloned()),
+                    other => return Err(RuntimeError::with_code(Code::InvalidOperand, format!("Can only spread a list, received {:?}", other)).at(element.span)),
+                },
+                _ => values.push(self.evaluate(element)?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn is_truthy(&self, value: &Object) -> bool {
+        if self.config.lox_compat {
+            lox_truthy(value)
+        } else {
+            is_truthy(value)
+        }
+    }
+
+    fn count_iteration(&self, iterations: &mut u64) -> Result<(), RuntimeError> {
+        *iterations += 1;
+        match self.config.max_loop_iterations {
+            Some(max) if *iterations > max => Err(RuntimeError::exhausted(Resource::LoopIterations, format!("Loop exceeded the maximum of {} iterations", max))),
+            _ => Ok(()),
+        }
+    }
+
+    // fails when spending that much would go past the configured limit, without spending it:
+    // for what is about to be allocated and gets charged once it has been
+    fn afford(&self, resource: Resource, amount: u64) -> Result<(), RuntimeError> {
+        let (used, limit, unit) = match resource {
+            Resource::Memory => (self.usage.memory, self.config.max_memory, "bytes of memory"),
+            Resource::Output => (self.usage.output, self.config.max_output_bytes, "bytes of output"),
+            _ => return Ok(()),
+        };
+        match limit {
+            Some(limit) if used.saturating_add(amount) > limit => Err(RuntimeError::exhausted(resource, format!("Exceeded the budget of {} {}", limit, unit))),
+            _ => Ok(()),
+        }
+    }
+
+    // charges the budget, failing once more than the configured limit has been used
+    fn spend(&mut self, resource: Resource, amount: u64) -> Result<(), RuntimeError> {
+        let (used, limit, unit) = match resource {
+            Resource::Statements => (&mut self.usage.statements, self.config.max_statements, "statements"),
+            Resource::Expressions => (&mut self.usage.expressions, self.config.max_expressions, "expressions"),
+            Resource::Memory => (&mut self.usage.memory, self.config.max_memory, "bytes of memory"),
+            Resource::Output => (&mut self.usage.output, self.config.max_output_bytes, "bytes of output"),
+            Resource::LoopIterations | Resource::Steps => return Ok(()),
+        };
+        *used += amount;
+        if let Some(limit) = limit {
+            if *used > limit {
+                return Err(RuntimeError::exhausted(resource, format!("Exceeded the budget of {} {}", limit, unit)));
+            }
+        }
+
+        if let (Resource::Statements | Resource::Expressions, Some(limit)) = (resource, self.config.max_steps_per_call) {
+            if self.usage.steps() - self.call_start > limit {
+                return Err(RuntimeError::exhausted(Resource::Steps, format!("Exceeded the limit of {} steps per call", limit)));
+            }
+        }
+        Ok(())
+    }
+
+    fn integer_arithmetic(&self, name: &str, lval: i64, rval: i64, checked: fn(i64, i64) -> Option<i64>, wrapping: fn(i64, i64) -> i64, saturating: fn(i64, i64) -> i64) -> Result<Object, RuntimeError> {
+        match self.config.overflow {
+            OverflowPolicy::Error => checked(lval, rval)
+                .map(Object::Integer)
+                .ok_or_else(|| RuntimeError::with_code(Code::IntegerOverflow, format!("Integer overflow trying to {} {} and {}", name, lval, rval))),
+            OverflowPolicy::Wrap => Ok(Object::Integer(wrapping(lval, rval))),
+            OverflowPolicy::Saturate => Ok(Object::Integer(saturating(lval, rval))),
+        }
+    }
+
+    // The quotient rounded down and what's left over, which takes the sign of the divisor:
+    // -7 ~/ 2 is -4 and leaves 1. Integers stay integers, anything with a float gives floats,
+    // and dividing those by zero gives infinities and NaN rather than an error.
+    pub(crate) fn divmod(&self, lhs: Object, rhs: Object) -> Result<(Object, Object), RuntimeError> {
+        let (lval, rval) = match (lhs, rhs) {
+            (Object::Integer(_), Object::Integer(0)) => return Err(division_by_zero()),
+            (Object::Integer(lval), Object::Integer(rval)) => {
+                let quotient = self.integer_arithmetic("divide", lval, rval, floor_div, |lval, rval| floor_div(lval, rval).unwrap_or(lval), |lval, rval| floor_div(lval, rval).unwrap_or(i64::MAX))?;
+                let remainder = lval.wrapping_rem(rval);
+                let remainder = if remainder != 0 && (remainder < 0) != (rval < 0) { remainder + rval } else { remainder };
+                return Ok((quotient, Object::Integer(remainder)));
+            },
+            (Object::Float(lval), Object::Float(rval)) => (lval, rval),
+            (Object::Integer(lval), Object::Float(rval)) => (lval as f64, rval),
+            (Object::Float(lval), Object::Integer(rval)) => (lval, rval as f64),
+            (lval, rval) => return Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot divide", lval, rval))),
+        };
+        let remainder = lval % rval;
+        let remainder = if remainder != 0.0 && (remainder < 0.0) != (rval < 0.0) { remainder + rval } else { remainder };
+        Ok((Object::Float((lval / rval).floor()), Object::Float(remainder)))
+    }
+
+    // the string `count` times over, "" for none
+    fn repeat(&mut self, string: &str, count: i64) -> Result<Object, RuntimeError> {
+        if count < 0 {
+            return Err(RuntimeError::with_code(Code::InvalidOperand, format!("Cannot repeat a string {} times", count)));
+        }
+        let size = (string.len() as u64).checked_mul(count as u64)
+            .filter(|size| *size <= isize::MAX as u64)
+            .ok_or_else(|| RuntimeError::with_code(Code::InvalidOperand, format!("Repeating a string of {} bytes {} times is too long", string.len(), count)))?;
+        self.spend(Resource::Memory, size)?;
+        Ok(Object::StringLiteral(string.repeat(count as usize).into()))
+    }
+
+    // the value as print writes it, floats at the configured precision
+    pub fn display(&self, value: &Object) -> String {
+        match self.config.float_precision {
+            Some(precision) => format!("{:.*}", precision, value),
+            None => value.to_string(),
+        }
+    }
+
+    fn enter_call(&mut self, name: &str) -> Result<(), RuntimeError> {
+        if let Some(max) = self.config.max_call_depth {
+            if self.call_depth >= max {
+                return Err(RuntimeError::with_code(Code::CallDepthExceeded, format!("Maximum call depth of {} exceeded calling {}", max, name)));
+            }
+        }
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    fn call_native(&mut self, native: &NativeFunction, arguments: &[Object]) -> Result<Object, RuntimeError> {
+        self.enter_call(&native.name)?;
+        let result = (native.function)(self, arguments);
+        self.call_depth -= 1;
+
+        // whatever a native hands back was allocated on the script's behalf
+        if let Ok(value) = &result {
+            self.spend(Resource::Memory, allocated(value))?;
+        }
+        result
+    }
+
+    // The body runs with only the globals around it: the caller's locals are set aside until
+    // it returns. A function returning a call to another (or itself) is replaced by that call,
+    // which runs next in the same frame, so tail recursion doesn't grow the stack.
+    fn call_function(&mut self, function: &Function, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+        self.enter_call(function.name())?;
+        let caller = mem::take(&mut self.environments);
+
+        let mut function = function.clone();
+        let mut arguments = arguments;
+        let result = loop {
+            let result = self.run_body(&function, arguments);
+            match self.tail_call.take() {
+                Some((next, next_arguments)) if result.is_ok() => {
+                    self.returning = None;
+                    function = next;
+                    arguments = next_arguments;
+                },
+                _ => break match &function.module {
+                    Some(module) => result.map_err(|error| error.in_module(module)),
+                    None => result,
+                },
+            }
+        };
+
+        self.environments = caller;
+        self.call_depth -= 1;
+        let value = self.returning.take().unwrap_or(Object::Nil());
+        result.map(|_| value)
+    }
+
+    fn run_body(&mut self, function: &Function, arguments: Vec<Object>) -> Result<(), RuntimeError> {
+        let declaration = &function.declaration;
+        let mut parameters = self.spare_scopes.pop().unwrap_or_default();
+        let mut arguments = arguments.into_iter();
+        for (index, parameter) in declaration.parameters.iter().enumerate() {
+            let argument = if declaration.variadic && index + 1 == declaration.parameters.len() {
+                Object::List(Arc::new(arguments.by_ref().collect()))
+            } else {
+                arguments.next().unwrap_or(Object::Nil())
+            };
+            parameters.insert(parameter.name.lexeme.to_string(), argument);
+        }
+        self.environments.push(Environment{
+            values: parameters
+        });
+
+        let mut result = Ok(());
+        for statement in declaration.body.iter() {
+            if let Err(error) = self.execute(statement) {
+                result = Err(error);
+                break;
+            }
+            if self.returning.is_some() {
+                break;
+            }
+        }
+        while !self.environments.is_empty() {
+            self.pop_scope();
+        }
+        result
+    }
+
+    // What `return` gives back. A call to a script function is only evaluated up to its
+    // arguments, call_function makes it once the returning function is gone.
+    fn return_value(&mut self, expr: &Expr) -> Result<Object, RuntimeError> {
+        let (callee, arguments) = match &expr.kind {
+            ExprKind::Call(callee, _paren, arguments) => (callee, arguments),
+            _ => return self.visit_expr(expr),
+        };
+        self.spend(Resource::Expressions, 1).map_err(|error| error.at(expr.span))?;
+        let callee = self.evaluate(callee)?;
+        let values = self.evaluate_elements(arguments)?;
+
+        match callee {
+            Object::Function(function) if !function.declaration.is_async && function.check_arity(values.len()).is_ok() => {
+                self.tail_call = Some((function, values));
+                Ok(Object::Nil())
+            },
+            callee => self.call(callee, values).map_err(|error| error.at(expr.span)),
+        }
+    }
+
+    // Calls a function or native with already evaluated arguments.
+    fn call(&mut self, callee: Object, values: Vec<Object>) -> Result<Object, RuntimeError> {
+        match callee {
+            Object::NativeFunction(native) => {
+                if let Arity::Fixed(arity) = native.arity {
+                    if values.len() != arity {
+                        return Err(RuntimeError::with_code(Code::WrongArity, format!("Expected {} arguments but got {}", arity, values.len())));
+                    }
+                }
+                self.call_native(&native, &values)
+            },
+            Object::Function(function) => {
+                function.check_arity(values.len())?;
+                if function.declaration.is_async {
+                    return tasks::spawn(self, Object::Function(function), values).map(Object::Task);
+                }
+                self.call_function(&function, values)
+            },
+            _ => Err(RuntimeError::with_code(Code::NotCallable, format!("Can only call functions, received {:?}", callee))),
+        }
+    }
+
+    // Runs a module's top level in the global scope, the first time it is imported. `at` is the
+    // import statement.
+    fn import(&mut self, name: &str, at: Span) -> Result<(), RuntimeError> {
+        if self.config.sandboxed {
+            return Err(RuntimeError::with_code(Code::HostAccessDenied, "import is not available in a sandboxed interpreter".to_string()));
+        }
+        let path = self.modules.resolve(name).map_err(|tried| {
+            let tried: Vec<String> = tried.iter().map(|path| format!("'{}'", path.display())).collect();
+            RuntimeError::with_code(Code::ModuleNotFound, format!("Could not find module '{}', tried {}", name, tried.join(", ")))
+        })?;
+        // checked first: a module that is still running is in the imported set already
+        if let Some(chain) = self.modules.cycle(&path, at) {
+            let error = RuntimeError::with_code(Code::ImportCycle, format!("Circular import of '{}'", name));
+            return Err(chain.into_iter().fold(error, RuntimeError::with_note));
+        }
+        if !self.modules.imported.insert(path.clone()) {
+            return Ok(());
+        }
+        let statements = self.modules.cache.load(&path).map_err(|error| match error {
+            LoadError::Read(error) => RuntimeError::with_code(Code::FileSystemError, format!("Could not read '{}': {}", path.display(), error)),
+            LoadError::Parse(error) => RuntimeError::with_code(Code::ImportFailed, format!("Could not import '{}': {} in {}", name, error, path.display())),
+        })?;
+
+        resolve::resolve(&statements);
+        let enclosing = mem::take(&mut self.environments);
+        self.modules.running.push(Running{path: path.clone(), at});
+        let result = statements.iter().try_for_each(|statement| self.execute(statement).map(|_| ()));
+        self.modules.running.pop();
+        self.environments = enclosing;
+        result.map_err(|error| error.in_module(&path))
+    }
+
+    // charged to the output budget and flushed straight away, so text without a newline (a
+    // prompt, a progress bar) shows up before the program goes on
+    fn write_output(&mut self, text: &str) -> Result<(), RuntimeError> {
+        self.spend(Resource::Output, text.len() as u64)?;
+        write_flushed(&mut *self.output, text)
+    }
+
+    // the same for the error output, so scripts can keep diagnostics out of their data
+    fn write_error_output(&mut self, text: &str) -> Result<(), RuntimeError> {
+        self.spend(Resource::Output, text.len() as u64)?;
+        write_flushed(&mut *self.error_output, text)
+    }
+
+    // writes the prompt to the output without a newline, then reads one line from the input;
+    // None at the end of the input
+    fn read_line(&mut self, prompt: &str) -> Result<Option<String>, RuntimeError> {
+        self.write_output(prompt)?;
+        let mut line = String::new();
+        let read = match self.input {
+            Some(ref mut input) => input.read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        }.map_err(|error| RuntimeError::with_code(Code::InputFailed, format!("Could not read input: {}", error)))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
     pub fn define_variable(&mut self, name: String, object: Object) {
         match self.environments.last_mut() {
-            Some(environment) => environment.values.insert(name, object),
-            None => None // TODO: probably should error out, no environments present
-        };
+            Some(environment) => {
+                environment.values.insert(name, object);
+            },
+            None => self.globals.define(name, object),
+        }
     }
 
     pub fn get_variable(&self, name: &String) -> Result<Object, RuntimeError> {
         for environment in self.environments.iter().rev() {
-            match environment.values.get(name) {
-                Some(object) => return Ok(object.clone()),
-                None => {}
+            if let Some(object) = environment.values.get(name) {
+                return Ok(object.clone());
             }
         }
-        return Err(RuntimeError{message: format!("Undefined variable '{}'.", name)});
+        match self.globals.get(name) {
+            Some(object) => Ok(object.clone()),
+            None => Err(undefined_variable(name)),
+        }
     }
 
     pub fn assign_variable(&mut self, name: String, object: Object) -> Result<Object, RuntimeError> {
         for environment in self.environments.iter_mut().rev() {
-            match environment.values.get(&name) {
-                Some(_) => {
-                    environment.values.insert(name, object.clone());
-                    return Ok(object);
-                },
-                None => {}
+            if let Some(value) = environment.values.get_mut(&name) {
+                *value = object.clone();
+                return Ok(object);
             }
         }
-        return Err(RuntimeError{message: format!("Undefined variable '{}'.", name)});
+        match self.globals.get_mut(&name) {
+            Some(value) => {
+                *value = object.clone();
+                Ok(object)
+            },
+            None => Err(undefined_variable(&name)),
+        }
     }
-}
 
-impl Visitor<Result<Object, RuntimeError>> for ExprEvaluator {
-    fn visit_expr(&mut self, e: &Expr) -> Result<Object, RuntimeError> {
-        match &*e {
-            Expr::Assign(token, ref expr) => {
+    // a reference the resolver marked, straight to the global's slot
+    fn get_global(&self, e: &Expr, name: &str) -> Result<Object, RuntimeError> {
+        match self.globals.slot(&e.resolution, name) {
+            Some(slot) => Ok(self.globals.at(slot).clone()),
+            None => Err(undefined_variable(name)),
+        }
+    }
+
+    fn assign_global(&mut self, e: &Expr, name: &str, object: Object) -> Result<(), RuntimeError> {
+        match self.globals.slot(&e.resolution, name) {
+            Some(slot) => {
+                *self.globals.at_mut(slot) = object;
+                Ok(())
+            },
+            None => Err(undefined_variable(name)),
+        }
+    }
+
+    fn evaluate_unary(&mut self, operator: &Expr, rhs: &Expr) -> Result<Object, RuntimeError> {
+        match operator_from_expression(operator)? {
+            Operator::Bang => {
+                let result = self.visit_expr(rhs)?;
+                match result {
+                    result if self.config.lox_compat => Ok(Object::Boolean(!lox_truthy(&result))),
+                    Object::Boolean(b) => Ok(Object::Boolean(!b)),
+                    _ => Err(RuntimeError::with_code(Code::InvalidOperand, format!("Bang operator received non-boolean expression: {:?}", result))),
+                }
+            },
+            Operator::Subtract => {
+                let result = self.visit_expr(rhs)?;
+                match result {
+                    Object::Float(float) => Ok(Object::Float(-float)),
+                    Object::Integer(integer) => match self.config.overflow {
+                        OverflowPolicy::Error => integer.checked_neg()
+                            .map(Object::Integer)
+                            .ok_or_else(|| RuntimeError::with_code(Code::IntegerOverflow, format!("Integer overflow trying to negate {}", integer))),
+                        OverflowPolicy::Wrap => Ok(Object::Integer(integer.wrapping_neg())),
+                        OverflowPolicy::Saturate => Ok(Object::Integer(integer.saturating_neg())),
+                    },
+                    _ => Err(RuntimeError::with_code(Code::InvalidOperand, format!("Unary subtract operator received non-number expression: {:?}", result))),
+                }
+            },
+            Operator::Await => {
+                let result = self.visit_expr(rhs)?;
+                tasks::wait(self, result)
+            },
+            op => Err(RuntimeError::with_code(Code::Internal, format!("Invalid unary opeartor {:?}", op))),
+        }
+    }
+
+    fn evaluate_binary(&mut self, lhs: &Expr, operator: &Expr, rhs: &Expr) -> Result<Object, RuntimeError> {
+        match operator_from_expression(operator)? {
+            Operator::BangEqual => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval != rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval != rval)),
+                    (Object::Integer(integer), Object::Float(float)) | (Object::Float(float), Object::Integer(integer)) => Ok(Object::Boolean(!integer_equals_float(integer, float))),
+                    (Object::Boolean(lval), Object::Boolean(rval)) => Ok(Object::Boolean(lval != rval)),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval != rval)),
+                    (Object::List(lval), Object::List(rval)) => Ok(Object::Boolean(!lists_equal(&lval, &rval))),
+                    (Object::Nil(), Object::Nil()) if self.config.lox_compat => Ok(Object::Boolean(false)),
+                    _ if self.config.lox_compat => Ok(Object::Boolean(true)),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot compare using !=", lval, rval))),
+                }
+            },
+            Operator::EqualEqual => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval == rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval == rval)),
+                    (Object::Integer(integer), Object::Float(float)) | (Object::Float(float), Object::Integer(integer)) => Ok(Object::Boolean(integer_equals_float(integer, float))),
+                    (Object::Boolean(lval), Object::Boolean(rval)) => Ok(Object::Boolean(lval == rval)),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval == rval)),
+                    (Object::List(lval), Object::List(rval)) => Ok(Object::Boolean(lists_equal(&lval, &rval))),
+                    (Object::Nil(), Object::Nil()) if self.config.lox_compat => Ok(Object::Boolean(true)),
+                    _ if self.config.lox_compat => Ok(Object::Boolean(false)),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot compare using ==", lval, rval))),
+                }
+            },
+            Operator::Greater => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval > rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval > rval)),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean(lval as f64 > rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval > rval as f64)),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval > rval)),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot compare using >", lval, rval))),
+                }
+            },
+            Operator::GreaterEqual => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval >= rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval >= rval)),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean(lval as f64 >= rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval >= rval as f64)),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval >= rval)),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot compare using >=", lval, rval))),
+                }
+            },
+            Operator::Less => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval < rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval < rval)),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean((lval as f64) < rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval < (rval as f64))),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval < rval)),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot compare using <", lval, rval))),
+                }
+            },
+            Operator::LessEqual => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval <= rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval <= rval)),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean((lval as f64) <= rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval <= (rval as f64))),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval <= rval)),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot compare using <=", lval, rval))),
+                }
+            },
+            // an equal value in a list, or a substring of a string
+            Operator::In => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (lval, Object::List(values)) => Ok(Object::Boolean(values.iter().any(|value| values_equal(&lval, value)))),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(rval.contains(&*lval))),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot check membership using in", lval, rval))),
+                }
+            },
+            Operator::Is => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                Ok(Object::Boolean(identical(&lhs_value, &rhs_value)))
+            },
+            Operator::Add => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval + rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => self.integer_arithmetic("add", lval, rval, i64::checked_add, i64::wrapping_add, i64::saturating_add),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) + rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval + (rval as f64))),
+                    (Object::StringLiteral(lval), Object::StringLiteral(rval)) => {
+                        self.spend(Resource::Memory, (lval.len() + rval.len()) as u64)?;
+                        Ok(Object::StringLiteral(format!("{}{}", lval, rval).into()))
+                    },
+                    (Object::StringLiteral(string), number @ (Object::Integer(_) | Object::Float(_))) if self.config.coercion == CoercionPolicy::Stringify => {
+                        let text = format!("{}{}", string, self.display(&number));
+                        self.spend(Resource::Memory, text.len() as u64)?;
+                        Ok(Object::StringLiteral(text.into()))
+                    },
+                    (number @ (Object::Integer(_) | Object::Float(_)), Object::StringLiteral(string)) if self.config.coercion == CoercionPolicy::Stringify => {
+                        let text = format!("{}{}", self.display(&number), string);
+                        self.spend(Resource::Memory, text.len() as u64)?;
+                        Ok(Object::StringLiteral(text.into()))
+                    },
+                    // unless the coercion policy says otherwise a number doesn't turn into text on
+                    // its own, that's what str() and format() are for
+                    (lval @ Object::StringLiteral(_), rval) | (lval, rval @ Object::StringLiteral(_)) =>
+                        Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot add, + joins two strings, use str() or format() for anything else", lval, rval))),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot add", lval, rval))),
+                }
+            },
+            Operator::Subtract => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval - rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => self.integer_arithmetic("subtract", lval, rval, i64::checked_sub, i64::wrapping_sub, i64::saturating_sub),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) - rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval - (rval as f64))),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot subtract", lval, rval))),
+                }
+            },
+            Operator::Multiply => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval * rval)),
+                    (Object::Integer(lval), Object::Integer(rval)) => self.integer_arithmetic("multiply", lval, rval, i64::checked_mul, i64::wrapping_mul, i64::saturating_mul),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) * rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval * (rval as f64))),
+                    (Object::StringLiteral(string), Object::Integer(count)) | (Object::Integer(count), Object::StringLiteral(string)) => self.repeat(&string, count),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot multiply", lval, rval))),
+                }
+            },
+            // always a float, ~/ is the division that stays whole. Only integers can't be divided
+            // by zero, floats follow IEEE 754
+            Operator::Divide => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                match (lhs_value, rhs_value) {
+                    (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval / rval)),
+                    (Object::Integer(_), Object::Integer(0)) => Err(division_by_zero()),
+                    (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Float((lval as f64) / (rval as f64))),
+                    (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) / rval)),
+                    (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval / (rval as f64))),
+                    (lval, rval) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("lhs is {:?} rhs is {:?} cannot divide", lval, rval))),
+                }
+            },
+            Operator::FloorDivide => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                self.divmod(lhs_value, rhs_value).map(|(quotient, _)| quotient)
+            },
+            Operator::Modulo => {
+                let lhs_value = self.visit_expr(lhs)?;
+                let rhs_value = self.visit_expr(rhs)?;
+                self.divmod(lhs_value, rhs_value).map(|(_, remainder)| remainder)
+            },
+            op => Err(RuntimeError::with_code(Code::Internal, format!("Invalid inline opeartor {:?}", op))),
+        }
+    }
+
+    // visit_expr without the span bookkeeping
+    fn evaluate_kind(&mut self, e: &Expr) -> Result<Object, RuntimeError> {
+        match &e.kind {
+            ExprKind::Assign(token, ref expr) => {
                 let result = self.visit_expr(expr)?;
-                self.assign_variable(token.lexeme.to_string(), result.clone())?;
+                if e.resolution.is_global() {
+                    self.assign_global(e, &token.lexeme, result.clone())?;
+                } else {
+                    self.assign_variable(token.lexeme.to_string(), result.clone())?;
+                }
                 Ok(result)
             },
-            Expr::Variable(token) => self.get_variable(&token.lexeme),
-            Expr::BoolLiteral(b) => Ok(Object::Boolean(*b)),
-            Expr::StringLiteral(n) => Ok(Object::StringLiteral(n.to_string())),
-            Expr::IntegerLiteral(n) => Ok(Object::Integer(n.parse::<i64>().unwrap())),
-            Expr::FloatLiteral(n) => Ok(Object::Float(n.parse::<f64>().unwrap())),
-            Expr::Logical(ref lhs, token_type, ref rhs) => {
+            ExprKind::Variable(token) if e.resolution.is_global() => self.get_global(e, &token.lexeme),
+            ExprKind::Variable(token) => self.get_variable(&token.lexeme),
+            ExprKind::BoolLiteral(b) => Ok(Object::Boolean(*b)),
+            ExprKind::NilLiteral => Ok(Object::Nil()),
+            ExprKind::Tuple(values) => {
+                let mut list = Vec::new();
+                for value in values.iter() {
+                    list.push(self.evaluate(value)?);
+                }
+                self.spend(Resource::Memory, list_size(&list))?;
+                Ok(Object::List(Arc::new(list)))
+            },
+            ExprKind::Spread(_) => Err(RuntimeError::with_code(Code::Internal, "Received a spread outside of a call or list".to_string())),
+            // shared with the AST, reading one allocates nothing
+            ExprKind::StringLiteral(n) => Ok(Object::StringLiteral(n.clone())),
+            ExprKind::IntegerLiteral(n) if self.config.lox_compat => Ok(Object::Float(n.parse::<f64>().unwrap())),
+            ExprKind::IntegerLiteral(n) => Ok(Object::Integer(n.parse::<i64>().unwrap())),
+            ExprKind::FloatLiteral(n) => Ok(Object::Float(n.parse::<f64>().unwrap())),
+            ExprKind::Logical(ref lhs, token_type, ref rhs) => {
                 let left = self.evaluate(lhs)?;
                 match token_type {
                     TokenType::Or => {
-                        if is_truthy(&left) {
+                        if self.is_truthy(&left) {
                             return Ok(left);
                         }
                     },
                     TokenType::And => {
-                        if !is_truthy(&left) {
+                        if !self.is_truthy(&left) {
                             return Ok(left);
                         }
                     }
-                    _ => return Err(RuntimeError{message: format!("Received unknown logical operator {:?}", token_type)}),
+                    _ => return Err(RuntimeError::with_code(Code::Internal, format!("Received unknown logical operator {:?}", token_type))),
                 }
                 self.evaluate(rhs)
             },
-            Expr::Operator(token_type, n) => Err(RuntimeError{message: format!("Received operator {:?} {} outside of expression", token_type, n)}),
-            Expr::Unary(ref operator, ref rhs) => 
-                match operator_from_expression(operator)? {
-                    Operator::Bang => {
-                        let result = self.visit_expr(rhs)?;
-                        match result {
-                            Object::Boolean(b) => Ok(Object::Boolean(!b)),
-                            _ => Err(RuntimeError{message: format!("Bang operator received non-boolean expression: {:?}", result)}),
-                        }
-                    },
-                    Operator::Subtract => {
-                        let result = self.visit_expr(rhs)?;
-                        match result {
-                            Object::Float(float) => Ok(Object::Float(-float)),
-                            Object::Integer(integer) => Ok(Object::Integer(-integer)),
-                            _ => Err(RuntimeError{message: format!("Unary subtract operator received non-number expression: {:?}", result)}),
-                        }
-                    },
-                    op => Err(RuntimeError{message: format!("Invalid unary opeartor {:?}", op)}),
-                },
-            Expr::Binary(ref lhs, ref operator, ref rhs) =>
-                match operator_from_expression(operator)? {
-                    Operator::BangEqual => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval != rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval != rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean(lval as f64 != rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval != rval as f64)),
-                            (Object::Boolean(lval), Object::Boolean(rval)) => Ok(Object::Boolean(lval != rval)),
-                            (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval != rval)),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot compare using !=", lval, rval)}),
-                        }
-                    },
-                    Operator::EqualEqual => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval == rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval == rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean(lval as f64 == rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval == rval as f64)),
-                            (Object::Boolean(lval), Object::Boolean(rval)) => Ok(Object::Boolean(lval == rval)),
-                            (Object::StringLiteral(lval), Object::StringLiteral(rval)) => Ok(Object::Boolean(lval == rval)),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot compare using ==", lval, rval)}),
-                        }
-                    },
-                    Operator::Greater => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval > rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval > rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean(lval as f64 > rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval > rval as f64)),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot compare using >", lval, rval)}),
-                        }
-                    },
-                    Operator::GreaterEqual => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval >= rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval >= rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean(lval as f64 >= rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval >= rval as f64)),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot compare using >=", lval, rval)}),
-                        }
-                    },
-                    Operator::Less => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval < rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval < rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean((lval as f64) < rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval < (rval as f64))),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot compare using <", lval, rval)}),
-                        }
-                    },
-                    Operator::LessEqual => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Boolean(lval <= rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval <= rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Boolean((lval as f64) <= rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Boolean(lval <= (rval as f64))),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot compare using <=", lval, rval)}),
-                        }
-                    },
-                    Operator::Add => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval + rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Integer(lval + rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) + rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval + (rval as f64))),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot add", lval, rval)}),
-                        }
-                    },
-                    Operator::Subtract => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval - rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Integer(lval - rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) - rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval - (rval as f64))),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot subtract", lval, rval)}),
-                        }
-                    },
-                    Operator::Multiply => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval * rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Integer(lval * rval)),
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) * rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval * (rval as f64))),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot multiply", lval, rval)}),
-                        }
-                    },
-                    Operator::Divide => {
-                        let lhs_value = self.visit_expr(lhs)?;
-                        let rhs_value = self.visit_expr(rhs)?;
-                        match (lhs_value, rhs_value) {
-                            (Object::Float(lval), Object::Float(rval)) => Ok(Object::Float(lval / rval)),
-                            (Object::Integer(lval), Object::Integer(rval)) => Ok(Object::Float((lval as f64) / (rval as f64))), // DEFER: determine if this should be integer division
-                            (Object::Integer(lval), Object::Float(rval)) => Ok(Object::Float((lval as f64) / rval)),
-                            (Object::Float(lval), Object::Integer(rval)) => Ok(Object::Float(lval / (rval as f64))),
-                            (lval, rval) => Err(RuntimeError{message: format!("lhs is {:?} rhs is {:?} cannot divide", lval, rval)}),
+            ExprKind::Operator(token_type, n) => Err(RuntimeError::with_code(Code::Internal, format!("Received operator {:?} {} outside of expression", token_type, n))),
+            ExprKind::Unary(ref operator, ref rhs) => self.evaluate_unary(operator, rhs),
+            ExprKind::Binary(ref lhs, ref operator, ref rhs) => self.evaluate_binary(lhs, operator, rhs),
+            ExprKind::Grouping(ref expr) => self.visit_expr(expr),
+            ExprKind::List(elements) => {
+                let values = self.evaluate_elements(elements)?;
+                self.spend(Resource::Memory, list_size(&values))?;
+                Ok(Object::List(Arc::new(values)))
+            },
+            ExprKind::Index(ref list, _bracket, ref index) => {
+                let list = self.evaluate(list)?;
+                let index = self.evaluate(index)?;
+                match (list, index) {
+                    (Object::List(values), Object::Integer(index)) => {
+                        if index < 0 || index as usize >= values.len() {
+                            return Err(RuntimeError::with_code(Code::IndexOutOfRange, format!("Index {} out of range for a list of length {}", index, values.len())));
                         }
+                        Ok(values[index as usize].clone())
                     },
-                    op => Err(RuntimeError{message: format!("Invalid inline opeartor {:?}", op)}),
-                },
-            Expr::Grouping(ref expr) => self.visit_expr(expr),
+                    (Object::List(_), index) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("List index must be an integer, received {:?}", index))),
+                    (list, _) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("Can only index lists, received {:?}", list))),
+                }
+            },
+            ExprKind::Call(ref callee, _paren, arguments) => {
+                let callee = self.evaluate(callee)?;
+                let values = self.evaluate_elements(arguments)?;
+                self.call(callee, values)
+            },
         }
     }
+}
+
+impl Visitor<Result<Object, RuntimeError>> for ExprEvaluator {
+    fn visit_expr(&mut self, e: &Expr) -> Result<Object, RuntimeError> {
+        self.spend(Resource::Expressions, 1).map_err(|error| error.at(e.span))?;
+        if self.tracer.is_none() {
+            return self.evaluate_nested(e);
+        }
+
+        self.trace_enter();
+        let result = self.evaluate_nested(e);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.leave(&AstPrinter{indent: 0}.visit_expr(e), e.span, &result);
+        }
+        result
+    }
 
     fn visit_statement(&mut self, s: &Statement) -> Result<Object, RuntimeError> {
-        match &*s {
-            Statement::Expression(ref expr) => self.visit_expr(expr),
-            Statement::If(ref expr, ref then_statement, ref else_branch) => {
-                if is_truthy(&self.visit_expr(expr)?) {
-                    self.execute(&then_statement)?;
+        match &s.kind {
+            StatementKind::Expression(ref expr) => self.visit_expr(expr),
+            StatementKind::If(ref expr, ref then_statement, ref else_branch) => {
+                let condition = self.visit_expr(expr)?;
+                if self.is_truthy(&condition) {
+                    self.execute(then_statement)?;
                 } else {
                     match else_branch {
-                        Some(ref else_statement) => self.execute(&else_statement)?,
+                        Some(ref else_statement) => self.execute(else_statement)?,
                         None => Object::Nil(),
                     };
                 }
                 Ok(Object::Nil())
             },
-            Statement::Print(ref expr) => {
+            StatementKind::Print(ref expr) => {
+                if !self.config.allow_print {
+                    return Err(RuntimeError::with_code(Code::PrintDisabled, "print is disabled".to_string()));
+                }
                 let result = self.visit_expr(expr)?;
-                println!("{}", stringify(&result));
+                let text = if self.config.lox_compat { lox_stringify(&result) } else { self.display(&result) };
+                self.write_output(&format!("{}\n", text))?;
                 Ok(result)
             },
-            Statement::While(ref condition, ref body) => {
-                while is_truthy(&self.evaluate(condition)?) {
+            StatementKind::While(ref condition, ref body) => {
+                let mut iterations = 0;
+                loop {
+                    let value = self.evaluate(condition)?;
+                    if !self.is_truthy(&value) {
+                        break;
+                    }
+                    self.count_iteration(&mut iterations)?;
                     self.execute(body)?;
+                    if self.returning.is_some() {
+                        break;
+                    }
                 }
                 Ok(Object::Nil())
             },
-            Statement::Var(token, initializer) => {
+            StatementKind::With(name, resource, body) => {
+                let file = match self.visit_expr(resource)? {
+                    Object::File(file) => file,
+                    other => return Err(RuntimeError::with_code(Code::InvalidOperand, format!("with expects a resource such as a file, received {:?}", other))),
+                };
+                self.push_scope();
+                self.define_variable(name.lexeme.to_string(), Object::File(file.clone()));
+                let mut result = self.execute(body);
+                // a call in tail position has to run while the resource is still open
+                if let (true, Some((function, arguments))) = (result.is_ok(), self.tail_call.take()) {
+                    self.returning = None;
+                    result = self.call_function(&function, arguments).map(|value| {
+                        self.returning = Some(value);
+                        Object::Nil()
+                    });
+                }
+                self.pop_scope();
+                // an error from the body wins over one from closing
+                let closed = file.close();
+                result.and_then(|value| closed.map(|_| value))
+            },
+            StatementKind::For(initializer, condition, increment, body) => {
+                // the initializer gets its own scope, like the block the loop used to desugar into
+                self.push_scope();
+                let result = self.execute_for(initializer, condition, increment, body);
+                self.pop_scope();
+                result
+            },
+            StatementKind::Var(token, _annotation, initializer) => {
                 let value =
                     match initializer {
                         Some(ref expr) => self.visit_expr(expr)?,
@@ -335,21 +1336,86 @@ impl Visitor<Result<Object, RuntimeError>> for ExprEvaluator {
                 self.define_variable(token.lexeme.to_string(), value);
                 Ok(Object::Nil())
             },
-            Statement::Block(statements) => {
+            StatementKind::VarTuple(names, initializer) => {
+                let values = unpack(names.len(), self.visit_expr(initializer)?)?;
+                for (name, value) in names.iter().zip(values) {
+                    self.define_variable(name.lexeme.to_string(), value);
+                }
+                Ok(Object::Nil())
+            },
+            StatementKind::AssignTuple(names, value) => {
+                let values = unpack(names.len(), self.visit_expr(value)?)?;
+                for (name, value) in names.iter().zip(values) {
+                    self.assign_variable(name.lexeme.to_string(), value)?;
+                }
+                Ok(Object::Nil())
+            },
+            StatementKind::Block(statements) => {
                 self.execute_block(statements)?;
                 Ok(Object::Nil())
-            }
+            },
+            StatementKind::Function(declaration) => {
+                let function = Function{
+                    declaration: declaration.clone(),
+                    module: self.modules.running.last().map(|running| Arc::from(running.path.as_path())),
+                };
+                let name = function.name().to_string();
+                // the decorator nearest the function wraps it first
+                let mut decorated = Object::Function(function);
+                for decorator in declaration.decorators.iter().rev() {
+                    let decorator = self.visit_expr(decorator)?;
+                    decorated = self.call(decorator, vec![decorated])?;
+                }
+                self.define_variable(name, decorated);
+                Ok(Object::Nil())
+            },
+            StatementKind::Return(_keyword, value) => {
+                let value =
+                    match value {
+                        Some(ref expr) => self.return_value(expr)?,
+                        None => Object::Nil()
+                    };
+                self.returning = Some(value);
+                Ok(Object::Nil())
+            },
+            StatementKind::Import(_keyword, name) => {
+                let name = name.lexeme.trim_matches('"');
+                self.import(name, s.span)?;
+                Ok(Object::Nil())
+            },
         }
     }
 }
 
-fn stringify(obj: &Object) -> String {
+// what jlox prints: whole numbers without a fractional part, Java's names for the float
+// specials, and natives without their name
+fn lox_stringify(obj: &Object) -> String {
     match obj {
-        Object::Nil() => format!("nil"),
+        Object::Float(float) if float.is_nan() => "NaN".to_string(),
+        Object::Float(float) if float.is_infinite() => if *float > 0.0 { "Infinity" } else { "-Infinity" }.to_string(),
         Object::Float(float) => format!("{}", float),
-        Object::Integer(integer) => format!("{}", integer),
-        Object::Boolean(boolean) => format!("{}", boolean),
-        Object::StringLiteral(string) => format!("{}", string),
+        Object::NativeFunction(_) => "<native fn>".to_string(),
+        obj => obj.to_string(),
+    }
+}
+
+fn write_flushed(output: &mut dyn Write, text: &str) -> Result<(), RuntimeError> {
+    output.write_all(text.as_bytes())
+        .and_then(|_| output.flush())
+        .map_err(|error| RuntimeError::with_code(Code::OutputFailed, format!("Could not write output: {}", error)))
+}
+
+// what a list charges against the memory budget, not counting what its elements hold
+fn list_size(values: &[Object]) -> u64 {
+    std::mem::size_of_val(values) as u64
+}
+
+// everything a value holds on the heap, for values made outside the script like a native's result
+fn allocated(value: &Object) -> u64 {
+    match value {
+        Object::StringLiteral(string) => string.len() as u64,
+        Object::List(values) => list_size(values) + values.iter().map(allocated).sum::<u64>(),
+        _ => 0,
     }
 }
 
@@ -358,15 +1424,94 @@ fn is_truthy(obj: &Object) -> bool {
         Object::Nil() => false,
         Object::Float(float) => *float != 0.0,
         Object::Integer(integer) => *integer != 0,
-        Object::Boolean(boolean) => *boolean == true,
+        Object::Boolean(boolean) => *boolean,
         Object::StringLiteral(_string) => true,
+        Object::List(_values) => true,
+        Object::NativeFunction(_native) => true,
+        Object::Function(_function) => true,
+        Object::Coroutine(_coroutine) => true,
+        Object::Task(_task) => true,
+        Object::File(_file) => true,
+    }
+}
+
+// the values of a list being unpacked into `count` variables
+fn unpack(count: usize, value: Object) -> Result<Vec<Object>, RuntimeError> {
+    match value {
+        Object::List(values) if values.len() == count => Ok(Arc::unwrap_or_clone(values)),
+        Object::List(values) => Err(RuntimeError::with_code(Code::InvalidOperand, format!("Expected {} values to unpack but got {}", count, values.len()))),
+        other => Err(RuntimeError::with_code(Code::InvalidOperand, format!("Can only unpack a list, received {:?}", other))),
+    }
+}
+
+// What == compares lists by and `in` looks for: values that == would call equal, lists with equal
+// elements, and nothing else, since a list can hold values == would reject. A list can't change
+// once it's made, so it can't come to contain itself and the recursion always ends.
+fn values_equal(lhs: &Object, rhs: &Object) -> bool {
+    match (lhs, rhs) {
+        (Object::Nil(), Object::Nil()) => true,
+        (Object::Float(lval), Object::Float(rval)) => lval == rval,
+        (Object::Integer(lval), Object::Integer(rval)) => lval == rval,
+        (Object::Integer(integer), Object::Float(float)) | (Object::Float(float), Object::Integer(integer)) => integer_equals_float(*integer, *float),
+        (Object::Boolean(lval), Object::Boolean(rval)) => lval == rval,
+        (Object::StringLiteral(lval), Object::StringLiteral(rval)) => lval == rval,
+        (Object::List(lval), Object::List(rval)) => lists_equal(lval, rval),
+        _ => false,
+    }
+}
+
+// exactly, where converting the integer to a float would round those past 2^53 onto their
+// neighbours
+fn integer_equals_float(integer: i64, float: f64) -> bool {
+    float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64 && float as i64 == integer
+}
+
+fn lists_equal(lhs: &[Object], rhs: &[Object]) -> bool {
+    lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(l, r)| values_equal(l, r))
+}
+
+// What `is` asks. Values that act like copies whenever they're passed around (nil, numbers,
+// booleans, and strings and lists, which are shared but never change) are the same when they are
+// of one kind and equal; functions, coroutines, tasks and files have state of their own, and are
+// only the same as themselves.
+fn identical(lhs: &Object, rhs: &Object) -> bool {
+    match (lhs, rhs) {
+        (Object::Float(lval), Object::Float(rval)) => lval == rval,
+        (Object::Integer(lval), Object::Integer(rval)) => lval == rval,
+        (Object::List(lval), Object::List(rval)) => lval.len() == rval.len() && lval.iter().zip(rval.iter()).all(|(l, r)| identical(l, r)),
+        (Object::Function(lval), Object::Function(rval)) => Arc::ptr_eq(&lval.declaration, &rval.declaration) && lval.module == rval.module,
+        (Object::NativeFunction(lval), Object::NativeFunction(rval)) => Arc::ptr_eq(&lval.function, &rval.function),
+        (Object::Coroutine(lval), Object::Coroutine(rval)) => lval.same(rval),
+        (Object::Task(lval), Object::Task(rval)) => lval.same(rval),
+        (Object::File(lval), Object::File(rval)) => lval.same(rval),
+        (Object::Float(_) | Object::Integer(_) | Object::List(_), _) => false,
+        (lval, rval) => values_equal(lval, rval),
     }
 }
 
+// None only for i64::MIN ~/ -1, the one quotient too big for an integer
+fn floor_div(lval: i64, rval: i64) -> Option<i64> {
+    let quotient = lval.checked_div(rval)?;
+    Some(if lval % rval != 0 && (lval < 0) != (rval < 0) { quotient - 1 } else { quotient })
+}
+
+fn undefined_variable(name: &str) -> RuntimeError {
+    RuntimeError::with_code(Code::UndefinedVariable, format!("Undefined variable '{}'", name))
+}
+
+fn division_by_zero() -> RuntimeError {
+    RuntimeError::with_code(Code::DivisionByZero, "Division by zero".to_string())
+}
+
+// Lox has no falsey numbers, strings or lists
+fn lox_truthy(obj: &Object) -> bool {
+    !matches!(obj, Object::Nil() | Object::Boolean(false))
+}
+
 // DEFER: this should probably be part of parsing?
 fn operator_from_expression(e: &Expr) -> Result<Operator, RuntimeError> {
-    match &e {
-        Expr::Operator(token_type, _string) =>
+    match &e.kind {
+        ExprKind::Operator(token_type, _string) =>
             match token_type {
                 TokenType::Bang => Ok(Operator::Bang),
                 TokenType::BangEqual => Ok(Operator::BangEqual),
@@ -376,12 +1521,17 @@ fn operator_from_expression(e: &Expr) -> Result<Operator, RuntimeError> {
                 TokenType::GreaterEqual => Ok(Operator::GreaterEqual),
                 TokenType::Less => Ok(Operator::Less),
                 TokenType::LessEqual => Ok(Operator::LessEqual),
+                TokenType::In => Ok(Operator::In),
+                TokenType::Is => Ok(Operator::Is),
                 TokenType::Minus => Ok(Operator::Subtract),
                 TokenType::Plus => Ok(Operator::Add),
                 TokenType::Star => Ok(Operator::Multiply),
                 TokenType::Slash => Ok(Operator::Divide),
-                _ => Err(RuntimeError{message: format!("Received unknown operator {:?}", token_type)})
+                TokenType::TildeSlash => Ok(Operator::FloorDivide),
+                TokenType::Percent => Ok(Operator::Modulo),
+                TokenType::Await => Ok(Operator::Await),
+                _ => Err(RuntimeError::with_code(Code::Internal, format!("Received unknown operator {:?}", token_type)))
             }
-        _ => Err(RuntimeError{message: format!("Received non-operator expression in operator expression field")}),
+        _ => Err(RuntimeError::with_code(Code::Internal, "Received non-operator expression in operator expression field".to_string())),
     }
 }
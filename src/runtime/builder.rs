@@ -0,0 +1,264 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+use crate::runtime::clock::Clock;
+use crate::runtime::modules::ModuleCache;
+use crate::runtime::modules::Modules;
+use crate::runtime::natives;
+use crate::runtime::Globals;
+use crate::runtime::ExprEvaluator;
+use crate::runtime::Usage;
+
+// Nested statements and expressions allowed by default, a few hundred levels of recursion
+// through a function like `return 1 + f(n - 1);`. It needs a thread with stack_size() of stack,
+// which the CLI and coroutines run on; hosts calling in from threads of their own (2 MiB when
+// spawned, 8 MiB for the main thread) should lower it to fit or give those threads more.
+pub const DEFAULT_RECURSION_DEPTH: usize = 4096;
+// the most digits after the point floats can be written with
+pub const MAX_FLOAT_PRECISION: usize = u16::MAX as usize;
+
+// Every level of nesting is a few interpreter frames on the Rust stack, a couple of KiB in
+// release builds and well under this in unoptimized ones.
+pub const STACK_PER_LEVEL: usize = 64 * 1024;
+
+// The stack a thread running scripts needs for `depth` levels of nesting, the default depth's
+// when unlimited: 256 MiB for the default. Only the part that is used ever takes up memory.
+pub fn stack_size(depth: Option<usize>) -> usize {
+    depth.unwrap_or(DEFAULT_RECURSION_DEPTH).saturating_mul(STACK_PER_LEVEL)
+}
+
+// what happens when integer arithmetic doesn't fit in an i64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    Error,
+    Wrap,
+    Saturate,
+}
+
+// what + does with a string and a number
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoercionPolicy {
+    // an error, the number has to go through str() or format() first
+    Strict,
+    // the number is written the way print would write it and joined on
+    Stringify,
+}
+
+// Settings an embedder can restrict; limits left at None are unbounded.
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub max_call_depth: Option<usize>,
+    // nesting of statements and expressions being evaluated, None risks overflowing the stack
+    pub max_recursion_depth: Option<usize>,
+    // per loop, counted each time the body runs
+    pub max_loop_iterations: Option<u64>,
+    pub overflow: OverflowPolicy,
+    pub coercion: CoercionPolicy,
+    pub allow_print: bool,
+    // execution budget for untrusted scripts, spent over the evaluator's whole life
+    pub max_statements: Option<u64>,
+    pub max_expressions: Option<u64>,
+    pub max_memory: Option<u64>,
+    pub max_output_bytes: Option<u64>,
+    // statements plus expressions each interpret call may evaluate, unlike the budget this
+    // starts over on every call
+    pub max_steps_per_call: Option<u64>,
+    // clock(), now() and sleep() use a virtual clock that only sleep moves
+    pub deterministic: bool,
+    // natives that reach outside the interpreter (environment variables, files) fail instead
+    pub sandboxed: bool,
+    // Lox's semantics, for running programs written for it: every number is a float, only nil
+    // and false are falsey, == never fails and print shows values the way jlox does
+    pub lox_compat: bool,
+    // digits after the point print, str() and format() give floats, up to MAX_FLOAT_PRECISION;
+    // None writes the shortest text that reads back as the same float
+    pub float_precision: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config{
+            max_call_depth: None,
+            max_recursion_depth: Some(DEFAULT_RECURSION_DEPTH),
+            max_loop_iterations: None,
+            overflow: OverflowPolicy::Error,
+            coercion: CoercionPolicy::Strict,
+            allow_print: true,
+            max_statements: None,
+            max_expressions: None,
+            max_memory: None,
+            max_output_bytes: None,
+            max_steps_per_call: None,
+            deterministic: false,
+            sandboxed: false,
+            lox_compat: false,
+            float_precision: None,
+        }
+    }
+}
+
+pub struct InterpreterBuilder {
+    config: Config,
+    output: Box<dyn Write + Send>,
+    error_output: Box<dyn Write + Send>,
+    input: Option<Box<dyn BufRead + Send>>,
+    module_path: Vec<PathBuf>,
+    module_cache: ModuleCache,
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> InterpreterBuilder {
+        InterpreterBuilder{
+            config: Config::default(),
+            output: Box::new(io::stdout()),
+            error_output: Box::new(io::stderr()),
+            input: None,
+            module_path: Vec::new(),
+            module_cache: ModuleCache::new(),
+        }
+    }
+
+    pub fn max_call_depth(mut self, depth: usize) -> InterpreterBuilder {
+        self.config.max_call_depth = Some(depth);
+        self
+    }
+
+    pub fn max_recursion_depth(mut self, depth: usize) -> InterpreterBuilder {
+        self.config.max_recursion_depth = Some(depth);
+        self
+    }
+
+    pub fn max_loop_iterations(mut self, iterations: u64) -> InterpreterBuilder {
+        self.config.max_loop_iterations = Some(iterations);
+        self
+    }
+
+    pub fn overflow(mut self, policy: OverflowPolicy) -> InterpreterBuilder {
+        self.config.overflow = policy;
+        self
+    }
+
+    pub fn coercion(mut self, policy: CoercionPolicy) -> InterpreterBuilder {
+        self.config.coercion = policy;
+        self
+    }
+
+    // when disabled, running a print statement is a runtime error
+    pub fn allow_print(mut self, allow: bool) -> InterpreterBuilder {
+        self.config.allow_print = allow;
+        self
+    }
+
+    pub fn max_statements(mut self, statements: u64) -> InterpreterBuilder {
+        self.config.max_statements = Some(statements);
+        self
+    }
+
+    pub fn max_expressions(mut self, expressions: u64) -> InterpreterBuilder {
+        self.config.max_expressions = Some(expressions);
+        self
+    }
+
+    // bytes of strings the script may create in total
+    pub fn max_memory(mut self, bytes: u64) -> InterpreterBuilder {
+        self.config.max_memory = Some(bytes);
+        self
+    }
+
+    pub fn max_output_bytes(mut self, bytes: u64) -> InterpreterBuilder {
+        self.config.max_output_bytes = Some(bytes);
+        self
+    }
+
+    pub fn max_steps_per_call(mut self, steps: u64) -> InterpreterBuilder {
+        self.config.max_steps_per_call = Some(steps);
+        self
+    }
+
+    // same times on every run, for tests and reproducible output; sleep() returns at once
+    pub fn deterministic(mut self, deterministic: bool) -> InterpreterBuilder {
+        self.config.deterministic = deterministic;
+        self
+    }
+
+    // for scripts that mustn't see or change the host, e.g. its environment variables and files
+    pub fn sandboxed(mut self, sandboxed: bool) -> InterpreterBuilder {
+        self.config.sandboxed = sandboxed;
+        self
+    }
+
+    // see Config::lox_compat
+    pub fn lox_compat(mut self, lox_compat: bool) -> InterpreterBuilder {
+        self.config.lox_compat = lox_compat;
+        self
+    }
+
+    // see Config::float_precision, more digits than MAX_FLOAT_PRECISION get that many
+    pub fn float_precision(mut self, digits: usize) -> InterpreterBuilder {
+        self.config.float_precision = Some(digits.min(MAX_FLOAT_PRECISION));
+        self
+    }
+
+    pub fn stdout(mut self, output: Box<dyn Write + Send>) -> InterpreterBuilder {
+        self.output = output;
+        self
+    }
+
+    pub fn stderr(mut self, error_output: Box<dyn Write + Send>) -> InterpreterBuilder {
+        self.error_output = error_output;
+        self
+    }
+
+    // lines for input(), stdin by default
+    pub fn stdin(mut self, input: Box<dyn BufRead + Send>) -> InterpreterBuilder {
+        self.input = Some(input);
+        self
+    }
+
+    // directories searched, in order, for imports that aren't relative paths
+    pub fn module_path(mut self, directories: Vec<PathBuf>) -> InterpreterBuilder {
+        self.module_path = directories;
+        self
+    }
+
+    // parsed modules, shared with every interpreter given a clone of the same cache
+    pub fn module_cache(mut self, cache: ModuleCache) -> InterpreterBuilder {
+        self.module_cache = cache;
+        self
+    }
+
+    pub fn build(self) -> ExprEvaluator {
+        let mut evaluator = ExprEvaluator{
+            globals: Globals::new(),
+            environments: Vec::new(),
+            spare_scopes: Vec::new(),
+            script_args: Vec::new(),
+            output: self.output,
+            error_output: self.error_output,
+            input: self.input,
+            hook: None,
+            config: self.config,
+            call_depth: 0,
+            depth: 0,
+            usage: Usage::default(),
+            call_start: 0,
+            interrupt: None,
+            tracer: None,
+            returning: None,
+            tail_call: None,
+            clock: Clock::new(self.config.deterministic),
+            modules: Modules{search_path: self.module_path, cache: self.module_cache, ..Modules::default()},
+            resumers: Vec::new(),
+            event_loop: Default::default(),
+        };
+        natives::define_natives(&mut evaluator);
+        evaluator
+    }
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+}
@@ -0,0 +1,50 @@
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// Where clock(), now() and sleep() get their time from.
+pub enum Clock {
+    System{started: Instant},
+    // Time only moves when the script sleeps, starting at the unix epoch, so every run sees the
+    // same times. Also the only clock there is in the browser.
+    Virtual{elapsed: Duration},
+}
+
+impl Clock {
+    pub fn new(deterministic: bool) -> Clock {
+        if deterministic {
+            Clock::Virtual{elapsed: Duration::ZERO}
+        } else {
+            Clock::System{started: Instant::now()}
+        }
+    }
+
+    // seconds since the interpreter was built, never going backwards
+    pub fn monotonic(&self) -> f64 {
+        match self {
+            Clock::System{started} => started.elapsed().as_secs_f64(),
+            Clock::Virtual{elapsed} => elapsed.as_secs_f64(),
+        }
+    }
+
+    // milliseconds since the unix epoch
+    pub fn epoch_millis(&self) -> i64 {
+        match self {
+            // a system clock set before 1970 reads as negative
+            Clock::System{..} => match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(since) => since.as_millis() as i64,
+                Err(error) => -(error.duration().as_millis() as i64),
+            },
+            Clock::Virtual{elapsed} => elapsed.as_millis() as i64,
+        }
+    }
+
+    pub fn sleep(&mut self, duration: Duration) {
+        match self {
+            Clock::System{..} => thread::sleep(duration),
+            Clock::Virtual{elapsed} => *elapsed += duration,
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+use crate::diagnostic::Code;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+
+impl From<i64> for Object {
+    fn from(integer: i64) -> Object {
+        Object::Integer(integer)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(float: f64) -> Object {
+        Object::Float(float)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(boolean: bool) -> Object {
+        Object::Boolean(boolean)
+    }
+}
+
+impl From<String> for Object {
+    fn from(string: String) -> Object {
+        Object::StringLiteral(string.into())
+    }
+}
+
+impl From<&str> for Object {
+    fn from(string: &str) -> Object {
+        Object::StringLiteral(string.into())
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(values: Vec<T>) -> Object {
+        Object::List(Arc::new(values.into_iter().map(Into::into).collect()))
+    }
+}
+
+impl From<()> for Object {
+    fn from(_unit: ()) -> Object {
+        Object::Nil()
+    }
+}
+
+fn mismatch(expected: &str, object: &Object) -> RuntimeError {
+    RuntimeError::with_code(Code::InvalidArgument, format!("Expected {}, received {:?}", expected, object))
+}
+
+impl TryFrom<Object> for i64 {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<i64, RuntimeError> {
+        match object {
+            Object::Integer(integer) => Ok(integer),
+            other => Err(mismatch("an integer", &other)),
+        }
+    }
+}
+
+// integers widen to floats the same way they do in mixed arithmetic
+impl TryFrom<Object> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<f64, RuntimeError> {
+        match object {
+            Object::Float(float) => Ok(float),
+            Object::Integer(integer) => Ok(integer as f64),
+            other => Err(mismatch("a number", &other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<bool, RuntimeError> {
+        match object {
+            Object::Boolean(boolean) => Ok(boolean),
+            other => Err(mismatch("a boolean", &other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<String, RuntimeError> {
+        match object {
+            Object::StringLiteral(string) => Ok(string.to_string()),
+            other => Err(mismatch("a string", &other)),
+        }
+    }
+}
+
+// every element has to convert, the first that doesn't is the error
+impl<T: TryFrom<Object, Error = RuntimeError>> TryFrom<Object> for Vec<T> {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Vec<T>, RuntimeError> {
+        match object {
+            Object::List(values) => values.iter().cloned().map(T::try_from).collect(),
+            other => Err(mismatch("a list", &other)),
+        }
+    }
+}
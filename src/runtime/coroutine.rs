@@ -0,0 +1,301 @@
+use std::fmt;
+use std::io;
+use std::mem;
+use std::panic;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use crate::diagnostic::Code;
+use crate::runtime::Arity;
+use crate::runtime::Config;
+use crate::runtime::Environment;
+use crate::runtime::Globals;
+use crate::runtime::ExprEvaluator;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+use crate::runtime::clock::Clock;
+use crate::runtime::modules::Modules;
+use crate::runtime::Usage;
+use crate::runtime::stack_size;
+
+// A function that can stop part way with yield() and carry on from there when resumed. Each one
+// runs on its own thread, but the interpreter is handed over by value on every resume and yield,
+// so only one of them ever runs at a time and they all share the same globals. The thread
+// starts at the first resume and ends with the coroutine, so how many can be started and not yet
+// finished is down to how many threads the system allows, past which resume fails with E0221.
+#[derive(Clone)]
+pub struct Coroutine {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Suspended,
+    Running,
+    Dead,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Suspended => "suspended",
+            Status::Running => "running",
+            Status::Dead => "dead",
+        }
+    }
+}
+
+struct State {
+    status: Status,
+    function: Object,
+    // set once the thread has started, taken while it runs
+    thread: Option<(mpsc::Sender<Resume>, mpsc::Receiver<Transfer>)>,
+}
+
+struct Resume {
+    evaluator: Box<ExprEvaluator>,
+    value: Object,
+}
+
+struct Transfer {
+    evaluator: Box<ExprEvaluator>,
+    outcome: Result<Outcome, RuntimeError>,
+}
+
+enum Outcome {
+    Yielded(Object),
+    Returned(Object),
+}
+
+// The running coroutine's end of its channels, the way back to whoever resumed it.
+pub struct Resumer {
+    resumed: mpsc::Receiver<Resume>,
+    transfers: mpsc::Sender<Transfer>,
+}
+
+// The scopes above the globals and the depth counters, which belong to the thread evaluating
+// them rather than to the interpreter.
+struct Frames {
+    environments: Vec<Environment>,
+    call_depth: usize,
+    depth: usize,
+}
+
+impl fmt::Debug for Coroutine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<coroutine>")
+    }
+}
+
+impl Coroutine {
+    // the same coroutine rather than a copy of it
+    pub fn same(&self, other: &Coroutine) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    pub fn new(function: Object) -> Result<Coroutine, RuntimeError> {
+        let parameters = match &function {
+            Object::Function(function) => function.declaration.parameters.len(),
+            Object::NativeFunction(native) => match native.arity {
+                Arity::Fixed(arity) => arity,
+                Arity::Variadic => 1,
+            },
+            other => return Err(RuntimeError::with_code(Code::InvalidArgument, format!("coroutine expects a function, received {:?}", other))),
+        };
+        if parameters > 1 {
+            return Err(RuntimeError::with_code(Code::InvalidArgument, format!("coroutine expects a function taking at most one argument, {:?} takes {}", function, parameters)));
+        }
+        Ok(Coroutine{
+            state: Arc::new(Mutex::new(State{
+                status: Status::Suspended,
+                function,
+                thread: None,
+            })),
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        self.state.lock().unwrap().status
+    }
+
+    // Runs the coroutine until it yields or returns, passing `value` to its function the first
+    // time and as the result of yield() after that.
+    pub fn resume(&self, evaluator: &mut ExprEvaluator, value: Object) -> Result<Object, RuntimeError> {
+        let (function, thread) = {
+            let mut state = self.state.lock().unwrap();
+            match state.status {
+                Status::Suspended => {},
+                Status::Running => return Err(coroutine_error("Cannot resume a running coroutine")),
+                Status::Dead => return Err(coroutine_error("Cannot resume a dead coroutine")),
+            }
+            state.status = Status::Running;
+            (state.function.clone(), state.thread.take())
+        };
+        let thread = match thread {
+            Some(thread) => thread,
+            // as much stack as the recursion limit needs, the limit starting over in the coroutine
+            None => match start(function, stack_size(evaluator.config.max_recursion_depth)) {
+                Ok(thread) => thread,
+                Err(error) => {
+                    self.state.lock().unwrap().status = Status::Dead;
+                    return Err(error);
+                },
+            },
+        };
+
+        let frames = evaluator.detach();
+        let (resumes, transfers) = &thread;
+        let transfer = match resumes.send(Resume{evaluator: evaluator.hand_over(), value}) {
+            Ok(()) => transfers.recv().ok(),
+            Err(mpsc::SendError(resume)) => Some(Transfer{
+                evaluator: resume.evaluator,
+                outcome: Err(coroutine_error("Coroutine stopped unexpectedly")),
+            }),
+        };
+        // run() answers even when the coroutine panics, so nothing but an abort can lose the
+        // interpreter, and carrying on with the stand-in would run the rest of the program
+        // without its globals
+        let Transfer{evaluator: returned, outcome} = transfer.expect("a coroutine thread hands the interpreter back");
+        *evaluator = *returned;
+        evaluator.attach(frames);
+
+        let mut state = self.state.lock().unwrap();
+        match outcome {
+            Ok(Outcome::Yielded(value)) => {
+                state.status = Status::Suspended;
+                state.thread = Some(thread);
+                Ok(value)
+            },
+            Ok(Outcome::Returned(value)) => {
+                state.status = Status::Dead;
+                Ok(value)
+            },
+            Err(error) => {
+                state.status = Status::Dead;
+                Err(error)
+            },
+        }
+    }
+}
+
+// Hands `value` to whoever resumed the running coroutine and waits to be resumed again.
+pub fn yield_value(evaluator: &mut ExprEvaluator, value: Object) -> Result<Object, RuntimeError> {
+    let resumer = evaluator.resumers.pop().ok_or_else(|| coroutine_error("Cannot yield outside a coroutine"))?;
+    let frames = evaluator.detach();
+    let transfer = Transfer{evaluator: evaluator.hand_over(), outcome: Ok(Outcome::Yielded(value))};
+    let resumed = match resumer.transfers.send(transfer) {
+        Ok(()) => resumer.resumed.recv().ok(),
+        Err(mpsc::SendError(transfer)) => Some(Resume{evaluator: transfer.evaluator, value: Object::Nil()}),
+    };
+    match resumed {
+        Some(Resume{evaluator: resumed, value}) => {
+            *evaluator = *resumed;
+            evaluator.attach(frames);
+            evaluator.resumers.push(resumer);
+            Ok(value)
+        },
+        // nothing can resume it any more, unwind its thread (the frames keep the depth counters
+        // of the stand-in interpreter right on the way out)
+        None => {
+            evaluator.attach(frames);
+            Err(coroutine_error("Coroutine dropped while suspended"))
+        },
+    }
+}
+
+fn start(function: Object, stack_size: usize) -> Result<(mpsc::Sender<Resume>, mpsc::Receiver<Transfer>), RuntimeError> {
+    let (resumes, resumed) = mpsc::channel();
+    let (transfers, transferred) = mpsc::channel();
+    thread::Builder::new()
+        .name("coroutine".to_string())
+        .stack_size(stack_size)
+        .spawn(move || run(function, Resumer{resumed, transfers}))
+        .map_err(|error| coroutine_error(&format!("Cannot start a coroutine: {}", error)))?;
+    Ok((resumes, transferred))
+}
+
+fn run(function: Object, resumer: Resumer) {
+    let Resume{mut evaluator, value} = match resumer.resumed.recv() {
+        Ok(resume) => resume,
+        Err(_) => return,
+    };
+    let arguments = match &function {
+        Object::Function(function) if function.declaration.parameters.is_empty() => vec![],
+        Object::NativeFunction(native) if native.arity == Arity::Fixed(0) => vec![],
+        _ => vec![value],
+    };
+    let transfers = resumer.transfers.clone();
+    evaluator.resumers.push(resumer);
+    // A panic in a native is caught here so the interpreter still goes back to the resumer,
+    // as a failed resume. The panic can only have come while this thread held the interpreter,
+    // never while it was handed over.
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| evaluator.call(function, arguments))) {
+        Ok(result) => {
+            // gone if it was dropped while suspended, with no one left to tell
+            if let Some(resumer) = evaluator.resumers.pop() {
+                let _ = resumer.transfers.send(Transfer{evaluator, outcome: result.map(Outcome::Returned)});
+            }
+        },
+        Err(payload) => {
+            evaluator.resumers.pop();
+            // whatever the unwound call left half done
+            evaluator.returning = None;
+            evaluator.tail_call = None;
+            let message = match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+            };
+            let _ = transfers.send(Transfer{evaluator, outcome: Err(coroutine_error(&format!("Coroutine panicked: {}", message)))});
+        },
+    }
+}
+
+fn coroutine_error(message: &str) -> RuntimeError {
+    RuntimeError::with_code(Code::CoroutineError, message.to_string())
+}
+
+impl ExprEvaluator {
+    fn detach(&mut self) -> Frames {
+        Frames{
+            environments: mem::take(&mut self.environments),
+            call_depth: mem::replace(&mut self.call_depth, 0),
+            depth: mem::replace(&mut self.depth, 0),
+        }
+    }
+
+    fn attach(&mut self, frames: Frames) {
+        self.environments = frames.environments;
+        self.call_depth = frames.call_depth;
+        self.depth = frames.depth;
+    }
+
+    // Moves the interpreter out for another thread to run, leaving an empty one in its place.
+    fn hand_over(&mut self) -> Box<ExprEvaluator> {
+        let config = Config::default();
+        let stand_in = ExprEvaluator{
+            globals: Globals::empty(),
+            environments: Vec::new(),
+            spare_scopes: Vec::new(),
+            script_args: Vec::new(),
+            output: Box::new(io::sink()),
+            error_output: Box::new(io::sink()),
+            input: None,
+            hook: None,
+            config,
+            call_depth: 0,
+            depth: 0,
+            usage: Usage::default(),
+            call_start: 0,
+            interrupt: None,
+            tracer: None,
+            returning: None,
+            tail_call: None,
+            clock: Clock::new(config.deterministic),
+            modules: Modules::default(),
+            resumers: Vec::new(),
+            event_loop: Default::default(),
+        };
+        Box::new(mem::replace(self, stand_in))
+    }
+}
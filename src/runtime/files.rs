@@ -0,0 +1,95 @@
+use std::fmt;
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use crate::diagnostic::Code;
+use crate::runtime::RuntimeError;
+
+// An open file, which stays open until close() or the end of the `with` that opened it. Copies
+// share the one handle, closing any of them closes it for all.
+#[derive(Clone)]
+pub struct File {
+    path: Arc<str>,
+    handle: Arc<Mutex<Option<Handle>>>,
+}
+
+enum Handle {
+    Read(BufReader<fs::File>),
+    Write(fs::File),
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<file {}>", self.path)
+    }
+}
+
+impl File {
+    // the same file rather than a copy of it
+    pub fn same(&self, other: &File) -> bool {
+        Arc::ptr_eq(&self.handle, &other.handle)
+    }
+
+    // mode is "r" to read, "w" to write over the file or "a" to add to its end
+    pub fn open(path: &str, mode: &str) -> Result<File, RuntimeError> {
+        let handle = match mode {
+            "r" => fs::File::open(path).map(|file| Handle::Read(BufReader::new(file))),
+            "w" => fs::File::create(path).map(Handle::Write),
+            "a" => fs::OpenOptions::new().append(true).create(true).open(path).map(Handle::Write),
+            other => return Err(RuntimeError::with_code(Code::InvalidArgument, format!("open expects the mode \"r\", \"w\" or \"a\", received {:?}", other))),
+        };
+        let handle = handle.map_err(|error| file_error(&format!("Could not open '{}': {}", path, error)))?;
+        Ok(File{path: Arc::from(path), handle: Arc::new(Mutex::new(Some(handle)))})
+    }
+
+    // the next line without its line ending, None at the end of the file
+    pub fn read_line(&self) -> Result<Option<String>, RuntimeError> {
+        let mut handle = self.handle.lock().unwrap();
+        let reader = match handle.as_mut() {
+            Some(Handle::Read(reader)) => reader,
+            Some(Handle::Write(_)) => return Err(file_error(&format!("'{}' was opened for writing", self.path))),
+            None => return Err(self.closed()),
+        };
+        let mut line = String::new();
+        let read = reader.read_line(&mut line).map_err(|error| file_error(&format!("Could not read '{}': {}", self.path, error)))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    pub fn write(&self, text: &str) -> Result<(), RuntimeError> {
+        let mut handle = self.handle.lock().unwrap();
+        let file = match handle.as_mut() {
+            Some(Handle::Write(file)) => file,
+            Some(Handle::Read(_)) => return Err(file_error(&format!("'{}' was opened for reading", self.path))),
+            None => return Err(self.closed()),
+        };
+        file.write_all(text.as_bytes()).map_err(|error| file_error(&format!("Could not write '{}': {}", self.path, error)))
+    }
+
+    // closing a file that's already closed does nothing
+    pub fn close(&self) -> Result<(), RuntimeError> {
+        match self.handle.lock().unwrap().take() {
+            Some(Handle::Write(mut file)) => file.flush().map_err(|error| file_error(&format!("Could not write '{}': {}", self.path, error))),
+            _ => Ok(()),
+        }
+    }
+
+    fn closed(&self) -> RuntimeError {
+        file_error(&format!("'{}' is closed", self.path))
+    }
+}
+
+fn file_error(message: &str) -> RuntimeError {
+    RuntimeError::with_code(Code::FileSystemError, message.to_string())
+}
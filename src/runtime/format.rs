@@ -0,0 +1,172 @@
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::diagnostic::Code;
+use crate::runtime::ExprEvaluator;
+use crate::runtime::Object;
+use crate::runtime::Resource;
+use crate::runtime::RuntimeError;
+
+// the widest width and precision a placeholder can ask for
+const MAX_WIDTH: usize = u16::MAX as usize;
+
+// The templates format() and printf() fill in. `{}` takes the next argument; after a colon it
+// can say how: `{:>8}` right aligns in 8 characters (`<` left, `^` centered, a character before
+// the alignment fills instead of spaces), `{:08}` pads a number with zeros after its sign and
+// `{:.2}` gives floats (and integers) 2 decimals or cuts strings to 2 characters. `{{` and `}}`
+// are literal braces. `{}` writes floats with the evaluator's `float_precision` decimals when
+// there is one. Widths and precisions go up to 65535, and padding has to fit the memory budget.
+pub fn format(evaluator: &ExprEvaluator, template: &str, args: &[Object]) -> Result<String, RuntimeError> {
+    let mut formatted = String::new();
+    let mut args = args.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                formatted.push('{');
+            },
+            '{' => {
+                let spec = placeholder(&mut chars)?;
+                let arg = args.next().ok_or_else(|| invalid("more placeholders than arguments".to_string()))?;
+                let text = spec.apply(evaluator, arg, formatted.len())?;
+                formatted.push_str(&text);
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                formatted.push('}');
+            },
+            '}' => return Err(invalid("unmatched '}', write '}}' for a literal brace".to_string())),
+            ch => formatted.push(ch),
+        }
+    }
+    if args.next().is_some() {
+        return Err(invalid("more arguments than placeholders".to_string()));
+    }
+    Ok(formatted)
+}
+
+fn invalid(message: String) -> RuntimeError {
+    RuntimeError::with_code(Code::InvalidArgument, format!("Invalid format: {}", message))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+struct Spec {
+    fill: char,
+    // None picks the default for the value: numbers go right, everything else left
+    align: Option<Align>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+// everything up to and including the closing brace, the opening one already taken
+fn placeholder(chars: &mut Peekable<Chars>) -> Result<Spec, RuntimeError> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(ch) => text.push(ch),
+            None => return Err(invalid("unterminated placeholder, write '{{' for a literal brace".to_string())),
+        }
+    }
+    let spec = match text.strip_prefix(':') {
+        Some(spec) => spec,
+        None if text.is_empty() => "",
+        None => return Err(invalid(format!("placeholders are '{{}}' or '{{:spec}}', found '{{{}}}'", text))),
+    };
+    let parsed = parse_spec(spec).ok_or_else(|| invalid(format!("bad specifier '{}'", spec)))?;
+    if parsed.width > MAX_WIDTH || parsed.precision.is_some_and(|precision| precision > MAX_WIDTH) {
+        return Err(invalid(format!("widths and precisions go up to {}, found '{}'", MAX_WIDTH, spec)));
+    }
+    Ok(parsed)
+}
+
+fn parse_spec(spec: &str) -> Option<Spec> {
+    let mut result = Spec{fill: ' ', align: None, zero: false, width: 0, precision: None};
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+    if chars.len() >= 2 && alignment(chars[1]).is_some() {
+        result.fill = chars[0];
+        result.align = alignment(chars[1]);
+        i = 2;
+    } else if let Some(align) = chars.first().and_then(|ch| alignment(*ch)) {
+        result.align = Some(align);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        result.zero = true;
+        i += 1;
+    }
+    let (width, next) = number(&chars, i);
+    result.width = width.unwrap_or(0);
+    i = next;
+    if chars.get(i) == Some(&'.') {
+        let (precision, next) = number(&chars, i + 1);
+        result.precision = Some(precision?);
+        i = next;
+    }
+    if i == chars.len() { Some(result) } else { None }
+}
+
+fn alignment(ch: char) -> Option<Align> {
+    match ch {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+// the digits starting at `start`, and where they end
+fn number(chars: &[char], start: usize) -> (Option<usize>, usize) {
+    let mut end = start;
+    while end < chars.len() && chars[end].is_ascii_digit() {
+        end += 1;
+    }
+    let digits: String = chars[start..end].iter().collect();
+    // too many digits for a usize is still too wide
+    let number = if digits.is_empty() { None } else { Some(digits.parse().unwrap_or(usize::MAX)) };
+    (number, end)
+}
+
+impl Spec {
+    // `written` is how much of the result there already is, which the budget has to fit as well
+    fn apply(&self, evaluator: &ExprEvaluator, value: &Object, written: usize) -> Result<String, RuntimeError> {
+        let numeric = matches!(value, Object::Integer(_) | Object::Float(_));
+        let text = match (value, self.precision) {
+            (Object::Float(float), Some(precision)) => format!("{:.*}", precision, float),
+            (Object::Integer(integer), Some(precision)) => format!("{:.*}", precision, *integer as f64),
+            (_, Some(precision)) => value.to_string().chars().take(precision).collect(),
+            (_, None) => match evaluator.config.float_precision {
+                Some(precision) => format!("{:.*}", precision, value),
+                None => value.to_string(),
+            },
+        };
+        let length = text.chars().count();
+        if length >= self.width {
+            return Ok(text);
+        }
+        let padding = self.width - length;
+        evaluator.afford(Resource::Memory, (written + text.len() + padding * self.fill.len_utf8()) as u64)?;
+        // zeros go between the sign and the digits; an explicit alignment fills instead
+        if self.zero && numeric && self.align.is_none() {
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", text.as_str()),
+            };
+            return Ok(format!("{}{}{}", sign, "0".repeat(padding), digits));
+        }
+        let fill = |count: usize| self.fill.to_string().repeat(count);
+        Ok(match self.align.unwrap_or(if numeric { Align::Right } else { Align::Left }) {
+            Align::Left => format!("{}{}", text, fill(padding)),
+            Align::Right => format!("{}{}", fill(padding), text),
+            Align::Center => format!("{}{}{}", fill(padding / 2), text, fill(padding - padding / 2)),
+        })
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use crate::parser::Resolution;
+use crate::runtime::Object;
+
+#[cfg(feature = "fxhash")]
+type Slots = HashMap<String, usize, rustc_hash::FxBuildHasher>;
+#[cfg(not(feature = "fxhash"))]
+type Slots = HashMap<String, usize>;
+
+// 0 is left for interpreters that don't remember slots
+static NEXT_OWNER: AtomicU32 = AtomicU32::new(1);
+
+// The top level's variables, natives included, kept apart from the scopes blocks and calls
+// push. A name gets a slot the first time it's defined and keeps it, so a reference the
+// resolver found can only mean a global remembers the slot and reads it without hashing the
+// name again.
+pub struct Globals {
+    // tells this interpreter's slots apart from another's in a program they share
+    owner: u32,
+    slots: Slots,
+    values: Vec<Object>,
+}
+
+impl Globals {
+    pub(crate) fn new() -> Globals {
+        // once every id has been handed out, later interpreters just look names up
+        let owner = NEXT_OWNER.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |owner| owner.checked_add(1)).unwrap_or(0);
+        Globals{owner, slots: Slots::default(), values: Vec::new()}
+    }
+
+    // for an interpreter that never runs anything
+    pub(crate) fn empty() -> Globals {
+        Globals{owner: 0, slots: Slots::default(), values: Vec::new()}
+    }
+
+    pub(crate) fn define(&mut self, name: String, value: Object) {
+        match self.slots.get(&name) {
+            Some(&slot) => self.values[slot] = value,
+            None => {
+                self.slots.insert(name, self.values.len());
+                self.values.push(value);
+            },
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.slots.get(name).map(|&slot| &self.values[slot])
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut Object> {
+        let slot = *self.slots.get(name)?;
+        Some(&mut self.values[slot])
+    }
+
+    // where `name` lives, remembered in the reference for next time
+    pub(crate) fn slot(&self, resolution: &Resolution, name: &str) -> Option<usize> {
+        if let Some(slot) = resolution.slot(self.owner) {
+            return Some(slot);
+        }
+        let slot = *self.slots.get(name)?;
+        resolution.remember(self.owner, slot);
+        Some(slot)
+    }
+
+    pub(crate) fn at(&self, slot: usize) -> &Object {
+        &self.values[slot]
+    }
+
+    pub(crate) fn at_mut(&mut self, slot: usize) -> &mut Object {
+        &mut self.values[slot]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Object)> {
+        self.slots.iter().map(move |(name, &slot)| (name, &self.values[slot]))
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use crate::diagnostic::Code;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+
+// A value that can be hashed, for looking things up by value (what memoize remembers calls by).
+// Keys are equal exactly when == calls the values equal, so 1 and 1.0 are the same key (and
+// 2^53 + 1 and 2.0^53 are not, == compares integers with floats exactly), and every NaN is the
+// same key even though NaN == NaN is false. Only values that can't change
+// can be keys: functions, coroutines, tasks and files carry state, so they can't.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Integer(i64),
+    // the bits of a float that isn't a whole number
+    Float(u64),
+    Boolean(bool),
+    String(Arc<str>),
+    List(Vec<Key>),
+}
+
+impl Key {
+    pub fn new(value: &Object) -> Result<Key, RuntimeError> {
+        Ok(match value {
+            Object::Nil() => Key::Nil,
+            Object::Integer(integer) => Key::Integer(*integer),
+            Object::Float(float) => float_key(*float),
+            Object::Boolean(boolean) => Key::Boolean(*boolean),
+            Object::StringLiteral(string) => Key::String(string.clone()),
+            Object::List(values) => Key::List(values.iter().map(Key::new).collect::<Result<_, _>>()?),
+            other => return Err(RuntimeError::with_code(Code::InvalidArgument, format!("{:?} can't be used as a key, only nil, numbers, booleans, strings and lists of those can", other))),
+        })
+    }
+}
+
+fn float_key(float: f64) -> Key {
+    // -0.0 == 0.0, and whole floats equal their integer
+    if float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64 {
+        return Key::Integer(float as i64);
+    }
+    if float.is_nan() {
+        return Key::Float(f64::NAN.to_bits());
+    }
+    Key::Float(float.to_bits())
+}
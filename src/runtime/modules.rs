@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::SystemTime;
+use crate::CraftyError;
+use crate::parser::Statement;
+use crate::scanner::token::Span;
+
+// a module being run, and where the import that started it is in the importing file
+pub struct Running {
+    pub path: PathBuf,
+    pub at: Span,
+}
+
+// What `import "name";` needs to find a module and run it only once.
+#[derive(Default)]
+pub struct Modules {
+    // searched in order for names that aren't relative paths
+    pub search_path: Vec<PathBuf>,
+    // the script being run, relative imports start from its directory
+    pub script: Option<PathBuf>,
+    // the modules being run, innermost last
+    pub running: Vec<Running>,
+    pub imported: HashSet<PathBuf>,
+    pub cache: ModuleCache,
+}
+
+impl Modules {
+    // The file `name` refers to, `.crafty` added when it has no extension. `./x` and `../x` (and
+    // absolute paths) are only looked up relative to the importing file; other names are tried
+    // in the importing file's directory, then in every search path entry in order, and the
+    // first file found wins. Err lists the places tried.
+    pub fn resolve(&self, name: &str) -> Result<PathBuf, Vec<PathBuf>> {
+        let mut file = PathBuf::from(name);
+        if file.extension().is_none() {
+            file.set_extension("crafty");
+        }
+        let directory = self.directory();
+        let explicit = name.starts_with("./") || name.starts_with("../") || file.is_absolute();
+        let candidates: Vec<PathBuf> = if explicit {
+            vec![directory.join(&file)]
+        } else {
+            std::iter::once(directory)
+                .chain(self.search_path.iter().map(PathBuf::as_path))
+                .map(|directory| directory.join(&file))
+                .collect()
+        };
+        match candidates.iter().find(|candidate| candidate.is_file()) {
+            Some(found) => Ok(found.canonicalize().unwrap_or_else(|_| found.to_path_buf())),
+            None => Err(candidates),
+        }
+    }
+
+    // where the code being run lives, the working directory for code that isn't in a file
+    fn directory(&self) -> &Path {
+        self.running.last().map(|running| &running.path).or(self.script.as_ref())
+            .and_then(|file| file.parent())
+            .unwrap_or_else(|| Path::new(""))
+    }
+
+    // When importing `path` at `at` would run a module that is already running, every import
+    // that leads back to it, as "file:line:column imports 'module'".
+    pub fn cycle(&self, path: &Path, at: Span) -> Option<Vec<String>> {
+        let is_script = self.script.as_ref().and_then(|script| script.canonicalize().ok()).is_some_and(|script| script == path);
+        let start = if is_script {
+            0
+        } else {
+            self.running.iter().position(|running| running.path == path)? + 1
+        };
+        let importer = |index: usize| match index {
+            0 => self.script.as_ref().map_or("<script>".to_string(), |script| script.display().to_string()),
+            index => self.running[index - 1].path.display().to_string(),
+        };
+        let describe = |importer: String, at: Span, imported: &Path| format!("{}:{}:{} imports '{}'", importer, at.line_number, at.column_number + 1, imported.display());
+        let mut chain: Vec<String> = (start..self.running.len())
+            .map(|index| describe(importer(index), self.running[index].at, &self.running[index].path))
+            .collect();
+        chain.push(describe(importer(self.running.len()), at, path));
+        Some(chain)
+    }
+}
+
+// Parsed modules, so a file that hasn't changed since it was last parsed isn't parsed again.
+// Clones share the same cache, so it can be handed to several interpreters. With the serde
+// feature the trees can also be kept on disk, for the next run of the program.
+#[derive(Clone, Default)]
+pub struct ModuleCache {
+    modules: Arc<Mutex<HashMap<PathBuf, Parsed>>>,
+    #[cfg(feature = "serde")]
+    directory: Option<PathBuf>,
+}
+
+// a file's modification time and length, what tells a changed module apart
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Stamp {
+    modified: SystemTime,
+    length: u64,
+}
+
+#[derive(Clone)]
+struct Parsed {
+    stamp: Stamp,
+    statements: Arc<Vec<Statement>>,
+}
+
+impl ModuleCache {
+    pub fn new() -> ModuleCache {
+        ModuleCache::default()
+    }
+
+    // also keep the parsed modules as files in `directory`, created when missing
+    #[cfg(feature = "serde")]
+    pub fn on_disk(mut self, directory: PathBuf) -> ModuleCache {
+        self.directory = Some(directory);
+        self
+    }
+
+    // the statements of the module at `path`, parsed only when the file changed; Err when it
+    // can't be read or doesn't parse
+    pub(crate) fn load(&self, path: &Path) -> Result<Arc<Vec<Statement>>, LoadError> {
+        let metadata = fs::metadata(path).map_err(LoadError::Read)?;
+        let stamp = Stamp{modified: metadata.modified().map_err(LoadError::Read)?, length: metadata.len()};
+
+        let mut modules = self.modules.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(parsed) = modules.get(path).filter(|parsed| parsed.stamp == stamp) {
+            return Ok(parsed.statements.clone());
+        }
+        let statements = match self.read_disk(path, stamp) {
+            Some(statements) => Arc::new(statements),
+            None => {
+                let source = fs::read_to_string(path).map_err(LoadError::Read)?;
+                let statements = crate::parse_source(&source).map_err(LoadError::Parse)?;
+                self.write_disk(path, stamp, &statements);
+                Arc::new(statements)
+            },
+        };
+        modules.insert(path.to_path_buf(), Parsed{stamp, statements: statements.clone()});
+        Ok(statements)
+    }
+
+    #[cfg(feature = "serde")]
+    fn read_disk(&self, path: &Path, stamp: Stamp) -> Option<Vec<Statement>> {
+        let file = fs::File::open(self.disk_file(path)?).ok()?;
+        let entry: DiskEntry = serde_json::from_reader(std::io::BufReader::new(file)).ok()?;
+        if entry.version == env!("CARGO_PKG_VERSION") && entry.path == path && entry.stamp == stamp {
+            Some(entry.statements)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn read_disk(&self, _path: &Path, _stamp: Stamp) -> Option<Vec<Statement>> {
+        None
+    }
+
+    // best effort, a cache that can't be written only means parsing again next time
+    #[cfg(feature = "serde")]
+    fn write_disk(&self, path: &Path, stamp: Stamp, statements: &[Statement]) {
+        let (directory, file) = match (&self.directory, self.disk_file(path)) {
+            (Some(directory), Some(file)) => (directory, file),
+            _ => return,
+        };
+        let entry = DiskEntry{version: env!("CARGO_PKG_VERSION").to_string(), path: path.to_path_buf(), stamp, statements: statements.to_vec()};
+        if let Ok(json) = serde_json::to_vec(&entry) {
+            // written aside and renamed, so another run never reads half a file
+            let partial = file.with_extension("partial");
+            let _ = fs::create_dir_all(directory)
+                .and_then(|_| fs::write(&partial, json))
+                .and_then(|_| fs::rename(&partial, &file));
+        }
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn write_disk(&self, _path: &Path, _stamp: Stamp, _statements: &[Statement]) {}
+
+    // one file per module, named after a hash of its path; the path is stored inside too, in
+    // case two of them hash the same
+    #[cfg(feature = "serde")]
+    fn disk_file(&self, path: &Path) -> Option<PathBuf> {
+        use std::hash::Hash;
+        use std::hash::Hasher;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        path.hash(&mut hasher);
+        self.directory.as_ref().map(|directory| directory.join(format!("{:016x}.json", hasher.finish())))
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DiskEntry {
+    // trees written by another version of crafty may not mean the same thing
+    version: String,
+    path: PathBuf,
+    stamp: Stamp,
+    statements: Vec<Statement>,
+}
+
+pub(crate) enum LoadError {
+    Read(std::io::Error),
+    Parse(CraftyError),
+}
@@ -0,0 +1,612 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::diagnostic::Code;
+use crate::runtime::Arity;
+use crate::runtime::Coroutine;
+use crate::runtime::ExprEvaluator;
+use crate::runtime::File;
+use crate::runtime::NativeFunction;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+use crate::runtime::coroutine;
+use crate::runtime::format;
+use crate::runtime::key::Key;
+use crate::runtime::is_truthy;
+use crate::runtime::tasks;
+
+pub fn define_natives(evaluator: &mut ExprEvaluator) {
+    evaluator.define_native("argc", Arity::Fixed(0), argc);
+    evaluator.define_native("argv", Arity::Fixed(1), argv);
+    evaluator.define_native("len", Arity::Fixed(1), len);
+    evaluator.define_native("substr", Arity::Fixed(3), substr);
+    evaluator.define_native("replace", Arity::Fixed(3), replace);
+    evaluator.define_native("starts_with", Arity::Fixed(2), starts_with);
+    evaluator.define_native("ends_with", Arity::Fixed(2), ends_with);
+    evaluator.define_native("chars", Arity::Fixed(1), chars);
+    evaluator.define_native("map", Arity::Fixed(2), map);
+    evaluator.define_native("filter", Arity::Fixed(2), filter);
+    evaluator.define_native("reduce", Arity::Fixed(3), reduce);
+    evaluator.define_native("sort", Arity::Variadic, sort);
+    evaluator.define_native("apply", Arity::Fixed(2), |evaluator, args| {
+        let arguments = list_argument("apply", &args[1])?.to_vec();
+        evaluator.call(args[0].clone(), arguments)
+    });
+    evaluator.define_native("memoize", Arity::Fixed(1), memoize);
+    evaluator.define_native("divmod", Arity::Fixed(2), divmod);
+    evaluator.define_native("clock", Arity::Fixed(0), clock);
+    evaluator.define_native("now", Arity::Fixed(0), now);
+    evaluator.define_native("sleep", Arity::Fixed(1), sleep);
+    evaluator.define_native("int", Arity::Fixed(1), int);
+    evaluator.define_native("float", Arity::Fixed(1), float);
+    evaluator.define_native("str", Arity::Fixed(1), str);
+    evaluator.define_native("bool", Arity::Fixed(1), bool);
+    evaluator.define_native("type", Arity::Fixed(1), type_of);
+    evaluator.define_native("input", Arity::Fixed(1), input);
+    evaluator.define_native("exit", Arity::Fixed(1), exit);
+    evaluator.define_native("error", Arity::Fixed(1), error);
+    evaluator.define_native("env", Arity::Fixed(1), get_env);
+    evaluator.define_native("set_env", Arity::Fixed(2), set_env);
+    evaluator.define_native("list_dir", Arity::Fixed(1), list_dir);
+    evaluator.define_native("mkdir", Arity::Fixed(1), mkdir);
+    evaluator.define_native("remove_file", Arity::Fixed(1), remove_file);
+    evaluator.define_native("open", Arity::Variadic, open);
+    evaluator.define_native("read_line", Arity::Fixed(1), read_line);
+    evaluator.define_native("write_line", Arity::Fixed(2), write_line);
+    evaluator.define_native("close", Arity::Fixed(1), |_, args| {
+        file_argument("close", &args[0])?.close()?;
+        Ok(Object::Nil())
+    });
+    evaluator.define_native("join_path", Arity::Variadic, join_path);
+    evaluator.define_native("basename", Arity::Fixed(1), basename);
+    evaluator.define_native("format", Arity::Variadic, format);
+    evaluator.define_native("printf", Arity::Variadic, printf);
+    evaluator.define_native("write", Arity::Fixed(1), write);
+    evaluator.define_native("eprint", Arity::Fixed(1), eprint);
+    evaluator.define_native("help", Arity::Fixed(1), help);
+    evaluator.define_native("coroutine", Arity::Fixed(1), |_, args| Ok(Object::Coroutine(Coroutine::new(args[0].clone())?)));
+    evaluator.define_native("resume", Arity::Variadic, resume);
+    evaluator.define_native("yield", Arity::Variadic, yield_value);
+    evaluator.define_native("coroutine_status", Arity::Fixed(1), coroutine_status);
+    evaluator.define_native("spawn", Arity::Variadic, spawn);
+    evaluator.define_native("delay", Arity::Fixed(1), delay);
+    evaluator.define_native("is_nil", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::Nil()))));
+    evaluator.define_native("is_integer", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::Integer(_)))));
+    evaluator.define_native("is_float", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::Float(_)))));
+    evaluator.define_native("is_number", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::Integer(_) | Object::Float(_)))));
+    evaluator.define_native("is_bool", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::Boolean(_)))));
+    evaluator.define_native("is_string", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::StringLiteral(_)))));
+    evaluator.define_native("is_list", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::List(_)))));
+    evaluator.define_native("is_function", Arity::Fixed(1), |_, args| Ok(Object::Boolean(matches!(args[0], Object::NativeFunction(_) | Object::Function(_)))));
+}
+
+fn string_argument<'a>(native: &str, value: &'a Object) -> Result<&'a str, RuntimeError> {
+    match value {
+        Object::StringLiteral(string) => Ok(string),
+        other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("{} expects a string, received {:?}", native, other))),
+    }
+}
+
+fn integer_argument(native: &str, value: &Object) -> Result<i64, RuntimeError> {
+    match value {
+        Object::Integer(integer) => Ok(*integer),
+        other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("{} expects an integer, received {:?}", native, other))),
+    }
+}
+
+fn list_argument<'a>(native: &str, value: &'a Object) -> Result<&'a [Object], RuntimeError> {
+    match value {
+        Object::List(values) => Ok(values),
+        other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("{} expects a list, received {:?}", native, other))),
+    }
+}
+
+fn argc(evaluator: &mut ExprEvaluator, _args: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::Integer(evaluator.script_args.len() as i64))
+}
+
+fn argv(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Integer(index) if *index >= 0 && (*index as usize) < evaluator.script_args.len() =>
+            Ok(Object::StringLiteral(evaluator.script_args[*index as usize].to_string().into())),
+        Object::Integer(index) => Err(RuntimeError::with_code(Code::InvalidArgument, format!("argv index {} out of range, argc() is {}", index, evaluator.script_args.len()))),
+        other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("argv expects an integer index, received {:?}", other))),
+    }
+}
+
+// strings are measured in characters, not bytes
+fn len(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::StringLiteral(string) => Ok(Object::Integer(string.chars().count() as i64)),
+        Object::List(values) => Ok(Object::Integer(values.len() as i64)),
+        other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("len expects a string or a list, received {:?}", other))),
+    }
+}
+
+fn map(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let values = list_argument("map", &args[0])?;
+    let mut mapped = Vec::with_capacity(values.len());
+    for value in values {
+        mapped.push(evaluator.call(args[1].clone(), vec![value.clone()])?);
+    }
+    Ok(Object::List(mapped.into()))
+}
+
+// keeps the values the function returns something truthy for
+fn filter(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let values = list_argument("filter", &args[0])?;
+    let mut kept = Vec::new();
+    for value in values {
+        let keep = evaluator.call(args[1].clone(), vec![value.clone()])?;
+        if evaluator.is_truthy(&keep) {
+            kept.push(value.clone());
+        }
+    }
+    Ok(Object::List(kept.into()))
+}
+
+// reduce(list, init, fn) calls fn(total, value) for each value, starting from init
+fn reduce(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let values = list_argument("reduce", &args[0])?;
+    let mut total = args[1].clone();
+    for value in values {
+        total = evaluator.call(args[2].clone(), vec![total, value.clone()])?;
+    }
+    Ok(total)
+}
+
+// Stable. sort(list) orders numbers or strings, sort(list, cmp) calls cmp(a, b), which returns
+// a negative number (or true) when a goes before b.
+fn sort(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(RuntimeError::with_code(Code::WrongArity, format!("Expected 1 or 2 arguments but got {}", args.len())));
+    }
+    let values = list_argument("sort", &args[0])?.to_vec();
+    let cmp = args.get(1);
+    let sorted = merge_sort(values, &mut |a, b| match cmp {
+        Some(cmp) => match evaluator.call(cmp.clone(), vec![a.clone(), b.clone()])? {
+            Object::Integer(order) => Ok(order < 0),
+            Object::Float(order) => Ok(order < 0.0),
+            Object::Boolean(before) => Ok(before),
+            other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("sort expects the comparison to return a number or a boolean, received {:?}", other))),
+        },
+        None => natural_order(a, b).map(|order| order == Ordering::Less),
+    })?;
+    Ok(Object::List(sorted.into()))
+}
+
+// Sorting by hand since the comparison can fail, and a script's needn't be consistent, which
+// the standard library's sorts are allowed to panic on.
+fn merge_sort(mut values: Vec<Object>, before: &mut dyn FnMut(&Object, &Object) -> Result<bool, RuntimeError>) -> Result<Vec<Object>, RuntimeError> {
+    if values.len() <= 1 {
+        return Ok(values);
+    }
+    let right = values.split_off(values.len() / 2);
+    let left = merge_sort(values, before)?;
+    let right = merge_sort(right, before)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // equal values keep their order
+        if before(b, a)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+fn natural_order(a: &Object, b: &Object) -> Result<Ordering, RuntimeError> {
+    let order = match (a, b) {
+        (Object::Integer(a), Object::Integer(b)) => Some(a.cmp(b)),
+        (Object::Integer(a), Object::Float(b)) => (*a as f64).partial_cmp(b),
+        (Object::Float(a), Object::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Object::Float(a), Object::Float(b)) => a.partial_cmp(b),
+        (Object::StringLiteral(a), Object::StringLiteral(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    order.ok_or_else(|| RuntimeError::with_code(Code::InvalidArgument, format!("sort can't order {:?} and {:?} without a comparison", a, b)))
+}
+
+// A function that calls the given one once for each distinct set of arguments and then answers
+// from what it returned. Arguments that can't be keys (functions, coroutines, tasks) aren't
+// remembered; equal ones are, so f(1) answers for f(1.0) too.
+fn memoize(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let (name, arity) = match &args[0] {
+        Object::Function(function) if function.declaration.variadic => (function.name().to_string(), Arity::Variadic),
+        Object::Function(function) => (function.name().to_string(), Arity::Fixed(function.declaration.parameters.len())),
+        Object::NativeFunction(native) => (native.name.clone(), native.arity),
+        other => return Err(RuntimeError::with_code(Code::InvalidArgument, format!("memoize expects a function, received {:?}", other))),
+    };
+    let function = args[0].clone();
+    let cache: Mutex<HashMap<Key, Object>> = Mutex::new(HashMap::new());
+    Ok(Object::NativeFunction(NativeFunction{
+        name,
+        arity,
+        function: Arc::new(move |evaluator, args| {
+            let key = match Key::new(&Object::List(Arc::new(args.to_vec()))) {
+                Ok(key) => key,
+                Err(_) => return evaluator.call(function.clone(), args.to_vec()),
+            };
+            if let Some(value) = cache.lock().unwrap().get(&key) {
+                return Ok(value.clone());
+            }
+            // not holding the lock, the call is likely to come back here
+            let value = evaluator.call(function.clone(), args.to_vec())?;
+            cache.lock().unwrap().insert(key, value.clone());
+            Ok(value)
+        }),
+    }))
+}
+
+// `length` characters from `start`, all of which have to be in the string
+fn substr(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let string = string_argument("substr", &args[0])?;
+    let start = integer_argument("substr", &args[1])?;
+    let length = integer_argument("substr", &args[2])?;
+    let count = string.chars().count() as i64;
+    if start < 0 || length < 0 || start > count || length > count - start {
+        return Err(RuntimeError::with_code(Code::IndexOutOfRange, format!("substr of {} characters from {} is out of range for a string of length {}", length, start, count)));
+    }
+    Ok(Object::StringLiteral(string.chars().skip(start as usize).take(length as usize).collect::<String>().into()))
+}
+
+// every occurrence
+fn replace(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let string = string_argument("replace", &args[0])?;
+    let from = string_argument("replace", &args[1])?;
+    let to = string_argument("replace", &args[2])?;
+    if from.is_empty() {
+        return Err(RuntimeError::with_code(Code::InvalidArgument, "replace can't search for an empty string".to_string()));
+    }
+    Ok(Object::StringLiteral(string.replace(from, to).into()))
+}
+
+fn starts_with(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let string = string_argument("starts_with", &args[0])?;
+    let prefix = string_argument("starts_with", &args[1])?;
+    Ok(Object::Boolean(string.starts_with(prefix)))
+}
+
+fn ends_with(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let string = string_argument("ends_with", &args[0])?;
+    let suffix = string_argument("ends_with", &args[1])?;
+    Ok(Object::Boolean(string.ends_with(suffix)))
+}
+
+// a list of one character strings
+fn chars(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let string = string_argument("chars", &args[0])?;
+    Ok(Object::List(Arc::new(string.chars().map(|ch| Object::StringLiteral(ch.to_string().into())).collect())))
+}
+
+// [a ~/ b, what's left over], the one list handy for a, b = divmod(a, b)
+fn divmod(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    match (&args[0], &args[1]) {
+        (Object::Integer(_) | Object::Float(_), Object::Integer(_) | Object::Float(_)) => {
+            let (quotient, remainder) = evaluator.divmod(args[0].clone(), args[1].clone())?;
+            Ok(Object::List(vec![quotient, remainder].into()))
+        },
+        (lhs, rhs) => Err(RuntimeError::with_code(Code::InvalidArgument, format!("divmod expects two numbers, received {:?} and {:?}", lhs, rhs))),
+    }
+}
+
+// seconds as a float, only good for measuring how long something took
+fn clock(evaluator: &mut ExprEvaluator, _args: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::Float(evaluator.clock.monotonic()))
+}
+
+// milliseconds since the unix epoch
+fn now(evaluator: &mut ExprEvaluator, _args: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::Integer(evaluator.clock.epoch_millis()))
+}
+
+fn sleep(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let millis = integer_argument("sleep", &args[0])?;
+    if millis < 0 {
+        return Err(RuntimeError::with_code(Code::InvalidArgument, format!("sleep expects a duration of at least 0 milliseconds, received {}", millis)));
+    }
+    evaluator.clock.sleep(Duration::from_millis(millis as u64));
+    Ok(Object::Nil())
+}
+
+fn conversion_failed(target: &str, value: &Object) -> RuntimeError {
+    RuntimeError::with_code(Code::ConversionFailed, format!("can't convert {:?} to {}", value, target))
+}
+
+// floats truncate toward zero, strings have to hold a whole number (surrounding whitespace is fine)
+fn int(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Integer(integer) => Ok(Object::Integer(*integer)),
+        // the cast saturates, so anything outside i64 (or nan) has to be ruled out first; i64::MAX as f64 is 2^63
+        Object::Float(float) if (i64::MIN as f64..i64::MAX as f64).contains(float) =>
+            Ok(Object::Integer(*float as i64)),
+        Object::Boolean(boolean) => Ok(Object::Integer(*boolean as i64)),
+        Object::StringLiteral(string) => string.trim().parse::<i64>()
+            .map(Object::Integer)
+            .map_err(|_| conversion_failed("int", &args[0])),
+        other => Err(conversion_failed("int", other)),
+    }
+}
+
+// "nan" and "inf" aren't numbers a script could have written, so they don't parse either
+fn float(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Integer(integer) => Ok(Object::Float(*integer as f64)),
+        Object::Float(float) => Ok(Object::Float(*float)),
+        Object::Boolean(boolean) => Ok(Object::Float(if *boolean { 1.0 } else { 0.0 })),
+        Object::StringLiteral(string) => match string.trim().parse::<f64>() {
+            Ok(float) if float.is_finite() => Ok(Object::Float(float)),
+            _ => Err(conversion_failed("float", &args[0])),
+        },
+        other => Err(conversion_failed("float", other)),
+    }
+}
+
+// the same text print would write
+fn str(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::StringLiteral(evaluator.display(&args[0]).into()))
+}
+
+// the same truthiness if and while use
+fn bool(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::Boolean(is_truthy(&args[0])))
+}
+
+fn type_of(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::StringLiteral(args[0].type_name().to_string().into()))
+}
+
+// the line without its line ending, nil once the input is exhausted
+fn input(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let prompt = args[0].to_string();
+    Ok(match evaluator.read_line(&prompt)? {
+        Some(line) => Object::StringLiteral(line.into()),
+        None => Object::Nil(),
+    })
+}
+
+// stops the program; the CLI exits with the status
+fn exit(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let status = integer_argument("exit", &args[0])?;
+    // a process can only exit with the low 8 bits, more would wrap around
+    match u8::try_from(status) {
+        Ok(status) => Err(RuntimeError::exit(status as i32)),
+        Err(_) => Err(RuntimeError::with_code(Code::InvalidArgument, format!("exit status {} out of range, statuses go from 0 to 255", status))),
+    }
+}
+
+// fails like any other runtime error, reported at the call
+fn error(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    Err(RuntimeError::new(args[0].to_string()))
+}
+
+fn check_host_access(evaluator: &ExprEvaluator, native: &str) -> Result<(), RuntimeError> {
+    if evaluator.config.sandboxed {
+        return Err(RuntimeError::with_code(Code::HostAccessDenied, format!("{} is not available in a sandboxed interpreter", native)));
+    }
+    Ok(())
+}
+
+// nil when the variable isn't set (or isn't valid unicode)
+fn get_env(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    check_host_access(evaluator, "env")?;
+    let name = string_argument("env", &args[0])?;
+    Ok(match env::var(name) {
+        Ok(value) => Object::StringLiteral(value.into()),
+        Err(_) => Object::Nil(),
+    })
+}
+
+// for the rest of the process, child processes included
+fn set_env(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    check_host_access(evaluator, "set_env")?;
+    let name = string_argument("set_env", &args[0])?;
+    let value = string_argument("set_env", &args[1])?;
+    // set_var panics on these rather than failing
+    if name.is_empty() || name.contains('=') || name.contains('\0') || value.contains('\0') {
+        return Err(RuntimeError::with_code(Code::InvalidArgument, format!("set_env can't set {:?} to {:?}", name, value)));
+    }
+    env::set_var(name, value);
+    Ok(Object::Nil())
+}
+
+fn file_system_error(action: &str, path: &str, error: io::Error) -> RuntimeError {
+    RuntimeError::with_code(Code::FileSystemError, format!("Could not {} '{}': {}", action, path, error))
+}
+
+// the names of the entries, sorted so scripts behave the same on every platform
+fn list_dir(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    check_host_access(evaluator, "list_dir")?;
+    let path = string_argument("list_dir", &args[0])?;
+    let mut names = fs::read_dir(path)
+        .and_then(|entries| entries
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<String>, io::Error>>())
+        .map_err(|error| file_system_error("list", path, error))?;
+    names.sort();
+    Ok(Object::List(Arc::new(names.into_iter().map(|name| Object::StringLiteral(name.into())).collect())))
+}
+
+// missing parents are created too, and a directory that already exists is fine
+fn mkdir(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    check_host_access(evaluator, "mkdir")?;
+    let path = string_argument("mkdir", &args[0])?;
+    fs::create_dir_all(path).map_err(|error| file_system_error("create", path, error))?;
+    Ok(Object::Nil())
+}
+
+fn remove_file(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    check_host_access(evaluator, "remove_file")?;
+    let path = string_argument("remove_file", &args[0])?;
+    fs::remove_file(path).map_err(|error| file_system_error("remove", path, error))?;
+    Ok(Object::Nil())
+}
+
+// open(path) reads the file, open(path, mode) takes "r", "w" or "a" like File::open
+fn open(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(RuntimeError::with_code(Code::WrongArity, format!("Expected 1 or 2 arguments but got {}", args.len())));
+    }
+    check_host_access(evaluator, "open")?;
+    let path = string_argument("open", &args[0])?;
+    let mode = match args.get(1) {
+        Some(mode) => string_argument("open", mode)?,
+        None => "r",
+    };
+    Ok(Object::File(File::open(path, mode)?))
+}
+
+// nil at the end of the file
+fn read_line(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let line = file_argument("read_line", &args[0])?.read_line()?;
+    Ok(line.map_or(Object::Nil(), |line| Object::StringLiteral(line.into())))
+}
+
+// writes the value as print would, newline included
+fn write_line(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    file_argument("write_line", &args[0])?.write(&format!("{}\n", evaluator.display(&args[1])))?;
+    Ok(Object::Nil())
+}
+
+fn file_argument<'a>(native: &str, value: &'a Object) -> Result<&'a File, RuntimeError> {
+    match value {
+        Object::File(file) => Ok(file),
+        other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("{} expects a file, received {:?}", native, other))),
+    }
+}
+
+// only manipulates strings, so it works in a sandbox; an absolute part replaces what came before it
+fn join_path(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    if args.is_empty() {
+        return Err(RuntimeError::with_code(Code::WrongArity, "Expected at least 1 argument but got 0".to_string()));
+    }
+    let mut path = PathBuf::new();
+    for arg in args {
+        path.push(string_argument("join_path", arg)?);
+    }
+    Ok(Object::StringLiteral(path.to_string_lossy().into_owned().into()))
+}
+
+fn coroutine_argument<'a>(native: &str, value: &'a Object) -> Result<&'a Coroutine, RuntimeError> {
+    match value {
+        Object::Coroutine(coroutine) => Ok(coroutine),
+        other => Err(RuntimeError::with_code(Code::InvalidArgument, format!("{} expects a coroutine, received {:?}", native, other))),
+    }
+}
+
+// resume(co) and yield() hand over nil
+fn resume(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(RuntimeError::with_code(Code::WrongArity, format!("Expected 1 or 2 arguments but got {}", args.len())));
+    }
+    let coroutine = coroutine_argument("resume", &args[0])?;
+    coroutine.resume(evaluator, args.get(1).cloned().unwrap_or(Object::Nil()))
+}
+
+fn yield_value(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    if args.len() > 1 {
+        return Err(RuntimeError::with_code(Code::WrongArity, format!("Expected at most 1 argument but got {}", args.len())));
+    }
+    coroutine::yield_value(evaluator, args.first().cloned().unwrap_or(Object::Nil()))
+}
+
+fn coroutine_status(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let coroutine = coroutine_argument("coroutine_status", &args[0])?;
+    Ok(Object::StringLiteral(coroutine.status().name().to_string().into()))
+}
+
+// spawn(fn, args...) calls fn(args...) as a task
+fn spawn(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let (function, arguments) = match args.split_first() {
+        Some((function @ (Object::Function(_) | Object::NativeFunction(_)), arguments)) => (function.clone(), arguments.to_vec()),
+        Some((other, _)) => return Err(RuntimeError::with_code(Code::InvalidArgument, format!("spawn expects a function, received {:?}", other))),
+        None => return Err(RuntimeError::with_code(Code::WrongArity, "Expected at least 1 argument but got 0".to_string())),
+    };
+    Ok(Object::Task(tasks::spawn(evaluator, function, arguments)?))
+}
+
+fn delay(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let millis = integer_argument("delay", &args[0])?;
+    if millis < 0 {
+        return Err(RuntimeError::with_code(Code::InvalidArgument, format!("delay expects a duration of at least 0 milliseconds, received {}", millis)));
+    }
+    Ok(Object::Task(tasks::delay(evaluator, Duration::from_millis(millis as u64))))
+}
+
+// the last component, "" for paths like "/" that don't have one
+fn basename(_evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let path = string_argument("basename", &args[0])?;
+    Ok(Object::StringLiteral(Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default().into()))
+}
+
+// the template and its arguments, see format::format for the placeholders
+fn format_arguments(evaluator: &ExprEvaluator, native: &str, args: &[Object]) -> Result<String, RuntimeError> {
+    match args.split_first() {
+        Some((template, args)) => format::format(evaluator, string_argument(native, template)?, args),
+        None => Err(RuntimeError::with_code(Code::WrongArity, "Expected at least 1 argument but got 0".to_string())),
+    }
+}
+
+fn format(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::StringLiteral(format_arguments(evaluator, "format", args)?.into()))
+}
+
+// like print, but without the newline unless the template ends with one
+fn printf(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let text = format_arguments(evaluator, "printf", args)?;
+    print_raw(evaluator, &text)
+}
+
+// print without the newline
+fn write(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let text = evaluator.display(&args[0]);
+    print_raw(evaluator, &text)
+}
+
+// print, but to the error output
+fn eprint(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    if !evaluator.config.allow_print {
+        return Err(RuntimeError::with_code(Code::PrintDisabled, "print is disabled".to_string()));
+    }
+    let text = evaluator.display(&args[0]);
+    evaluator.write_error_output(&format!("{}\n", text))?;
+    Ok(Object::Nil())
+}
+
+// prints how a function is declared and its docstring, indented under it
+fn help(evaluator: &mut ExprEvaluator, args: &[Object]) -> Result<Object, RuntimeError> {
+    let (signature, doc) = match &args[0] {
+        Object::Function(function) => (function.declaration.signature(), function.doc()),
+        Object::NativeFunction(native) => (format!("{:?}", native), None),
+        other => return Err(RuntimeError::with_code(Code::InvalidArgument, format!("help expects a function, received {:?}", other))),
+    };
+    let mut text = format!("{}\n", signature);
+    for line in doc.unwrap_or_else(|| "No documentation.".to_string()).lines() {
+        // blank lines stay blank
+        text.push_str(format!("  {}", line).trim_end());
+        text.push('\n');
+    }
+    print_raw(evaluator, &text)
+}
+
+fn print_raw(evaluator: &mut ExprEvaluator, text: &str) -> Result<Object, RuntimeError> {
+    if !evaluator.config.allow_print {
+        return Err(RuntimeError::with_code(Code::PrintDisabled, "print is disabled".to_string()));
+    }
+    evaluator.write_output(text)?;
+    Ok(Object::Nil())
+}
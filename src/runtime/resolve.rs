@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+
+// Marks the variable references in a program run at the top level that can only mean a global.
+// The scopes a reference can see are those of the blocks, loops and `with`s around it in the
+// same function (calls set the caller's aside), so a name none of them declares anywhere can't
+// be a local. Nested scopes are lumped in with the outermost one, which only ever leaves a
+// reference unmarked, and unmarked references are looked up through every scope as before.
+pub fn resolve(statements: &[Statement]) {
+    for statement in statements.iter() {
+        statement_at_top(statement);
+    }
+}
+
+// a statement outside every scope, declarations here are globals
+fn statement_at_top(statement: &Statement) {
+    match &statement.kind {
+        StatementKind::Block(_) | StatementKind::For(..) | StatementKind::With(..) => {
+            let mut locals = HashSet::new();
+            declarations(statement, &mut locals);
+            self::statement(statement, Some(&locals));
+        },
+        StatementKind::If(condition, then_branch, else_branch) => {
+            expr(condition, None);
+            statement_at_top(then_branch);
+            if let Some(else_branch) = else_branch {
+                statement_at_top(else_branch);
+            }
+        },
+        StatementKind::While(condition, body) => {
+            expr(condition, None);
+            statement_at_top(body);
+        },
+        _ => self::statement(statement, None),
+    }
+}
+
+// Every name declared in the statement, other than inside functions it declares. Some are only
+// in scope part of the time, which at worst leaves a global reference unmarked.
+pub(crate) fn declarations(statement: &Statement, names: &mut HashSet<String>) {
+    match &statement.kind {
+        StatementKind::Var(name, _, _) => {
+            names.insert(name.lexeme.to_string());
+        },
+        StatementKind::With(name, _, body) => {
+            names.insert(name.lexeme.to_string());
+            declarations(body, names);
+        },
+        StatementKind::VarTuple(tokens, _) => names.extend(tokens.iter().map(|name| name.lexeme.to_string())),
+        StatementKind::Function(declaration) => {
+            names.insert(declaration.name.lexeme.to_string());
+        },
+        StatementKind::Block(statements) => statements.iter().for_each(|statement| declarations(statement, names)),
+        StatementKind::If(_, then_branch, else_branch) => {
+            declarations(then_branch, names);
+            if let Some(else_branch) = else_branch {
+                declarations(else_branch, names);
+            }
+        },
+        StatementKind::While(_, body) => declarations(body, names),
+        StatementKind::For(initializer, _, _, body) => {
+            if let Some(initializer) = initializer {
+                declarations(initializer, names);
+            }
+            declarations(body, names);
+        },
+        StatementKind::Expression(_) | StatementKind::Print(_) | StatementKind::AssignTuple(..)
+            | StatementKind::Return(..) | StatementKind::Import(..) => {},
+    }
+}
+
+// `locals` is every name the scopes around the statement declare, None outside them all
+fn statement(statement: &Statement, locals: Option<&HashSet<String>>) {
+    match &statement.kind {
+        StatementKind::Expression(value) | StatementKind::Print(value) => expr(value, locals),
+        StatementKind::If(condition, then_branch, else_branch) => {
+            expr(condition, locals);
+            self::statement(then_branch, locals);
+            if let Some(else_branch) = else_branch {
+                self::statement(else_branch, locals);
+            }
+        },
+        StatementKind::Var(_, _, value) | StatementKind::Return(_, value) => {
+            if let Some(value) = value {
+                expr(value, locals);
+            }
+        },
+        StatementKind::VarTuple(_, value) | StatementKind::AssignTuple(_, value) => expr(value, locals),
+        StatementKind::While(condition, body) => {
+            expr(condition, locals);
+            self::statement(body, locals);
+        },
+        StatementKind::With(_, resource, body) => {
+            expr(resource, locals);
+            self::statement(body, locals);
+        },
+        StatementKind::For(initializer, condition, increment, body) => {
+            if let Some(initializer) = initializer {
+                self::statement(initializer, locals);
+            }
+            if let Some(condition) = condition {
+                expr(condition, locals);
+            }
+            if let Some(increment) = increment {
+                expr(increment, locals);
+            }
+            self::statement(body, locals);
+        },
+        StatementKind::Block(statements) => statements.iter().for_each(|statement| self::statement(statement, locals)),
+        StatementKind::Function(declaration) => {
+            for decorator in declaration.decorators.iter() {
+                expr(decorator, locals);
+            }
+            // the body only sees its own scopes and the globals
+            let mut body_locals: HashSet<String> = declaration.parameters.iter()
+                .map(|parameter| parameter.name.lexeme.to_string())
+                .collect();
+            for statement in declaration.body.iter() {
+                declarations(statement, &mut body_locals);
+            }
+            for statement in declaration.body.iter() {
+                self::statement(statement, Some(&body_locals));
+            }
+        },
+        StatementKind::Import(..) => {},
+    }
+}
+
+fn expr(e: &Expr, locals: Option<&HashSet<String>>) {
+    match &e.kind {
+        ExprKind::Variable(name) | ExprKind::Assign(name, _) => {
+            if !locals.is_some_and(|locals| locals.contains(&name.lexeme)) {
+                e.resolution.mark_global();
+            }
+            if let ExprKind::Assign(_, value) = &e.kind {
+                expr(value, locals);
+            }
+        },
+        ExprKind::Grouping(inner) | ExprKind::Unary(_, inner) | ExprKind::Spread(inner) => expr(inner, locals),
+        ExprKind::Binary(lhs, _, rhs) | ExprKind::Logical(lhs, _, rhs) | ExprKind::Index(lhs, _, rhs) => {
+            expr(lhs, locals);
+            expr(rhs, locals);
+        },
+        ExprKind::Call(callee, _, arguments) => {
+            expr(callee, locals);
+            arguments.iter().for_each(|argument| expr(argument, locals));
+        },
+        ExprKind::List(elements) | ExprKind::Tuple(elements) => elements.iter().for_each(|element| expr(element, locals)),
+        ExprKind::Operator(..) | ExprKind::BoolLiteral(_) | ExprKind::NilLiteral | ExprKind::StringLiteral(_)
+            | ExprKind::IntegerLiteral(_) | ExprKind::FloatLiteral(_) => {},
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::diagnostic::Code;
+use crate::runtime::coroutine;
+use crate::runtime::Arity;
+use crate::runtime::Coroutine;
+use crate::runtime::CoroutineStatus;
+use crate::runtime::ExprEvaluator;
+use crate::runtime::NativeFunction;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+
+// Something running on the event loop: a call to an async function, a spawned function or a
+// timer. Tasks take turns on the one interpreter, each running until it awaits something
+// unfinished (or calls yield()) and then letting the next ready one go. All but timers run as a
+// coroutine, and so on a thread of their own until they finish.
+#[derive(Clone)]
+pub struct Task {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    // None for timers, which only wait
+    coroutine: Option<Coroutine>,
+    result: Option<Result<Object, RuntimeError>>,
+    // tasks to run again once this one finishes
+    waiters: Vec<Task>,
+}
+
+// The tasks that haven't finished, kept by the interpreter.
+#[derive(Default)]
+pub struct EventLoop {
+    ready: VecDeque<Task>,
+    // when each timer goes off, in clock() seconds
+    timers: Vec<(f64, Task)>,
+    // how many coroutines deep the task being run is, where its await hands control back
+    running: Option<usize>,
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<task>")
+    }
+}
+
+impl Task {
+    // the same task rather than a copy of it
+    pub fn same(&self, other: &Task) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    fn new(coroutine: Option<Coroutine>) -> Task {
+        Task{state: Arc::new(Mutex::new(State{coroutine, result: None, waiters: Vec::new()}))}
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state.lock().unwrap().result.is_some()
+    }
+
+    fn result(&self) -> Option<Result<Object, RuntimeError>> {
+        self.state.lock().unwrap().result.clone()
+    }
+}
+
+// Starts calling `function` with `arguments` as a new task; it first runs when something awaits.
+pub fn spawn(evaluator: &mut ExprEvaluator, function: Object, arguments: Vec<Object>) -> Result<Task, RuntimeError> {
+    if let Object::Function(function) = &function {
+        function.check_arity(arguments.len())?;
+    }
+    let name = format!("{:?}", function);
+    // an async function's body runs directly, calling it again would only start another task
+    let body = NativeFunction{
+        name,
+        arity: Arity::Fixed(0),
+        function: Arc::new(move |evaluator, _| match &function {
+            Object::Function(function) => evaluator.call_function(function, arguments.clone()),
+            other => evaluator.call(other.clone(), arguments.clone()),
+        }),
+    };
+    let task = Task::new(Some(Coroutine::new(Object::NativeFunction(body))?));
+    evaluator.event_loop.ready.push_back(task.clone());
+    Ok(task)
+}
+
+// A task that finishes with nil once `duration` has passed.
+pub fn delay(evaluator: &mut ExprEvaluator, duration: Duration) -> Task {
+    let task = Task::new(None);
+    let at = evaluator.clock.monotonic() + duration.as_secs_f64();
+    evaluator.event_loop.timers.push((at, task.clone()));
+    task
+}
+
+// What `await` does: a finished task gives its result (or raises its error) and anything else
+// isn't waited on. Inside a task the task is suspended until `value` finishes; elsewhere the
+// event loop runs right here until it does.
+pub fn wait(evaluator: &mut ExprEvaluator, value: Object) -> Result<Object, RuntimeError> {
+    let task = match value {
+        Object::Task(task) => task,
+        other => return Ok(other),
+    };
+    loop {
+        if let Some(result) = task.result() {
+            return result;
+        }
+        if evaluator.event_loop.running == Some(evaluator.resumers.len()) {
+            coroutine::yield_value(evaluator, Object::Task(task.clone()))?;
+        } else if !step(evaluator)? {
+            return Err(RuntimeError::with_code(Code::CoroutineError, "Awaiting a task that can never finish, every task left is waiting on another".to_string()));
+        }
+    }
+}
+
+// Runs the next ready task until it awaits, yields or finishes, or waits for the next timer when
+// none is ready. False when nothing is left that could ever run.
+fn step(evaluator: &mut ExprEvaluator) -> Result<bool, RuntimeError> {
+    fire_due_timers(evaluator);
+    let task = match evaluator.event_loop.ready.pop_front() {
+        Some(task) => task,
+        None => return Ok(sleep_until_timer(evaluator)),
+    };
+    let coroutine = match task.state.lock().unwrap().coroutine.clone() {
+        Some(coroutine) => coroutine,
+        None => return Ok(true),
+    };
+
+    let outer = evaluator.event_loop.running.replace(evaluator.resumers.len() + 1);
+    let outcome = coroutine.resume(evaluator, Object::Nil());
+    evaluator.event_loop.running = outer;
+
+    match outcome {
+        Ok(value) if coroutine.status() == CoroutineStatus::Dead => finish(evaluator, &task, Ok(value)),
+        Ok(Object::Task(awaited)) => {
+            let mut state = awaited.state.lock().unwrap();
+            match state.result {
+                Some(_) => evaluator.event_loop.ready.push_back(task),
+                None => state.waiters.push(task),
+            }
+        },
+        // yield() lets the others have a turn
+        Ok(_) => evaluator.event_loop.ready.push_back(task),
+        // the program has to stop, whoever was going to await the task
+        Err(error) if error.exit.is_some() || error.exhausted.is_some() || error.code == Code::Interrupted => return Err(error),
+        Err(error) => finish(evaluator, &task, Err(error)),
+    }
+    Ok(true)
+}
+
+// earliest first, so their waiters run in the order the timers went off
+fn fire_due_timers(evaluator: &mut ExprEvaluator) {
+    let now = evaluator.clock.monotonic();
+    let (mut due, pending): (Vec<(f64, Task)>, _) = evaluator.event_loop.timers.drain(..).partition(|(at, _)| *at <= now);
+    evaluator.event_loop.timers = pending;
+    due.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    for (_, task) in due {
+        finish(evaluator, &task, Ok(Object::Nil()));
+    }
+}
+
+// false when there is no timer left to wait for
+fn sleep_until_timer(evaluator: &mut ExprEvaluator) -> bool {
+    let earliest = evaluator.event_loop.timers.iter()
+        .enumerate()
+        .min_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))
+        .map(|(index, _)| index);
+    let (at, task) = match earliest {
+        Some(index) => evaluator.event_loop.timers.remove(index),
+        None => return false,
+    };
+    let now = evaluator.clock.monotonic();
+    if at > now {
+        evaluator.clock.sleep(Duration::from_secs_f64(at - now));
+    }
+    finish(evaluator, &task, Ok(Object::Nil()));
+    true
+}
+
+fn finish(evaluator: &mut ExprEvaluator, task: &Task, result: Result<Object, RuntimeError>) {
+    let mut state = task.state.lock().unwrap();
+    state.result = Some(result);
+    state.coroutine = None;
+    evaluator.event_loop.ready.extend(state.waiters.drain(..));
+}
@@ -0,0 +1,48 @@
+use std::io::Write;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::parser::Visitor;
+use crate::printer::AstPrinter;
+use crate::runtime::Object;
+use crate::runtime::RuntimeError;
+use crate::scanner::token::Span;
+
+// Writes a line for every statement and expression once it has been evaluated, indented by
+// how deeply it is nested, so inner expressions come first and their parent follows.
+pub struct Tracer {
+    output: Box<dyn Write + Send>,
+    depth: usize,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write + Send>) -> Tracer {
+        Tracer{output, depth: 0}
+    }
+
+    pub fn enter(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn leave(&mut self, text: &str, span: Span, result: &Result<Object, RuntimeError>) {
+        self.depth -= 1;
+        let outcome = match result {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("error: {}", error.message),
+        };
+        // tracing is best effort, a broken trace file shouldn't stop the program
+        let _ = writeln!(self.output, "{:indent$}[line {}:{}] {} => {}", "", span.line_number, span.column_number, text, outcome, indent = self.depth * 2);
+    }
+}
+
+// compound statements only name themselves, their parts get lines of their own
+pub fn describe_statement(statement: &Statement) -> String {
+    let mut printer = AstPrinter{indent: 0};
+    match &statement.kind {
+        StatementKind::If(condition, _, _) => format!("if {}", printer.visit_expr(condition)),
+        StatementKind::While(condition, _) => format!("while {}", printer.visit_expr(condition)),
+        StatementKind::For(_, _, _, _) => "for".to_string(),
+        StatementKind::Block(_) => "block".to_string(),
+        StatementKind::Function(declaration) => format!("fun {}", declaration.name.lexeme),
+        _ => printer.visit_statement(statement),
+    }
+}
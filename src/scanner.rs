@@ -1,73 +1,216 @@
 pub mod token;
+mod incremental;
+mod parallel;
+pub use incremental::relex;
+pub(crate) use incremental::byte_offset;
+pub(crate) use incremental::end_of_insert;
+pub(crate) use incremental::moved;
+pub(crate) use incremental::Position;
+pub use parallel::scan_in_chunks;
+pub use parallel::PARALLEL_THRESHOLD;
+use token::Span;
 use token::Token;
 use token::TokenType;
 
+use std::collections::VecDeque;
 use std::str::Chars;
-use std::iter::Peekable;
+use crate::diagnostic::Code;
 
+// digits, optionally followed by a dot and more digits
+fn is_well_formed_number(lexeme: &str) -> bool {
+    let mut parts = lexeme.splitn(2, '.');
+    let is_digits = |part: &str| !part.is_empty() && part.chars().all(|ch| ch.is_ascii_digit());
+    match (parts.next(), parts.next()) {
+        (Some(integer), None) => is_digits(integer),
+        (Some(integer), Some(fraction)) => is_digits(integer) && is_digits(fraction),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    // letters running into a number
+    MalformedNumber,
+    // an integer literal that doesn't fit in an i64
+    IntegerOutOfRange,
+}
+
+#[derive(Debug)]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    // without the location, that is in span
+    pub message: String,
+    pub span: Span,
+}
+
+impl ScanError {
+    pub fn code(&self) -> Code {
+        match self.kind {
+            ScanErrorKind::UnexpectedCharacter(_) => Code::UnexpectedCharacter,
+            ScanErrorKind::UnterminatedString => Code::UnterminatedString,
+            ScanErrorKind::MalformedNumber => Code::MalformedNumber,
+            ScanErrorKind::IntegerOutOfRange => Code::IntegerOutOfRange,
+        }
+    }
+}
+
+// Produces tokens lazily, trivia included, ending with a single Eof token.
 pub struct Scanner<'a> {
-    src_iter: Peekable<Chars<'a>>,
+    src_iter: Chars<'a>,
+    // characters peeked at but not consumed yet, at most two (see consume_number)
+    lookahead: VecDeque<char>,
     lexeme: String,
     line_number: u32,
     column_number: u32,
+    finished: bool,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(source: &'a str) -> Scanner<'a> {
+        Scanner {
+            src_iter: source.chars(),
+            lookahead: VecDeque::new(),
+            lexeme: String::from(""),
+            line_number: 1u32,
+            column_number: 0u32,
+            finished: false,
+        }
+    }
+
+    // scans a piece of a larger source that begins at line:column of it
+    fn starting_at(source: &'a str, line_number: u32, column_number: u32) -> Scanner<'a> {
+        Scanner {
+            line_number,
+            column_number,
+            ..Scanner::new(source)
+        }
+    }
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.finished {
+            return None;
+        }
+
+        match self.scan_token() {
+            Some(token) => Some(token),
+            None => {
+                self.finished = true;
+                Some(Token::new(TokenType::Eof, String::from(""), self.line_number, self.column_number))
+            },
+        }
+    }
 }
 
 impl Scanner<'_> {
     fn advance(&mut self) -> Option<char> {
-        let ch = self.src_iter.next();
-        self.column_number += 1;
+        let ch = self.lookahead.pop_front().or_else(|| self.src_iter.next())?;
+        self.lexeme.push(ch);
 
-        if let Some(ch) = ch {
-            self.lexeme.push(ch);
+        // strings can span lines too, so this can't wait for the Newline token
+        if ch == '\n' {
+            self.line_number += 1;
+            self.column_number = 0;
+        } else {
+            self.column_number += 1;
         }
+        Some(ch)
+    }
 
-        return ch;
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        while self.lookahead.len() <= n {
+            self.lookahead.push_back(self.src_iter.next()?);
+        }
+        Some(self.lookahead[n])
     }
 
     fn scan_token(&mut self) -> Option<Token> {
+        let line_number = self.line_number;
+        let column_number = self.column_number;
+
         self.advance()
             .map(|ch| match ch {
                 '(' => TokenType::LeftParen,
                 ')' => TokenType::RightParen,
                 '{' => TokenType::LeftBrace,
                 '}' => TokenType::RightBrace,
+                '[' => TokenType::LeftBracket,
+                ']' => TokenType::RightBracket,
                 ',' => TokenType::Comma,
-                '.' => TokenType::Dot,
-                '-' => TokenType::Minus,
+                ':' => TokenType::Colon,
+                '@' => TokenType::At,
+                // `//` already starts a comment
+                '~' => match self.peek() {
+                    Some('/') => {
+                        self.advance();
+                        TokenType::TildeSlash
+                    }
+                    _ => TokenType::Unknown,
+                },
+                '.' => match (self.peek(), self.peek_nth(1)) {
+                    (Some('.'), Some('.')) => {
+                        self.advance();
+                        self.advance();
+                        TokenType::Ellipsis
+                    }
+                    _ => TokenType::Dot,
+                },
+                '-' => match self.peek() {
+                    Some('>') => {
+                        self.advance();
+                        TokenType::Arrow
+                    }
+                    _ => TokenType::Minus,
+                },
                 '+' => TokenType::Plus,
                 ';' => TokenType::Semicolon,
                 '*' => TokenType::Star,
-                '!' => match self.src_iter.peek() {
+                '%' => TokenType::Percent,
+                '!' => match self.peek() {
                     Some('=') => {
                         self.advance();
                         TokenType::BangEqual
                     }
                     _ => TokenType::Bang,
                 },
-                '=' => match self.src_iter.peek() {
+                '=' => match self.peek() {
                     Some('=') => {
                         self.advance();
                         TokenType::EqualEqual
                     }
                     _ => TokenType::Equal,
                 },
-                '<' => match self.src_iter.peek() {
+                '<' => match self.peek() {
                     Some('=') => {
                         self.advance();
                         TokenType::LessEqual
                     }
                     _ => TokenType::Less,
                 },
-                '>' => match self.src_iter.peek() {
+                '>' => match self.peek() {
                     Some('=') => {
                         self.advance();
                         TokenType::GreaterEqual
                     }
                     _ => TokenType::Greater,
                 },
-                '/' => match self.src_iter.peek() {
+                '/' => match self.peek() {
                     Some('/') => {
-                        while self.advance() != None {}
+                        while let Some(ch) = self.peek() {
+                            if ch == '\n' {
+                                break;
+                            }
+                            self.advance();
+                        }
                         TokenType::Comment
                     }
                     _ => TokenType::Slash,
@@ -80,9 +223,9 @@ impl Scanner<'_> {
                     self.consume_string();
                     TokenType::Str
                 }
-                _ => if ch.is_digit(10) {
+                _ => if ch.is_ascii_digit() {
                     self.consume_number()
-                } else if ch.is_alphabetic() {
+                } else if ch.is_alphabetic() || ch == '_' {
                     self.consume_identifier();
                     self.identifier_token_type()
                 } else {
@@ -90,20 +233,7 @@ impl Scanner<'_> {
                 },
             })
             .map(|token_type| {
-                let column_number = self.column_number - self.lexeme.len() as u32;
-                let line_number = self.line_number;
-
-                if token_type == TokenType::Newline {
-                    self.line_number += 1;
-                    self.column_number = 0;
-                }
-
-                let token = Token {
-                    token_type: token_type,
-                    lexeme: self.lexeme.clone(),
-                    line_number: line_number,
-                    column_number: column_number,
-                };
+                let token = Token::new(token_type, self.lexeme.clone(), line_number, column_number);
 
                 self.lexeme = String::from("");
                 token
@@ -111,8 +241,8 @@ impl Scanner<'_> {
     }
 
     fn consume_string(&mut self) {
-        while let Some(ch) = self.src_iter.peek() {
-            if ch == &'"' {
+        while let Some(ch) = self.peek() {
+            if ch == '"' {
                 break;
             }
             self.advance();
@@ -121,31 +251,46 @@ impl Scanner<'_> {
     }
 
     fn consume_number(&mut self) -> TokenType {
-        while let Some(ch) = self.src_iter.peek() {
-            if !ch.is_digit(10) {
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_digit() {
                 break;
             }
             self.advance();
         }
 
-        // TODO: I don't think we properly handle 45.function()
-        match self.src_iter.peek() {
-            Some(&'.') => self.advance(),
-            _ => return TokenType::Integer,
+        // only a dot followed by a digit makes a float, `45.abs()` is a method call on 45
+        match (self.peek(), self.peek_nth(1)) {
+            (Some('.'), Some(next)) if next.is_ascii_digit() => self.advance(),
+            _ => {
+                self.consume_malformed_suffix();
+                return TokenType::Integer;
+            },
         };
 
-        while let Some(ch) = self.src_iter.peek() {
-            if !ch.is_digit(10) {
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_digit() {
                 break;
             }
             self.advance();
         }
+        self.consume_malformed_suffix();
         TokenType::Float
     }
 
+    // letters running straight into a number (`12abc`) stay part of it, so the whole thing is
+    // reported as one malformed number rather than a number followed by an identifier
+    fn consume_malformed_suffix(&mut self) {
+        while let Some(ch) = self.peek() {
+            if !ch.is_alphanumeric() && ch != '_' {
+                break;
+            }
+            self.advance();
+        }
+    }
+
     fn consume_identifier(&mut self) {
-        while let Some(ch) = self.src_iter.peek() {
-          if !ch.is_alphanumeric() {
+        while let Some(ch) = self.peek() {
+          if !ch.is_alphanumeric() && ch != '_' {
               break;
           }
           self.advance();
@@ -155,12 +300,17 @@ impl Scanner<'_> {
     fn identifier_token_type(&mut self) -> TokenType {
         match self.lexeme.as_str() {
             "and" => TokenType::And,
+            "async" => TokenType::Async,
+            "await" => TokenType::Await,
             "class" => TokenType::Class,
             "else" => TokenType::Else,
             "false" => TokenType::False,
             "for" => TokenType::For,
             "fun" => TokenType::Fun,
             "if" => TokenType::If,
+            "import" => TokenType::Import,
+            "in" => TokenType::In,
+            "is" => TokenType::Is,
             "nil" => TokenType::Nil,
             "or" => TokenType::Or,
             "print" => TokenType::Print,
@@ -170,29 +320,92 @@ impl Scanner<'_> {
             "true" => TokenType::True,
             "var" => TokenType::Var,
             "while" => TokenType::While,
+            "with" => TokenType::With,
             _ => TokenType::Identifier
         }
     }
 }
 
-pub fn scan_tokens(source: &String) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut scanner = Scanner {
-        src_iter: source.chars().peekable(),
-        lexeme: String::from(""),
-        line_number: 1u32,
-        column_number: 0u32,
-    };
+// Sources of PARALLEL_THRESHOLD bytes or more are split between the machine's cores.
+pub fn scan_tokens(source: &str) -> Vec<Token> {
+    if source.len() >= PARALLEL_THRESHOLD && cfg!(not(target_arch = "wasm32")) {
+        let threads = std::thread::available_parallelism().map_or(1, usize::from);
+        if threads > 1 {
+            return scan_in_chunks(source, threads);
+        }
+    }
+    Scanner::new(source).collect()
+}
+
+// only the significant tokens, each carrying the trivia around it, see attach_trivia
+pub fn scan_with_trivia(source: &str) -> Vec<Token> {
+    attach_trivia(scan_tokens(source))
+}
 
-    while let Some(token) = scanner.scan_token() {
-        tokens.push(token)
+// Folds trivia tokens into the significant tokens next to them, so the source can be
+// reproduced exactly from Token::with_trivia. A token's trailing trivia runs to the end of
+// its line, newline included; everything else leads the next token, Eof takes what's left.
+pub fn attach_trivia(tokens: Vec<Token>) -> Vec<Token> {
+    let mut attached: Vec<Token> = Vec::new();
+    let mut pending = Vec::new();
+    // still on the line of the last significant token
+    let mut trailing = false;
+
+    for token in tokens {
+        if !token.token_type.is_trivia() {
+            let mut token = token;
+            token.leading_trivia = std::mem::take(&mut pending);
+            attached.push(token);
+            trailing = true;
+            continue;
+        }
+
+        match attached.last_mut() {
+            Some(previous) if trailing => {
+                trailing = token.token_type != TokenType::Newline;
+                previous.trailing_trivia.push(token);
+            },
+            _ => pending.push(token),
+        }
     }
+    attached
+}
+
+// Scans the whole source and reports every error in it. The tokens still cover all of the
+// source, what couldn't be scanned included, so they can be shown or formatted regardless.
+pub fn scan(source: &str) -> (Vec<Token>, Vec<ScanError>) {
+    let tokens = scan_tokens(source);
+    let errors = tokens.iter().filter_map(token_error).collect();
+    (tokens, errors)
+}
+
+// The scanner never gives up, it keeps whatever it can't make sense of as a token so tools
+// like the formatter still see the whole file. This finds the first such token.
+pub fn check_tokens(tokens: &[Token]) -> Result<(), ScanError> {
+    match tokens.iter().find_map(token_error) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
 
-    tokens.push(Token {
-        token_type: TokenType::Eof,
-        lexeme: String::from(""),
-        line_number: scanner.line_number as u32,
-        column_number: scanner.column_number as u32,
-    });
-    tokens
+// what is wrong with a token the scanner couldn't make sense of, if anything
+pub fn token_error(token: &Token) -> Option<ScanError> {
+    let kind = match token.token_type {
+        TokenType::Unknown => ScanErrorKind::UnexpectedCharacter(token.lexeme.chars().next().unwrap_or_default()),
+        TokenType::Str if token.lexeme.len() < 2 || !token.lexeme.ends_with('"') => ScanErrorKind::UnterminatedString,
+        TokenType::Integer | TokenType::Float if !is_well_formed_number(&token.lexeme) => ScanErrorKind::MalformedNumber,
+        TokenType::Integer if token.lexeme.parse::<i64>().is_err() => ScanErrorKind::IntegerOutOfRange,
+        _ => return None,
+    };
+    let message = match kind {
+        ScanErrorKind::UnexpectedCharacter(ch) => format!("Unexpected character '{}'", ch),
+        ScanErrorKind::UnterminatedString => "Unterminated string".to_string(),
+        ScanErrorKind::MalformedNumber => format!("Malformed number '{}'", token.lexeme),
+        ScanErrorKind::IntegerOutOfRange => format!("Integer literal '{}' is out of range", token.lexeme),
+    };
+    Some(ScanError{
+        kind,
+        message,
+        span: token.span(),
+    })
 }
@@ -0,0 +1,97 @@
+use crate::scanner::scan_tokens;
+use crate::scanner::Scanner;
+use crate::scanner::token::Span;
+use crate::scanner::token::Token;
+
+// line and column, counted like Token::line_number and Token::column_number
+pub type Position = (u32, u32);
+
+// Updates a raw token stream (as from scan_tokens) in place after `range` of the old source
+// was replaced by `text`, giving `source`. Scanning restarts just before the edit and stops as
+// soon as a token lines up with an old one again: the scanner keeps no state between tokens,
+// so everything after that point would come out the same and is kept, only moved by however
+// many lines and columns the edit added or removed.
+pub fn relex(tokens: &mut Vec<Token>, source: &str, range: Span, text: &str) {
+    let edit_start = (range.line_number, range.column_number);
+    let edit_end = (range.end_line_number, range.end_column_number);
+    let new_end = end_of_insert(edit_start, text);
+
+    // The two tokens before the edit are scanned again as well: the edit may extend the one
+    // right before it (`ab` + `c`), and numbers look two characters ahead (`1.` + `5`).
+    let restart = tokens.iter().rposition(|token| start(token) < edit_start).unwrap_or(0).saturating_sub(1);
+    let (line_number, column_number) = tokens.get(restart).map(start).unwrap_or((1, 0));
+    let offset = match byte_offset(source, line_number, column_number) {
+        Some(offset) => offset,
+        None => {
+            *tokens = scan_tokens(source);
+            return;
+        },
+    };
+
+    // old tokens from here on survived the edit, at least as text
+    let mut old = restart + tokens[restart..].iter().take_while(|token| start(token) < edit_end).count();
+    let mut relexed = Vec::new();
+
+    for token in Scanner::starting_at(&source[offset..], line_number, column_number) {
+        while old < tokens.len() && moved(start(&tokens[old]), edit_end, new_end) < start(&token) {
+            old += 1;
+        }
+
+        if let Some(candidate) = tokens.get(old) {
+            if moved(start(candidate), edit_end, new_end) == start(&token) && candidate.token_type == token.token_type && candidate.lexeme == token.lexeme {
+                for token in tokens[old..].iter_mut() {
+                    let (line_number, column_number) = moved(start(token), edit_end, new_end);
+                    token.line_number = line_number;
+                    token.column_number = column_number;
+                }
+                tokens.splice(restart..old, relexed);
+                return;
+            }
+        }
+        relexed.push(token);
+    }
+    tokens.splice(restart.., relexed);
+}
+
+fn start(token: &Token) -> Position {
+    (token.line_number, token.column_number)
+}
+
+// where the inserted text ends, in the new source
+pub fn end_of_insert(start: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(last_newline) => (start.0 + text.matches('\n').count() as u32, text[last_newline + 1..].chars().count() as u32),
+        None => (start.0, start.1 + text.chars().count() as u32),
+    }
+}
+
+// where a position after the edit ends up in the new source
+pub fn moved(position: Position, edit_end: Position, new_end: Position) -> Position {
+    let (line_number, column_number) = position;
+    if line_number == edit_end.0 {
+        (new_end.0, new_end.1 + (column_number - edit_end.1))
+    } else {
+        ((line_number as i64 + new_end.0 as i64 - edit_end.0 as i64) as u32, column_number)
+    }
+}
+
+// counts lines and columns the way the scanner does
+pub fn byte_offset(source: &str, line_number: u32, column_number: u32) -> Option<usize> {
+    let (mut line, mut column) = (1, 0);
+    for (offset, ch) in source.char_indices() {
+        if (line, column) == (line_number, column_number) {
+            return Some(offset);
+        }
+        if ch == '\n' {
+            line += 1;
+            column = 0;
+        } else {
+            column += 1;
+        }
+    }
+    if (line, column) == (line_number, column_number) {
+        Some(source.len())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,70 @@
+use std::thread;
+use crate::scanner::Scanner;
+use crate::scanner::token::Token;
+use crate::scanner::token::TokenType;
+
+// where scan_tokens starts splitting the source between threads
+pub const PARALLEL_THRESHOLD: usize = 1 << 20;
+
+// Scans the source in about `chunks` pieces, each on its own thread, giving the same tokens as
+// scanning it in one go. Pieces are split just after a newline that isn't in a string: no
+// token runs past one, and the scanner never looks past one to decide what a token before it
+// is, so each piece can be scanned on its own from the start of its line.
+pub fn scan_in_chunks(source: &str, chunks: usize) -> Vec<Token> {
+    let pieces = split(source, chunks);
+    let scanned: Vec<Vec<Token>> = thread::scope(|scope| {
+        let handles: Vec<_> = pieces.iter()
+            .map(|&(piece, line_number)| scope.spawn(move || Scanner::starting_at(piece, line_number, 0).collect::<Vec<Token>>()))
+            .collect();
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    });
+
+    // every piece ends with an Eof, only the last one's is the end of the source
+    let last = scanned.len() - 1;
+    let mut tokens = Vec::with_capacity(scanned.iter().map(Vec::len).sum());
+    for (index, piece) in scanned.into_iter().enumerate() {
+        tokens.extend(piece.into_iter().filter(|token| index == last || token.token_type != TokenType::Eof));
+    }
+    tokens
+}
+
+// The pieces with the line each starts on, cut as close to even as the newlines allow. Finding
+// them means following strings and comments through the whole source, but only by looking for
+// the few bytes that start and end them, far quicker than scanning.
+fn split(source: &str, chunks: usize) -> Vec<(&str, u32)> {
+    let bytes = source.as_bytes();
+    let size = source.len() / chunks.max(1);
+    let mut pieces = Vec::new();
+    let (mut start, mut start_line) = (0, 1);
+    let mut line_number = 1;
+    let mut in_string = false;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\n' => {
+                line_number += 1;
+                if !in_string && index + 1 - start >= size && index + 1 < bytes.len() {
+                    pieces.push((&source[start..index + 1], start_line));
+                    start = index + 1;
+                    start_line = line_number;
+                }
+            },
+            b'"' => in_string = !in_string,
+            // `~/` is one token, the slash doesn't start a comment
+            b'~' if !in_string && bytes.get(index + 1) == Some(&b'/') => index += 1,
+            // a comment runs to the end of its line, quotes in it don't count
+            b'/' if !in_string && bytes.get(index + 1) == Some(&b'/') => {
+                while index + 1 < bytes.len() && bytes[index + 1] != b'\n' {
+                    index += 1;
+                }
+            },
+            _ => {},
+        }
+        index += 1;
+    }
+    pieces.push((&source[start..], start_line));
+    pieces
+}
@@ -1,18 +1,24 @@
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     // Single-character tokens.                      
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
+    At,
     Dot,
     Minus,
     Plus,
     Semicolon,
     Slash,
     Star,
+    Percent,
 
     // One or two character tokens.                  
     Bang,
@@ -23,6 +29,9 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    Arrow,
+    Ellipsis,
+    TildeSlash,
 
     // Literals.                                     
     Identifier,
@@ -33,12 +42,17 @@ pub enum TokenType {
 
     // Keywords.                                     
     And,
+    Async,
+    Await,
     Class,
     Else,
     False,
     Fun,
     For,
     If,
+    Import,
+    In,
+    Is,
     Nil,
     Or,
     Print,
@@ -48,6 +62,7 @@ pub enum TokenType {
     True,
     Var,
     While,
+    With,
 
     Whitespace,
     Newline,
@@ -55,10 +70,188 @@ pub enum TokenType {
     Eof,
 }
 
+// what a token is to someone reading the source, for highlighting it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenClass {
+    Keyword,
+    Identifier,
+    String,
+    Number,
+    // true, false and nil
+    Constant,
+    Comment,
+    Operator,
+    // brackets, commas, semicolons and the like
+    Punctuation,
+    Whitespace,
+    // characters the scanner couldn't make sense of
+    Invalid,
+}
+
+impl TokenType {
+    // tokens the parser never sees, kept around for tooling such as the formatter
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenType::Whitespace | TokenType::Newline | TokenType::Comment)
+    }
+
+    pub fn class(&self) -> TokenClass {
+        match self {
+            TokenType::LeftParen | TokenType::RightParen | TokenType::LeftBrace | TokenType::RightBrace
+                | TokenType::LeftBracket | TokenType::RightBracket | TokenType::Comma | TokenType::Colon
+                | TokenType::Semicolon | TokenType::Dot | TokenType::At => TokenClass::Punctuation,
+            TokenType::Minus | TokenType::Plus | TokenType::Slash | TokenType::Star | TokenType::Percent
+                | TokenType::Bang | TokenType::BangEqual | TokenType::Equal | TokenType::EqualEqual
+                | TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+                | TokenType::Arrow | TokenType::Ellipsis | TokenType::TildeSlash => TokenClass::Operator,
+            TokenType::Identifier => TokenClass::Identifier,
+            TokenType::Str => TokenClass::String,
+            TokenType::Integer | TokenType::Float => TokenClass::Number,
+            TokenType::Comment => TokenClass::Comment,
+            TokenType::True | TokenType::False | TokenType::Nil => TokenClass::Constant,
+            // `and`, `or`, `in` and `is` are operators spelled as words, they read as keywords
+            TokenType::And | TokenType::Async | TokenType::Await | TokenType::Class | TokenType::Else
+                | TokenType::Fun | TokenType::For | TokenType::If | TokenType::Import | TokenType::In
+                | TokenType::Is | TokenType::Or | TokenType::Print | TokenType::Return | TokenType::Super
+                | TokenType::This | TokenType::Var | TokenType::While | TokenType::With => TokenClass::Keyword,
+            TokenType::Whitespace | TokenType::Newline | TokenType::Eof => TokenClass::Whitespace,
+            TokenType::Unknown => TokenClass::Invalid,
+        }
+    }
+
+    // how error messages name the token
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TokenType::LeftParen => "'('",
+            TokenType::RightParen => "')'",
+            TokenType::LeftBrace => "'{'",
+            TokenType::RightBrace => "'}'",
+            TokenType::LeftBracket => "'['",
+            TokenType::RightBracket => "']'",
+            TokenType::Comma => "','",
+            TokenType::Colon => "':'",
+            TokenType::At => "'@'",
+            TokenType::Dot => "'.'",
+            TokenType::Minus => "'-'",
+            TokenType::Plus => "'+'",
+            TokenType::Semicolon => "';'",
+            TokenType::Slash => "'/'",
+            TokenType::Star => "'*'",
+            TokenType::Percent => "'%'",
+            TokenType::Bang => "'!'",
+            TokenType::BangEqual => "'!='",
+            TokenType::Equal => "'='",
+            TokenType::EqualEqual => "'=='",
+            TokenType::Greater => "'>'",
+            TokenType::GreaterEqual => "'>='",
+            TokenType::Less => "'<'",
+            TokenType::LessEqual => "'<='",
+            TokenType::Arrow => "'->'",
+            TokenType::Ellipsis => "'...'",
+            TokenType::TildeSlash => "'~/'",
+            TokenType::Identifier => "identifier",
+            TokenType::Str => "string",
+            TokenType::Integer | TokenType::Float => "number",
+            TokenType::Comment => "comment",
+            TokenType::And => "'and'",
+            TokenType::Async => "'async'",
+            TokenType::Await => "'await'",
+            TokenType::Class => "'class'",
+            TokenType::Else => "'else'",
+            TokenType::False => "'false'",
+            TokenType::Fun => "'fun'",
+            TokenType::For => "'for'",
+            TokenType::If => "'if'",
+            TokenType::Import => "'import'",
+            TokenType::In => "'in'",
+            TokenType::Is => "'is'",
+            TokenType::Nil => "'nil'",
+            TokenType::Or => "'or'",
+            TokenType::Print => "'print'",
+            TokenType::Return => "'return'",
+            TokenType::Super => "'super'",
+            TokenType::This => "'this'",
+            TokenType::True => "'true'",
+            TokenType::Var => "'var'",
+            TokenType::While => "'while'",
+            TokenType::With => "'with'",
+            TokenType::Whitespace => "whitespace",
+            TokenType::Newline => "newline",
+            TokenType::Unknown => "unknown character",
+            TokenType::Eof => "end of file",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line_number: u32,
     pub column_number: u32,
+    // whitespace, newlines and comments around the token, only filled in by
+    // scanner::attach_trivia; empty in the raw stream where trivia are tokens of their own
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub leading_trivia: Vec<Token>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trailing_trivia: Vec<Token>,
+}
+
+// start is inclusive, end is exclusive; columns count from 0 like Token::column_number
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub line_number: u32,
+    pub column_number: u32,
+    pub end_line_number: u32,
+    pub end_column_number: u32,
+}
+
+impl Span {
+    pub fn to(&self, end: Span) -> Span {
+        Span {
+            line_number: self.line_number,
+            column_number: self.column_number,
+            end_line_number: end.end_line_number,
+            end_column_number: end.end_column_number,
+        }
+    }
+}
+
+impl Token {
+    pub fn new(token_type: TokenType, lexeme: String, line_number: u32, column_number: u32) -> Token {
+        Token {
+            token_type,
+            lexeme,
+            line_number,
+            column_number,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+        }
+    }
+
+    // the token with its trivia, in source order
+    pub fn with_trivia(&self) -> impl Iterator<Item = &Token> {
+        self.leading_trivia.iter()
+            .chain(std::iter::once(self))
+            .chain(self.trailing_trivia.iter())
+    }
+
+    pub fn span(&self) -> Span {
+        let lines: Vec<&str> = self.lexeme.split('\n').collect();
+        let last_line = lines[lines.len() - 1].chars().count() as u32;
+        let end_column_number =
+            if lines.len() > 1 {
+                last_line
+            } else {
+                self.column_number + last_line
+            };
+
+        Span {
+            line_number: self.line_number,
+            column_number: self.column_number,
+            end_line_number: self.line_number + lines.len() as u32 - 1,
+            end_column_number,
+        }
+    }
 }
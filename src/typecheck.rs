@@ -0,0 +1,674 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use crate::diagnostic::Code;
+use crate::diagnostic::Diagnostic;
+use crate::linter::constant_condition;
+use crate::parser::Expr;
+use crate::parser::ExprKind;
+use crate::parser::FunctionDeclaration;
+use crate::parser::Statement;
+use crate::parser::StatementKind;
+use crate::parser::Visitor;
+use crate::scanner::token::Span;
+use crate::scanner::token::Token;
+use crate::scanner::token::TokenType;
+
+// The types a value can have, as written in annotations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Nil,
+    Integer,
+    Float,
+    Boolean,
+    String,
+    List,
+    Function,
+}
+
+const TYPE_NAMES: [&str; 7] = ["Nil", "Integer", "Float", "Boolean", "String", "List", "Function"];
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "Nil" => Some(Type::Nil),
+            "Integer" => Some(Type::Integer),
+            "Float" => Some(Type::Float),
+            "Boolean" => Some(Type::Boolean),
+            "String" => Some(Type::String),
+            "List" => Some(Type::List),
+            "Function" => Some(Type::Function),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Nil => "Nil",
+            Type::Integer => "Integer",
+            Type::Float => "Float",
+            Type::Boolean => "Boolean",
+            Type::String => "String",
+            Type::List => "List",
+            Type::Function => "Function",
+        }
+    }
+
+    // whether a value of this type may be stored where `target` is expected; integers widen
+    // to floats the same way they do everywhere else
+    pub fn is_assignable_to(&self, target: Type) -> bool {
+        *self == target || (*self == Type::Integer && target == Type::Float)
+    }
+
+    fn is_numeric(&self) -> bool {
+        *self == Type::Integer || *self == Type::Float
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// the annotated parameter and return types of a declared function
+#[derive(Clone)]
+struct Signature {
+    parameters: Vec<Option<Type>>,
+    // the last parameter takes any number of arguments
+    variadic: bool,
+    returns: Option<Type>,
+}
+
+#[derive(Clone)]
+struct Binding {
+    // the annotated type, None when it has none
+    declared: Option<Type>,
+    // the type of the value it holds at this point in the program, as far as that is certain
+    inferred: Option<Type>,
+    // set for names declared with `fun`
+    signature: Option<Signature>,
+}
+
+impl Binding {
+    fn current(&self) -> Option<Type> {
+        self.inferred.or(self.declared)
+    }
+}
+
+// Checks a program without running it: against its type annotations, and for operations that
+// fail whenever they run, like `"a" - 1`. Types of unannotated variables are followed from
+// their initializers and assignments. Anything whose type depends on what happens at runtime
+// (which branch ran, what a call did) is never reported.
+pub struct TypeChecker {
+    scopes: Vec<HashMap<String, Binding>>,
+    // the declared return type of each function being checked, innermost last
+    functions: Vec<Option<Type>>,
+    // false to ignore annotations and only report what fails at runtime regardless
+    annotations: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// annotation mismatches and guaranteed runtime errors, see --type-check
+pub fn check(statements: &[Statement]) -> Vec<Diagnostic> {
+    TypeChecker::new().check(statements)
+}
+
+// only the guaranteed runtime errors, annotations are ignored, see --check
+pub fn infer(statements: &[Statement]) -> Vec<Diagnostic> {
+    TypeChecker::without_annotations().check(statements)
+}
+
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        TypeChecker{
+            scopes: vec![HashMap::new()],
+            functions: Vec::new(),
+            annotations: true,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn without_annotations() -> TypeChecker {
+        TypeChecker{annotations: false, ..TypeChecker::new()}
+    }
+
+    pub fn check(mut self, statements: &[Statement]) -> Vec<Diagnostic> {
+        for statement in statements.iter() {
+            self.visit_statement(statement);
+        }
+        self.diagnostics
+    }
+
+    // the type an annotation names, reporting it when it names none
+    fn resolve(&mut self, annotation: Option<&Token>) -> Option<Type> {
+        if !self.annotations {
+            return None;
+        }
+        annotation.and_then(|annotation| {
+            let declared = Type::from_name(&annotation.lexeme);
+            if declared.is_none() {
+                self.diagnostics.push(Diagnostic::error(Code::UnknownType, format!("unknown type '{}'", annotation.lexeme), Some(annotation.span()))
+                    .with_note(format!("the types are {}", TYPE_NAMES.join(", "))));
+            }
+            declared
+        })
+    }
+
+    fn declare(&mut self, name: &Token, annotation: Option<&Token>, inferred: Option<Type>) -> Option<Type> {
+        let declared = self.resolve(annotation);
+        self.bind(name, Binding{declared, inferred, signature: None});
+        declared
+    }
+
+    // `value` is what's assigned, which has the type `found`
+    fn assign(&mut self, name: &Token, value: &Expr, found: Option<Type>) {
+        if let Some(declared) = self.lookup(&name.lexeme) {
+            self.expect(declared, found, value, &format!("'{}'", name.lexeme));
+        }
+        if let Some(binding) = self.binding_mut(&name.lexeme) {
+            binding.inferred = found;
+            binding.signature = None;
+        }
+    }
+
+    fn bind(&mut self, name: &Token, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.to_string(), binding);
+        }
+    }
+
+    fn binding(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn binding_mut(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name))
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.binding(name).and_then(|binding| binding.declared)
+    }
+
+    // what is known about the variables named no longer holds
+    fn forget(&mut self, effects: &Effects) {
+        for name in effects.assigned.iter() {
+            if let Some(binding) = self.binding_mut(name) {
+                binding.inferred = None;
+            }
+        }
+        if effects.calls {
+            self.forget_globals();
+        }
+    }
+
+    // a function can assign to any global, so after a call none of them are certain
+    fn forget_globals(&mut self) {
+        if let Some(globals) = self.scopes.first_mut() {
+            for binding in globals.values_mut() {
+                binding.inferred = None;
+            }
+        }
+    }
+
+    // combine what two alternative branches left behind, keeping only what they agree on
+    fn merge(&mut self, other: Vec<HashMap<String, Binding>>) {
+        for (scope, other_scope) in self.scopes.iter_mut().zip(other) {
+            for (name, other_binding) in other_scope {
+                if let Some(binding) = scope.get_mut(&name) {
+                    if binding.inferred != other_binding.inferred {
+                        binding.inferred = None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_function(&mut self, declaration: &FunctionDeclaration) {
+        let mut parameters: Vec<Option<Type>> = declaration.parameters.iter()
+            .map(|parameter| self.resolve(parameter.annotation.as_ref()))
+            .collect();
+        if declaration.variadic {
+            parameters.pop();
+        }
+        let returns = self.resolve(declaration.return_type.as_ref());
+        // declared before the body is checked so it can call itself; calling an async function
+        // gives a task, not what its body returns
+        let signature = Signature{parameters: parameters.clone(), variadic: declaration.variadic, returns: if declaration.is_async { None } else { returns }};
+        if declaration.decorators.is_empty() {
+            self.bind(&declaration.name, Binding{declared: Some(Type::Function), inferred: Some(Type::Function), signature: Some(signature)});
+        } else {
+            // the name holds whatever the decorators return
+            for decorator in declaration.decorators.iter() {
+                self.visit_expr(decorator);
+            }
+            self.bind(&declaration.name, Binding{declared: None, inferred: None, signature: None});
+        }
+
+        // like the runtime, the body sees the globals but none of the enclosing locals, and it
+        // may be called when the globals hold anything
+        let enclosing = self.scopes.split_off(1.min(self.scopes.len()));
+        let globals = self.scopes.clone();
+        self.forget_globals();
+        self.scopes.push(HashMap::new());
+        for (parameter, declared) in declaration.parameters.iter().zip(parameters) {
+            self.bind(&parameter.name, Binding{declared, inferred: None, signature: None});
+        }
+        if let (true, Some(rest)) = (declaration.variadic, declaration.parameters.last()) {
+            self.bind(&rest.name, Binding{declared: Some(Type::List), inferred: Some(Type::List), signature: None});
+        }
+        self.functions.push(returns);
+        for statement in declaration.body.iter() {
+            self.visit_statement(statement);
+        }
+        self.functions.pop();
+        self.scopes = globals;
+        self.scopes.extend(enclosing);
+
+        if let Some(returns) = returns {
+            if !Type::Nil.is_assignable_to(returns) && !declaration.body.iter().any(always_returns) {
+                self.diagnostics.push(Diagnostic::error(Code::TypeMismatch, format!("expected {}, found Nil", returns), Some(declaration.name.span()))
+                    .with_note(format!("'{}' can reach the end of its body without returning, which returns nil", declaration.name.lexeme)));
+            }
+        }
+    }
+
+    fn expect_return(&mut self, expected: Type, found: Option<Type>, span: Span) {
+        if let Some(found) = found {
+            if !found.is_assignable_to(expected) {
+                self.diagnostics.push(Diagnostic::error(Code::TypeMismatch, format!("expected {}, found {}", expected, found), Some(span))
+                    .with_note(format!("the function is declared to return {}", expected)));
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: Type, found: Option<Type>, expr: &Expr, what: &str) {
+        if let Some(found) = found {
+            if !found.is_assignable_to(expected) {
+                self.diagnostics.push(Diagnostic::error(Code::TypeMismatch, format!("expected {}, found {}", expected, found), Some(expr.span))
+                    .with_note(format!("{} is declared as {}", what, expected)));
+            }
+        }
+    }
+
+    // an operation the runtime rejects for these types every time
+    fn fails(&mut self, code: Code, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic::error(code, message, Some(span))
+            .with_note("this fails whenever it runs".to_string()));
+    }
+
+    fn check_call(&mut self, e: &Expr, callee: &Expr, arguments: &[Expr]) -> Option<Type> {
+        let callee_type = self.visit_expr(callee);
+        let signature = match &callee.kind {
+            ExprKind::Variable(token) => self.binding(&token.lexeme).and_then(|binding| binding.signature.clone()).map(|signature| (token.lexeme.clone(), signature)),
+            _ => None,
+        };
+        let found: Vec<Option<Type>> = arguments.iter().map(|argument| self.visit_expr(argument)).collect();
+        self.forget_globals();
+
+        if let Some(callee_type) = callee_type.filter(|callee_type| *callee_type != Type::Function) {
+            self.fails(Code::NotCallable, format!("{} can't be called", callee_type), e.span);
+            return None;
+        }
+        let (name, signature) = signature?;
+        // how many arguments a spread list makes is only known when it runs
+        if arguments.iter().any(|argument| matches!(argument.kind, ExprKind::Spread(_))) {
+            return signature.returns;
+        }
+        if signature.variadic && found.len() < signature.parameters.len() {
+            self.fails(Code::WrongArity, format!("'{}' expects at least {} arguments but got {}", name, signature.parameters.len(), found.len()), e.span);
+            return None;
+        }
+        if !signature.variadic && found.len() != signature.parameters.len() {
+            self.fails(Code::WrongArity, format!("'{}' expects {} arguments but got {}", name, signature.parameters.len(), found.len()), e.span);
+            return None;
+        }
+        for (index, argument) in arguments.iter().enumerate().take(signature.parameters.len()) {
+            if let Some(expected) = signature.parameters[index] {
+                self.expect(expected, found[index], argument, &format!("parameter {} of '{}'", index + 1, name));
+            }
+        }
+        signature.returns
+    }
+}
+
+impl Default for TypeChecker {
+    fn default() -> TypeChecker {
+        TypeChecker::new()
+    }
+}
+
+// What running part of a program may change: the variables it assigns to, and whether it
+// calls anything (a function may assign to any global).
+#[derive(Default)]
+struct Effects {
+    assigned: Vec<String>,
+    calls: bool,
+}
+
+impl Effects {
+    fn of_statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Expression(expr) | StatementKind::Print(expr) => self.of_expr(expr),
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.of_expr(condition);
+                self.of_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.of_statement(else_branch);
+                }
+            },
+            StatementKind::While(condition, body) | StatementKind::With(_, condition, body) => {
+                self.of_expr(condition);
+                self.of_statement(body);
+            },
+            StatementKind::For(initializer, condition, increment, body) => {
+                if let Some(initializer) = initializer {
+                    self.of_statement(initializer);
+                }
+                if let Some(condition) = condition {
+                    self.of_expr(condition);
+                }
+                if let Some(increment) = increment {
+                    self.of_expr(increment);
+                }
+                self.of_statement(body);
+            },
+            StatementKind::Var(_, _, Some(initializer)) | StatementKind::VarTuple(_, initializer) => self.of_expr(initializer),
+            StatementKind::AssignTuple(names, value) => {
+                self.assigned.extend(names.iter().map(|name| name.lexeme.to_string()));
+                self.of_expr(value);
+            },
+            StatementKind::Block(statements) => statements.iter().for_each(|statement| self.of_statement(statement)),
+            StatementKind::Return(_, Some(value)) => self.of_expr(value),
+            // declaring a function doesn't run its body, but it does call its decorators
+            StatementKind::Function(declaration) if !declaration.decorators.is_empty() => {
+                self.calls = true;
+                declaration.decorators.iter().for_each(|decorator| self.of_expr(decorator));
+            },
+            StatementKind::Var(_, _, None) | StatementKind::Return(_, None) | StatementKind::Function(_) => {},
+            // runs the module's code
+            StatementKind::Import(..) => self.calls = true,
+        }
+    }
+
+    fn of_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Assign(token, value) => {
+                self.assigned.push(token.lexeme.to_string());
+                self.of_expr(value);
+            },
+            ExprKind::Call(callee, _, arguments) => {
+                self.calls = true;
+                self.of_expr(callee);
+                arguments.iter().for_each(|argument| self.of_expr(argument));
+            },
+            ExprKind::List(elements) | ExprKind::Tuple(elements) => elements.iter().for_each(|element| self.of_expr(element)),
+            ExprKind::Spread(list) => self.of_expr(list),
+            ExprKind::Grouping(inner) | ExprKind::Unary(_, inner) => self.of_expr(inner),
+            ExprKind::Binary(lhs, _, rhs) | ExprKind::Logical(lhs, _, rhs) | ExprKind::Index(lhs, _, rhs) => {
+                self.of_expr(lhs);
+                self.of_expr(rhs);
+            },
+            ExprKind::Operator(..) | ExprKind::BoolLiteral(_) | ExprKind::NilLiteral | ExprKind::StringLiteral(_) | ExprKind::IntegerLiteral(_)
+                | ExprKind::FloatLiteral(_) | ExprKind::Variable(_) => {},
+        }
+    }
+}
+
+// whether running the statement is sure to end in a `return`; a loop that never ends counts too,
+// the end of the body can't be reached past it
+fn always_returns(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(..) => true,
+        StatementKind::While(condition, _) => constant_condition(condition) == Some(true),
+        StatementKind::For(_, condition, _, _) => condition.as_ref().is_none_or(|condition| constant_condition(condition) == Some(true)),
+        StatementKind::Block(statements) => statements.iter().any(always_returns),
+        StatementKind::With(_, _, body) => always_returns(body),
+        StatementKind::If(_, then_branch, Some(else_branch)) => always_returns(then_branch) && always_returns(else_branch),
+        _ => false,
+    }
+}
+
+// the type of the result, None when it depends on the values or the operation fails at runtime
+fn binary_type(operator: &TokenType, lhs: Type, rhs: Type) -> Option<Type> {
+    match operator {
+        // only numbers, booleans, strings and lists compare, each with their own kind
+        TokenType::EqualEqual | TokenType::BangEqual
+            if (lhs.is_numeric() && rhs.is_numeric()) || (lhs == rhs && matches!(lhs, Type::Boolean | Type::String | Type::List)) => Some(Type::Boolean),
+        // numbers by value, strings by code point
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+            if (lhs.is_numeric() && rhs.is_numeric()) || (lhs == Type::String && rhs == Type::String) => Some(Type::Boolean),
+        TokenType::Is => Some(Type::Boolean),
+        // anything can be in a list, only a string in a string
+        TokenType::In if rhs == Type::List || (lhs == Type::String && rhs == Type::String) => Some(Type::Boolean),
+        // integers divide into floats
+        TokenType::Slash if lhs.is_numeric() && rhs.is_numeric() => Some(Type::Float),
+        TokenType::Plus if lhs == Type::String && rhs == Type::String => Some(Type::String),
+        TokenType::Star if (lhs == Type::String && rhs == Type::Integer) || (lhs == Type::Integer && rhs == Type::String) => Some(Type::String),
+        TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::TildeSlash | TokenType::Percent if lhs == Type::Integer && rhs == Type::Integer => Some(Type::Integer),
+        TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::TildeSlash | TokenType::Percent if lhs.is_numeric() && rhs.is_numeric() => Some(Type::Float),
+        _ => None,
+    }
+}
+
+// Whether the runtime rejects the operator for operands of these types whatever their values;
+// an unknown operand could be anything, but some types fail with any partner.
+fn binary_fails(operator: &TokenType, lhs: Option<Type>, rhs: Option<Type>) -> bool {
+    match (operator, lhs, rhs) {
+        (_, Some(lhs), Some(rhs)) => binary_type(operator, lhs, rhs).is_none(),
+        (TokenType::Is, _, _) | (TokenType::In, _, None) => false,
+        (TokenType::In, None, Some(rhs)) => !matches!(rhs, Type::List | Type::String),
+        (TokenType::EqualEqual | TokenType::BangEqual, Some(known), None) | (TokenType::EqualEqual | TokenType::BangEqual, None, Some(known)) =>
+            matches!(known, Type::Nil | Type::Function),
+        // joined, repeated or ordered
+        (TokenType::Plus | TokenType::Star | TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual, Some(Type::String), None)
+            | (TokenType::Plus | TokenType::Star | TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual, None, Some(Type::String)) => false,
+        (_, Some(known), None) | (_, None, Some(known)) => !known.is_numeric(),
+        (_, None, None) => false,
+    }
+}
+
+impl Visitor<Option<Type>> for TypeChecker {
+    fn visit_expr(&mut self, e: &Expr) -> Option<Type> {
+        match &e.kind {
+            ExprKind::BoolLiteral(_) => Some(Type::Boolean),
+            ExprKind::NilLiteral => Some(Type::Nil),
+            ExprKind::StringLiteral(_) => Some(Type::String),
+            ExprKind::IntegerLiteral(_) => Some(Type::Integer),
+            ExprKind::FloatLiteral(_) => Some(Type::Float),
+            ExprKind::Operator(..) => None,
+            ExprKind::Grouping(ref expr) => self.visit_expr(expr),
+            ExprKind::Unary(ref operator, ref rhs) => {
+                let rhs = self.visit_expr(rhs)?;
+                match &operator.kind {
+                    ExprKind::Operator(TokenType::Bang, _) if rhs == Type::Boolean => Some(Type::Boolean),
+                    ExprKind::Operator(TokenType::Minus, _) if rhs.is_numeric() => Some(rhs),
+                    ExprKind::Operator(TokenType::Await, _) => None,
+                    ExprKind::Operator(_, lexeme) => {
+                        self.fails(Code::InvalidOperand, format!("'{}' can't be applied to {}", lexeme, rhs), e.span);
+                        None
+                    },
+                    _ => None,
+                }
+            },
+            ExprKind::Binary(ref lhs, ref operator, ref rhs) => {
+                let lhs = self.visit_expr(lhs);
+                let rhs = self.visit_expr(rhs);
+                let (token_type, lexeme) = match &operator.kind {
+                    ExprKind::Operator(token_type, lexeme) => (token_type, lexeme),
+                    _ => return None,
+                };
+                if binary_fails(token_type, lhs, rhs) {
+                    let operands = match (lhs, rhs) {
+                        (Some(lhs), Some(rhs)) => format!("{} and {}", lhs, rhs),
+                        (Some(known), None) | (None, Some(known)) => known.to_string(),
+                        (None, None) => String::new(),
+                    };
+                    self.fails(Code::InvalidOperand, format!("'{}' can't be applied to {}", lexeme, operands), e.span);
+                    return None;
+                }
+                binary_type(token_type, lhs?, rhs?)
+            },
+            // `or` and `and` hand back one of their operands, and the right one only runs sometimes
+            ExprKind::Logical(ref lhs, _, ref rhs) => {
+                let lhs = self.visit_expr(lhs);
+                let before = self.scopes.clone();
+                let rhs = self.visit_expr(rhs);
+                let after = mem::replace(&mut self.scopes, before);
+                self.merge(after);
+                if lhs == rhs { lhs } else { None }
+            },
+            ExprKind::Variable(token) => self.binding(&token.lexeme).and_then(Binding::current),
+            ExprKind::Assign(token, ref value) => {
+                let found = self.visit_expr(value);
+                self.assign(token, value, found);
+                found
+            },
+            ExprKind::Call(ref callee, _paren, arguments) => self.check_call(e, callee, arguments),
+            ExprKind::Spread(ref list) => {
+                if let Some(list) = self.visit_expr(list).filter(|list| *list != Type::List) {
+                    self.fails(Code::InvalidOperand, format!("{} can't be spread", list), e.span);
+                }
+                None
+            },
+            ExprKind::List(elements) | ExprKind::Tuple(elements) => {
+                for element in elements.iter() {
+                    self.visit_expr(element);
+                }
+                Some(Type::List)
+            },
+            ExprKind::Index(ref list, _bracket, ref index) => {
+                let list = self.visit_expr(list);
+                let index = self.visit_expr(index);
+                match (list, index) {
+                    (Some(list), _) if list != Type::List => self.fails(Code::InvalidOperand, format!("{} can't be indexed", list), e.span),
+                    (_, Some(index)) if index != Type::Integer => self.fails(Code::InvalidOperand, format!("a list can't be indexed with {}", index), e.span),
+                    _ => {},
+                }
+                None
+            },
+        }
+    }
+
+    fn visit_statement(&mut self, s: &Statement) -> Option<Type> {
+        match &s.kind {
+            StatementKind::Expression(ref expr) | StatementKind::Print(ref expr) => {
+                self.visit_expr(expr);
+            },
+            StatementKind::If(ref condition, ref then_branch, ref else_branch) => {
+                self.visit_expr(condition);
+                let before = self.scopes.clone();
+                self.visit_statement(then_branch);
+                let after_then = mem::replace(&mut self.scopes, before);
+                if let Some(else_branch) = else_branch {
+                    self.visit_statement(else_branch);
+                }
+                self.merge(after_then);
+            },
+            StatementKind::While(ref condition, ref body) => {
+                let mut effects = Effects::default();
+                effects.of_expr(condition);
+                effects.of_statement(body);
+                // whatever a previous iteration changed is unknown at the start of the next one
+                self.forget(&effects);
+                self.visit_expr(condition);
+                self.visit_statement(body);
+            },
+            StatementKind::For(initializer, condition, increment, body) => {
+                self.scopes.push(HashMap::new());
+                if let Some(initializer) = initializer {
+                    self.visit_statement(initializer);
+                }
+                let mut effects = Effects::default();
+                if let Some(condition) = condition {
+                    effects.of_expr(condition);
+                }
+                if let Some(increment) = increment {
+                    effects.of_expr(increment);
+                }
+                effects.of_statement(body);
+                self.forget(&effects);
+                if let Some(condition) = condition {
+                    self.visit_expr(condition);
+                }
+                self.visit_statement(body);
+                if let Some(increment) = increment {
+                    self.visit_expr(increment);
+                }
+                self.scopes.pop();
+            },
+            StatementKind::Var(name, annotation, initializer) => {
+                // the initializer can't see the variable it initializes
+                let found =
+                    match initializer {
+                        Some(initializer) => self.visit_expr(initializer),
+                        None => Some(Type::Nil),
+                    };
+                let declared = self.declare(name, annotation.as_ref(), found);
+                if let (Some(declared), Some(initializer)) = (declared, initializer) {
+                    self.expect(declared, found, initializer, &format!("'{}'", name.lexeme));
+                }
+            },
+            StatementKind::VarTuple(names, initializer) => {
+                if let Some(found) = self.visit_expr(initializer).filter(|found| *found != Type::List) {
+                    self.fails(Code::InvalidOperand, format!("{} can't be unpacked", found), initializer.span);
+                }
+                for name in names {
+                    self.declare(name, None, None);
+                }
+            },
+            StatementKind::With(name, resource, body) => {
+                // no type is a resource, a file's is unknown
+                if let Some(found) = self.visit_expr(resource) {
+                    self.fails(Code::InvalidOperand, format!("{} can't be used in with", found), resource.span);
+                }
+                self.scopes.push(HashMap::new());
+                self.declare(name, None, None);
+                self.visit_statement(body);
+                self.scopes.pop();
+            },
+            StatementKind::AssignTuple(names, value) => {
+                match &value.kind {
+                    // each value's type is known, so each can be checked against its variable's
+                    ExprKind::Tuple(values) if values.len() == names.len() => {
+                        let found: Vec<Option<Type>> = values.iter().map(|value| self.visit_expr(value)).collect();
+                        for ((name, value), found) in names.iter().zip(values).zip(found) {
+                            self.assign(name, value, found);
+                        }
+                    },
+                    _ => {
+                        if let Some(found) = self.visit_expr(value).filter(|found| *found != Type::List) {
+                            self.fails(Code::InvalidOperand, format!("{} can't be unpacked", found), value.span);
+                        }
+                        for name in names {
+                            self.assign(name, value, None);
+                        }
+                    },
+                }
+            },
+            StatementKind::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                for statement in statements.iter() {
+                    self.visit_statement(statement);
+                }
+                self.scopes.pop();
+            },
+            StatementKind::Function(declaration) => self.check_function(declaration),
+            StatementKind::Return(keyword, value) => {
+                let found = match value {
+                    Some(value) => self.visit_expr(value),
+                    None => Some(Type::Nil),
+                };
+                if let Some(Some(returns)) = self.functions.last().copied() {
+                    match value {
+                        Some(value) => self.expect_return(returns, found, value.span),
+                        None => self.expect_return(returns, found, keyword.span()),
+                    }
+                }
+            },
+            // the module can assign any global
+            StatementKind::Import(..) => self.forget_globals(),
+        }
+        None
+    }
+}
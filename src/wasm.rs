@@ -0,0 +1,76 @@
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use wasm_bindgen::prelude::*;
+use crate::json::Json;
+use crate::printer::json::JsonPrinter;
+use crate::scanner;
+use crate::Interpreter;
+use crate::InterpreterBuilder;
+
+// Browser facade: everything comes in as strings and goes out as strings (JSON for the
+// structured results), nothing touches stdin/stdout.
+
+// every token, trivia included, as [{type, lexeme, line, column}]
+#[wasm_bindgen]
+pub fn scan_js(source: &str) -> String {
+    let tokens = scanner::scan_tokens(source).iter()
+        .map(|token| Json::object(vec![
+            ("type", Json::string(&format!("{:?}", token.token_type))),
+            ("lexeme", Json::string(&token.lexeme)),
+            ("line", Json::Number(token.line_number as f64)),
+            ("column", Json::Number(token.column_number as f64)),
+        ]))
+        .collect();
+    Json::Array(tokens).to_string()
+}
+
+// the syntax tree in the same shape as --dump-ast=json
+#[wasm_bindgen]
+pub fn parse_js(source: &str) -> Result<String, JsValue> {
+    let statements = crate::parse(&scanner::scan_tokens(source))
+        .map_err(|error| JsValue::from_str(&error.to_string()))?;
+    Ok(JsonPrinter{}.visit_statements(&statements).to_string())
+}
+
+// Runs the program and returns its final value. Every printed line (without its newline) is
+// handed to `print`; JS is blocked while the program runs anyway, so the lines are collected
+// and delivered once it finishes rather than keeping a JS function inside the interpreter.
+#[wasm_bindgen]
+pub fn eval_js(source: &str, print: &js_sys::Function) -> Result<String, JsValue> {
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    // there is no system clock or environment to read in the browser
+    let mut interpreter: Interpreter = InterpreterBuilder::new().deterministic(true).sandboxed(true).build().into();
+    interpreter.set_output(Box::new(SharedOutput{buffer: printed.clone()}));
+    interpreter.set_error_output(Box::new(io::sink()));
+    // and no stdin, input() sees the end of the input straight away
+    interpreter.set_input(Box::new(io::empty()));
+    let result = interpreter.interpret(source);
+
+    let printed = printed.lock().unwrap_or_else(PoisonError::into_inner);
+    for line in String::from_utf8_lossy(&printed).lines() {
+        print.call1(&JsValue::NULL, &JsValue::from_str(line))?;
+    }
+
+    match result {
+        Ok(value) => Ok(value.to_string()),
+        Err(error) => Err(JsValue::from_str(&error.to_string())),
+    }
+}
+
+struct SharedOutput {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.lock().unwrap_or_else(PoisonError::into_inner).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
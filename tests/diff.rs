@@ -0,0 +1,50 @@
+use crafty::diff::diff;
+use crafty::diff::ChangeKind;
+use crafty::parse_source;
+
+const OLD: &str = r#"
+var limit = 10;
+fun area(w, h) {
+  var a = w * h;
+  return a;
+}
+fun gone() {}
+print area(1, 2);
+print "done";
+"#;
+
+// the same program reordered, reformatted and commented, with a few real changes
+const NEW: &str = r#"
+// area first now
+fun area(w, h) {
+
+  var a = w * h;  // unchanged
+  return a + 1;
+}
+var   limit=10;
+fun fresh() {}
+print area(1, 2);
+print "finished";
+"#;
+
+#[test]
+fn layout_and_comments_are_not_changes() {
+    let old = parse_source(OLD).unwrap();
+    let reformatted = parse_source("var limit = 10;\n\n\nfun area(w, h) { var a = w * h; return a; } // same\nfun gone() {}\nprint area(1,2); print \"done\";").unwrap();
+    assert!(diff(&old, &reformatted).is_empty());
+}
+
+#[test]
+fn declarations_are_matched_by_name_and_statements_in_order() {
+    let changes = diff(&parse_source(OLD).unwrap(), &parse_source(NEW).unwrap());
+    let described: Vec<(ChangeKind, &str)> = changes.iter().map(|change| (change.kind, change.what.as_str())).collect();
+    assert_eq!(described, vec![
+        (ChangeKind::Changed, "fun area"),
+        (ChangeKind::Removed, "fun gone"),
+        (ChangeKind::Added, "fun fresh"),
+        (ChangeKind::Removed, "statement"),
+        (ChangeKind::Added, "statement"),
+    ]);
+    assert_eq!(changes[0].to_string(), "changed fun area (line 3 -> 3)\n    fun area(w, h) {\n      var a = w * h;\n  -   return a;\n  +   return a + 1;\n    }\n");
+    assert_eq!(changes[4].to_string(), "added statement (line 11)\n  + print \"finished\";\n");
+}
@@ -0,0 +1,54 @@
+use crafty::docs;
+use crafty::docs::Kind;
+use crafty::parse_all;
+use crafty::scanner::scan_with_trivia;
+
+const SOURCE: &str = r#"// not documentation
+
+/// Sides a shape
+/// may have.
+var MAX_SIDES: Integer = 12;
+
+/// left behind by the blank line
+
+fun helper() {}
+
+/// The area of a rectangle.
+@memoize
+fun area(width, height) -> Integer {
+  "Multiplies <width> by height.";
+  return width * height;
+}
+
+print area(2, 3);
+"#;
+
+fn items() -> Vec<docs::Item> {
+    let tokens = scan_with_trivia(SOURCE);
+    let statements = parse_all(&tokens).unwrap();
+    docs::items(&tokens, &statements)
+}
+
+#[test]
+fn declarations_collect_their_comments_and_docstrings() {
+    let items = items();
+    let described: Vec<(Kind, &str, &str, Option<&str>)> = items.iter()
+        .map(|item| (item.kind, item.name.as_str(), item.signature.as_str(), item.doc.as_deref()))
+        .collect();
+    assert_eq!(described, vec![
+        (Kind::Variable, "MAX_SIDES", "var MAX_SIDES: Integer", Some("Sides a shape\nmay have.")),
+        (Kind::Function, "helper", "fun helper()", None),
+        (Kind::Function, "area", "fun area(width, height) -> Integer", Some("The area of a rectangle.\n\nMultiplies <width> by height.")),
+    ]);
+}
+
+#[test]
+fn pages_list_functions_then_variables() {
+    let items = items();
+    let markdown = docs::markdown("shapes", &items);
+    assert!(markdown.starts_with("# shapes\n\n## Functions\n\n### `helper`\n\n```\nfun helper()\n```\n"));
+    assert!(markdown.ends_with("## Variables\n\n### `MAX_SIDES`\n\n```\nvar MAX_SIDES: Integer\n```\n\nSides a shape\nmay have.\n"));
+
+    let html = docs::html("shapes", &items);
+    assert!(html.contains("<h3 id=\"area\"><code>area</code></h3>\n<pre><code>fun area(width, height) -&gt; Integer</code></pre>\n<p>The area of a rectangle.</p>\n<p>Multiplies &lt;width&gt; by height.</p>\n"));
+}
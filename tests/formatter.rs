@@ -0,0 +1,22 @@
+use crafty::formatter::Formatter;
+use crafty::parse_all;
+use crafty::scanner::scan_with_trivia;
+
+fn formatted(source: &str) -> String {
+    let tokens = scan_with_trivia(source);
+    let statements = parse_all(&tokens).unwrap();
+    Formatter::new(&tokens).format(&statements)
+}
+
+#[test]
+fn trailing_comments_stay_after_the_last_token_on_their_line() {
+    assert_eq!(formatted("if(x>0){print x;}else print \"no\"; // else\n"), "if (x > 0) {\n  print x;\n} else\n  print \"no\";  // else\n");
+    assert_eq!(formatted("{ print 1; // one\n}\n"), "{\n  print 1;  // one\n}\n");
+}
+
+#[test]
+fn empty_files_stay_empty() {
+    assert_eq!(formatted(""), "");
+    assert_eq!(formatted("\n\n"), "");
+    assert_eq!(formatted("// only a comment\n"), "// only a comment\n");
+}
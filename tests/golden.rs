@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Every tests/golden/**/*.cty (or *.crafty) script is run with the crafty binary and checked
+// against the comments in it:
+//
+//     print 1 + 2; // expect: 3
+//     print x;     // expect-error: Undefined variable 'x'
+//
+// `expect:` lines are the program's output, in order and nothing else. `expect-error:` is an
+// error reported on the line of the comment whose message contains the text; a script with any
+// of them has to fail, a script without them has to succeed. `// flags: --lox-compat` passes
+// command line flags to the run. Modules the scripts import go in `helpers` directories, which
+// aren't run on their own, and a file of any other kind fails the test rather than being skipped.
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_ERROR: &str = "// expect-error: ";
+const FLAGS: &str = "// flags: ";
+
+struct Expectations {
+    flags: Vec<String>,
+    output: Vec<String>,
+    // line, part of the message
+    errors: Vec<(u32, String)>,
+}
+
+fn expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations{flags: Vec::new(), output: Vec::new(), errors: Vec::new()};
+    for (index, line) in source.lines().enumerate() {
+        if let Some(flags) = line.strip_prefix(FLAGS) {
+            expectations.flags.extend(flags.split_whitespace().map(str::to_string));
+        }
+        if let Some(position) = line.find(EXPECT) {
+            expectations.output.push(line[position + EXPECT.len()..].to_string());
+        }
+        if let Some(position) = line.find(EXPECT_ERROR) {
+            expectations.errors.push((index as u32 + 1, line[position + EXPECT_ERROR.len()..].to_string()));
+        }
+    }
+    expectations
+}
+
+// the line and message of every error in human readable diagnostics:
+//
+//     error[E0201]: Undefined variable 'x'
+//      --> script.cty:3:7
+fn reported_errors(stderr: &str) -> Vec<(u32, String)> {
+    let mut errors = Vec::new();
+    let mut lines = stderr.lines();
+    while let Some(line) = lines.next() {
+        let message = match line.strip_prefix("error[").and_then(|rest| rest.split_once("]: ")) {
+            Some((_code, message)) => message.to_string(),
+            None => continue,
+        };
+        let location = lines.next().and_then(|location| location.trim_start().strip_prefix("--> "));
+        let line_number = location
+            .and_then(|location| location.rsplit(':').nth(1))
+            .and_then(|line_number| line_number.parse().ok())
+            .unwrap_or(0);
+        errors.push((line_number, message));
+    }
+    errors
+}
+
+const EXTENSIONS: [&str; 2] = ["cty", "crafty"];
+
+// the scripts under `directory`, and the files that aren't scripts
+fn scripts(directory: &Path, found: &mut Vec<PathBuf>, unknown: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        let script = path.extension().is_some_and(|extension| EXTENSIONS.iter().any(|known| extension == *known));
+        if path.is_dir() && path.file_name().is_some_and(|name| name == "helpers") {
+            continue;
+        } else if path.is_dir() {
+            scripts(&path, found, unknown);
+        } else if script {
+            found.push(path);
+        } else {
+            unknown.push(path);
+        }
+    }
+}
+
+// what is wrong with the script's run, if anything
+fn check(script: &Path) -> Option<String> {
+    let source = fs::read_to_string(script).unwrap();
+    let expected = expectations(&source);
+    let run = Command::new(env!("CARGO_BIN_EXE_crafty"))
+        .arg("--no-color")
+        .args(&expected.flags)
+        .arg(script)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let stderr = String::from_utf8_lossy(&run.stderr);
+
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expected.output {
+        return Some(format!("expected output:\n{}\nactual output:\n{}\nstderr:\n{}", expected.output.join("\n"), output.join("\n"), stderr));
+    }
+
+    let errors = reported_errors(&stderr);
+    for (line, text) in expected.errors.iter() {
+        if !errors.iter().any(|(error_line, message)| error_line == line && message.contains(text.as_str())) {
+            return Some(format!("expected an error on line {} containing {:?}, stderr:\n{}", line, text, stderr));
+        }
+    }
+    if expected.errors.is_empty() && !run.status.success() {
+        return Some(format!("failed with {}, stderr:\n{}", run.status, stderr));
+    }
+    if !expected.errors.is_empty() && run.status.success() {
+        return Some("expected errors, but it succeeded".to_string());
+    }
+    None
+}
+
+#[test]
+fn golden_scripts() {
+    let (mut found, mut unknown) = (Vec::new(), Vec::new());
+    scripts(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden"), &mut found, &mut unknown);
+    assert!(!found.is_empty(), "no scripts in tests/golden");
+    assert!(unknown.is_empty(), "not .cty or .crafty scripts, rename or move them: {:?}", unknown);
+
+    let failures: Vec<String> = found.iter()
+        .filter_map(|script| check(script).map(|failure| format!("{}: {}", script.display(), failure)))
+        .collect();
+    assert!(failures.is_empty(), "{} of {} golden scripts failed:\n\n{}", failures.len(), found.len(), failures.join("\n\n"));
+}
@@ -0,0 +1,37 @@
+use crafty::highlight::highlight;
+use crafty::highlight::Format;
+
+const SOURCE: &str = "var x = \"a<b\"; // hi\nprint x ~/ 2 and nil;\n12abc\n";
+
+// the escapes taken out, or the spans and entities for HTML, leave the source as it was
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            chars.by_ref().find(|&ch| ch == 'm');
+        } else {
+            stripped.push(ch);
+        }
+    }
+    stripped
+}
+
+#[test]
+fn ansi_keeps_the_source() {
+    let highlighted = highlight(SOURCE, Format::Ansi);
+    assert_eq!(strip_ansi(&highlighted), SOURCE);
+    assert!(highlighted.contains("\x1b[1;35mvar\x1b[0m"));
+    assert!(highlighted.contains("\x1b[90m// hi\x1b[0m"));
+}
+
+#[test]
+fn html_spans_tokens_by_class() {
+    let highlighted = highlight(SOURCE, Format::Html);
+    assert!(highlighted.starts_with("<pre class=\"crafty\"><code><span class=\"keyword\">var</span> x "));
+    assert!(highlighted.contains("<span class=\"string\">&quot;a&lt;b&quot;</span>"));
+    assert!(highlighted.contains("<span class=\"operator\">~/</span>"));
+    assert!(highlighted.contains("<span class=\"keyword\">and</span> <span class=\"constant\">nil</span>;"));
+    assert!(highlighted.contains("<span class=\"invalid\">12abc</span>"));
+    assert!(highlighted.ends_with("</code></pre>\n"));
+}
@@ -0,0 +1,154 @@
+use proptest::prelude::*;
+use proptest::sample::Index;
+use crafty::document::Document;
+use crafty::parse;
+use crafty::printer::json::JsonPrinter;
+use crafty::scanner::relex;
+use crafty::scanner::scan_tokens;
+use crafty::scanner::token::Span;
+use crafty::scanner::token::Token;
+use crafty::scanner::token::TokenType;
+
+// pieces that open and close strings and comments, change how numbers and names end, and make
+// and break statements, so edits land everywhere the incremental paths have to get right
+const FRAGMENTS: [&str; 34] = [
+    "var a = 1;", "print a;", "\n", " ", "if (a) print 2;", " else ", "{", "}", ";", "a = a + 1;",
+    "while (a < 3) a = a + 1;", "for (var i = 0; i < 2; i = i + 1) print i;", "f(1, 2);", "(", ")", "1", ".", "5", ".5",
+    "//c\n", "//", "\"s\"", "\"", "\"two\nlines\"", "x", "-", "var b: Integer = 2;", "fun g(p: Integer) -> Integer {",
+    "return p;", "[1, 2]", "x[0]", "my_var", "import \"m\";", "é",
+];
+
+fn text() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(FRAGMENTS.to_vec()), 0..10).prop_map(|fragments| fragments.concat())
+}
+
+// where to cut, what to cut up to, and what to put in instead
+fn edits() -> impl Strategy<Value = Vec<(Index, Index, String)>> {
+    let inserted = prop::collection::vec(prop::sample::select(FRAGMENTS.to_vec()), 0..3).prop_map(|fragments| fragments.concat());
+    prop::collection::vec((any::<Index>(), any::<Index>(), inserted), 1..4)
+}
+
+// line and column of a byte offset, counted like tokens count them
+fn position(source: &str, offset: usize) -> (u32, u32) {
+    source[..offset].chars().fold((1, 0), |(line, column), ch| if ch == '\n' { (line + 1, 0) } else { (line, column + 1) })
+}
+
+// the range from one byte offset to another, and the source with it replaced
+fn replaced(source: &str, start: usize, end: usize, text: &str) -> (Span, String) {
+    let (line_number, column_number) = position(source, start);
+    let (end_line_number, end_column_number) = position(source, end);
+    let range = Span{line_number, column_number, end_line_number, end_column_number};
+    (range, format!("{}{}{}", &source[..start], text, &source[end..]))
+}
+
+fn boundaries(source: &str) -> Vec<usize> {
+    source.char_indices().map(|(offset, _)| offset).chain(std::iter::once(source.len())).collect()
+}
+
+fn described(tokens: &[Token]) -> Vec<(TokenType, String, u32, u32)> {
+    tokens.iter().map(|token| (token.token_type.clone(), token.lexeme.to_string(), token.line_number, token.column_number)).collect()
+}
+
+// statements as JSON, positions included, or the error
+fn rendered(document: &Document) -> String {
+    match document.statements() {
+        Ok(statements) => JsonPrinter{}.visit_statements(statements).to_string(),
+        Err(error) => format!("error: {}", error),
+    }
+}
+
+fn fresh(source: &str) -> String {
+    match parse(&scan_tokens(source)) {
+        Ok(statements) => JsonPrinter{}.visit_statements(&statements).to_string(),
+        Err(error) => format!("error: {}", error),
+    }
+}
+
+// relexes the tokens of `source` after an edit, then checks them against scanning the new source
+fn assert_relex(source: &str, start: usize, end: usize, text: &str) {
+    let mut tokens = scan_tokens(source);
+    let (range, source) = replaced(source, start, end, text);
+    relex(&mut tokens, &source, range, text);
+    assert_eq!(described(&tokens), described(&scan_tokens(&source)), "source: {:?}", source);
+}
+
+// edits the document, then checks it against the same source parsed from scratch
+fn assert_edit(document: &mut Document, start: usize, end: usize, text: &str) {
+    let (range, source) = replaced(document.source(), start, end, text);
+    document.edit(range, text);
+    assert_eq!(document.source(), source);
+    assert_eq!(described(document.tokens()), described(&scan_tokens(&source)), "source: {:?}", source);
+    assert_eq!(rendered(document), fresh(&source), "source: {:?}", source);
+}
+
+proptest! {
+    #[test]
+    fn edited_documents_match_a_fresh_parse(source in text(), edits in edits()) {
+        let mut document = Document::new(source);
+        for (start, end, text) in edits {
+            let boundaries = boundaries(document.source());
+            let (start, end) = (boundaries[start.index(boundaries.len())], boundaries[end.index(boundaries.len())]);
+            assert_edit(&mut document, start.min(end), start.max(end), &text);
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn relexed_tokens_match_a_fresh_scan(source in text(), start: Index, end: Index, text in text()) {
+        let boundaries = boundaries(&source);
+        let (start, end) = (boundaries[start.index(boundaries.len())], boundaries[end.index(boundaries.len())]);
+        assert_relex(&source, start.min(end), start.max(end), &text);
+    }
+}
+
+#[test]
+fn relexing_at_the_ends_and_across_strings() {
+    let source = "var a = 1;\nprint \"one\ntwo\nthree\";\nprint a;\n";
+    let string = source.find('"').unwrap();
+    let string_end = source.rfind('"').unwrap() + 1;
+
+    // at the start, including gluing onto and splitting off the first token
+    assert_relex(source, 0, 0, "x");
+    assert_relex(source, 0, 0, "\n");
+    assert_relex(source, 0, 3, "print");
+    assert_relex(source, 0, source.len(), "");
+
+    // at the end, including after the last newline and into the last token
+    assert_relex(source, source.len(), source.len(), "print 2;");
+    assert_relex(source, source.len(), source.len(), "\"");
+    assert_relex(source, source.len() - 2, source.len(), "");
+
+    // inside, over and out of a string running across lines
+    let inside = source.find("two").unwrap();
+    assert_relex(source, inside, inside + 3, "2\n2");
+    assert_relex(source, inside, inside, "\"");
+    assert_relex(source, string, string_end, "1");
+    assert_relex(source, string - 1, inside, "");
+    assert_relex(source, string + 1, string_end + 4, "\nmore");
+
+    // a number the edit extends from behind, `1.` becoming `1.5`
+    assert_relex("print 1.;", 8, 8, "5");
+    assert_relex("1.", 2, 2, "5");
+}
+
+#[test]
+fn edits_opening_and_closing_strings_and_comments() {
+    let source = "var a = 1;\nprint a;\nvar b = \"x\";\nprint b;\n";
+    let mut document = Document::new(source.to_string());
+
+    // a quote turns the rest of the file into an unterminated string, removing it turns it back
+    let quote = document.source().find("print a").unwrap();
+    assert_edit(&mut document, quote, quote, "\"");
+    assert_edit(&mut document, quote, quote + 1, "");
+
+    // a string across lines, closed further down
+    assert_edit(&mut document, quote, quote, "print \"open\n");
+    let close = document.source().find("print a").unwrap();
+    assert_edit(&mut document, close, close, "\";\n");
+
+    // a comment hiding the rest of its line, then taken out
+    let line = document.source().find("var b").unwrap();
+    assert_edit(&mut document, line, line, "//");
+    assert_edit(&mut document, line, line + 2, "");
+}
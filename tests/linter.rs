@@ -0,0 +1,85 @@
+use crafty::linter::warnings;
+use crafty::parse_all;
+use crafty::scanner::scan_with_trivia;
+
+// each warning as its rule and message
+fn lint(source: &str) -> Vec<(&'static str, String)> {
+    let tokens = scan_with_trivia(source);
+    let statements = parse_all(&tokens).unwrap();
+    warnings(&tokens, &statements).into_iter().map(|warning| (warning.rule, warning.message)).collect()
+}
+
+#[test]
+fn globals_declared_after_a_function_that_reads_them_are_read() {
+    assert!(lint("fun g(){return y;} var y=7; print g();").is_empty());
+}
+
+#[test]
+fn unused_globals_are_still_reported() {
+    assert_eq!(lint("var unused = 1;\nfun f() { return 2; }\nprint f();"), vec![("unused-variable", "variable 'unused' is never read".to_string())]);
+}
+
+#[test]
+fn function_reading_a_global_declared_later() {
+    let source = "fun describe() {\n  print limit;\n}\nvar limit = 10;\ndescribe();\n";
+    assert!(lint(source).is_empty());
+}
+
+#[test]
+fn mutually_recursive_functions() {
+    let source = r#"
+fun is_even(n) {
+  if (n == 0) return true;
+  return is_odd(n - 1);
+}
+fun is_odd(n) {
+  if (n == 0) return false;
+  return is_even(n - 1);
+}
+print is_even(10);
+"#;
+    assert!(lint(source).is_empty());
+}
+
+// functions don't capture locals, so a nested function reads globals, which may come after it
+#[test]
+fn nested_function_reading_a_global_declared_after_it() {
+    let source = r#"
+{
+  fun report() {
+    print total;
+  }
+  report();
+}
+fun outer() {
+  fun inner() { return total + 1; }
+  return inner();
+}
+var total = 3;
+print outer();
+"#;
+    assert!(lint(source).is_empty());
+}
+
+// a value read on one branch but not the other may still be read, so it isn't reported
+#[test]
+fn assignments_read_in_either_branch_are_read() {
+    assert!(lint("var x = 1; if (x = 2) print x;").iter().all(|(rule, _)| *rule != "unused-assignment"));
+    assert!(lint("var x = 1;\nx = 2;\nif (argc() > 1) print 1; else print x;\n").is_empty());
+    assert!(lint("var x = 1;\nx = 2;\nvar y = false or x;\nprint y;\n").is_empty());
+}
+
+#[test]
+fn assignments_read_on_neither_branch_are_reported() {
+    let source = "var x = 1;\nx = 2;\nif (argc() > 1) print 1; else print 2;\nx = 3;\nprint x;\n";
+    assert_eq!(lint(source), vec![("unused-assignment", "value assigned to 'x' is overwritten before being read".to_string())]);
+    let source = "var x = 1;\nif (argc() > 1) x = 2;\nx = 3;\nprint x;\n";
+    assert_eq!(lint(source), vec![("unused-assignment", "value assigned to 'x' is overwritten before being read".to_string())]);
+}
+
+#[test]
+fn self_comparison_is_only_for_variables_and_their_elements() {
+    assert_eq!(lint("var a = 1;\nprint a == a;\n"), vec![("self-comparison", "comparing 'a' with itself".to_string())]);
+    assert_eq!(lint("var xs = [1];\nprint xs[0] < xs[0];\n").len(), 1);
+    assert!(lint("print 1 >= 1;\nprint true == true;\nprint \"a\" != \"a\";\n").is_empty());
+}
@@ -0,0 +1,162 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Runs the test suite of Crafting Interpreters (the test/ directory of
+// https://github.com/munificent/craftinginterpreters) with `crafty --lox-compat` and reports
+// which files pass. It isn't part of the normal run, since the suite isn't in this repository
+// and crafty doesn't have all of Lox (classes, closures), so plenty of it fails:
+//
+//     CRAFTY_LOX_TESTS=../craftinginterpreters/test cargo test --test lox -- --ignored --nocapture
+//
+// A file passes when its output is its `// expect: ` lines, and it fails the way jlox does when
+// it has `// expect runtime error: ` (exit status 70, the message reported) or compile errors
+// such as `// [line 3] Error at 'x': ...` (exit status 65, an error reported on each line).
+
+// chapters' partial interpreters and performance tests, which jlox's own runner skips too
+const SKIPPED: &[&str] = &["benchmark", "expressions", "scanning"];
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+struct Expectations {
+    output: Vec<String>,
+    runtime_error: Option<String>,
+    // lines compile errors are expected on
+    compile_errors: Vec<u32>,
+}
+
+fn expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations{output: Vec::new(), runtime_error: None, compile_errors: Vec::new()};
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index as u32 + 1;
+        if let Some(position) = line.find(EXPECT) {
+            expectations.output.push(line[position + EXPECT.len()..].to_string());
+        } else if let Some(position) = line.find(EXPECT_RUNTIME_ERROR) {
+            expectations.runtime_error = Some(line[position + EXPECT_RUNTIME_ERROR.len()..].to_string());
+        } else if let Some(error) = compile_error(line) {
+            expectations.compile_errors.push(error.unwrap_or(line_number));
+        }
+    }
+    expectations
+}
+
+// `// Error ...` on the line it is about, `// [line 3] Error ...` or `// [java line 3] Error ...`
+// elsewhere; clox's `// [c line 3]` ones are left out
+fn compile_error(line: &str) -> Option<Option<u32>> {
+    let comment = &line[line.find("// ")? + 3..];
+    if comment.starts_with("Error") {
+        return Some(None);
+    }
+    let rest = comment.strip_prefix("[line ").or_else(|| comment.strip_prefix("[java line "))?;
+    let (line_number, message) = rest.split_once(']')?;
+    if !message.trim_start().starts_with("Error") {
+        return None;
+    }
+    line_number.parse().ok().map(Some)
+}
+
+// the line of every error in human readable diagnostics
+fn reported_lines(stderr: &str) -> Vec<u32> {
+    stderr.lines()
+        .filter_map(|line| line.trim_start().strip_prefix("--> "))
+        .filter_map(|location| location.rsplit(':').nth(1))
+        .filter_map(|line_number| line_number.parse().ok())
+        .collect()
+}
+
+fn scripts(directory: &Path, found: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        let skipped = path.file_name().is_some_and(|name| SKIPPED.iter().any(|skipped| name == *skipped));
+        if path.is_dir() && !skipped {
+            scripts(&path, found);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            found.push(path);
+        }
+    }
+}
+
+// what is wrong with the script's run, if anything
+fn check(script: &Path) -> Option<String> {
+    let source = fs::read_to_string(script).unwrap();
+    let expected = expectations(&source);
+    let run = Command::new(env!("CARGO_BIN_EXE_crafty"))
+        .arg("--no-color")
+        .arg("--lox-compat")
+        .arg(script)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    // only the messages, the source lines quoted under them hold the expectations themselves
+    let messages: Vec<&str> = stderr.lines()
+        .filter_map(|line| line.strip_prefix("error[").and_then(|rest| rest.split_once("]: ")))
+        .map(|(_code, message)| message)
+        .collect();
+    let first_error = || messages.first().copied().unwrap_or("").to_string();
+
+    if !expected.compile_errors.is_empty() {
+        if run.status.code() != Some(65) {
+            return Some(format!("expected compile errors, exited with {}", run.status));
+        }
+        let reported = reported_lines(&stderr);
+        return expected.compile_errors.iter()
+            .find(|line| !reported.contains(line))
+            .map(|line| format!("expected a compile error on line {}, got {:?}", line, first_error()));
+    }
+
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expected.output {
+        let mismatch = output.iter().zip(expected.output.iter()).position(|(actual, expected)| actual != expected)
+            .unwrap_or_else(|| output.len().min(expected.output.len()));
+        return Some(format!(
+            "output line {} is {:?}, expected {:?} {:?}",
+            mismatch + 1,
+            output.get(mismatch).unwrap_or(&"<nothing>"),
+            expected.output.get(mismatch).map_or("<nothing>", String::as_str),
+            first_error(),
+        ));
+    }
+
+    match expected.runtime_error {
+        Some(message) if run.status.code() != Some(70) => Some(format!("expected runtime error {:?}, exited with {}", message, run.status)),
+        Some(message) if !messages.iter().any(|reported| reported.contains(&message)) => Some(format!("expected runtime error {:?}, got {:?}", message, first_error())),
+        Some(_) => None,
+        None if !run.status.success() => Some(format!("exited with {}: {:?}", run.status, first_error())),
+        None => None,
+    }
+}
+
+#[test]
+#[ignore]
+fn lox_test_suite() {
+    let directory = match env::var_os("CRAFTY_LOX_TESTS") {
+        Some(directory) => PathBuf::from(directory),
+        None => {
+            println!("set CRAFTY_LOX_TESTS to the test directory of a craftinginterpreters checkout");
+            return;
+        },
+    };
+    let mut found = Vec::new();
+    scripts(&directory, &mut found);
+    assert!(!found.is_empty(), "no .lox files in {}", directory.display());
+
+    let mut passed = 0;
+    for script in found.iter() {
+        let name = script.strip_prefix(&directory).unwrap_or(script).display();
+        match check(script) {
+            None => {
+                passed += 1;
+                println!("PASS {}", name);
+            },
+            Some(failure) => println!("FAIL {}: {}", name, failure),
+        }
+    }
+    println!("\n{} of {} passed", passed, found.len());
+}
@@ -0,0 +1,106 @@
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use crafty::parse_all;
+use crafty::printer::minify::minify;
+use crafty::scanner::scan_tokens;
+use crafty::Interpreter;
+
+const PROGRAM: &str = r#"
+// a comment that goes away
+var total = 0;
+fun add(first, second) {
+  var result = first + second;
+  if (result > 10) print "big"; else print result;
+  return result;
+}
+{
+  var inner = 3;
+  for (var index = 0; index < 3; index = index + 1) {
+    total = total + add(inner, index);
+  }
+}
+var xs = [1, 2, 3];
+print 1 in xs;
+print -(-1);
+print 1.5 - -2;
+print total;
+"#;
+
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn output(source: &str) -> String {
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(Captured(printed.clone())));
+    interpreter.interpret(source).unwrap();
+    let printed = printed.lock().unwrap().clone();
+    String::from_utf8(printed).unwrap()
+}
+
+fn minified(source: &str, rename_locals: bool) -> String {
+    let tokens = scan_tokens(source);
+    let statements = parse_all(&tokens).unwrap();
+    minify(&tokens, &statements, rename_locals)
+}
+
+#[test]
+fn spaces_only_where_tokens_would_run_together() {
+    assert_eq!(
+        minified(PROGRAM, false),
+        "var total=0;fun add(first,second){var result=first+second;if(result>10)print\"big\";else print result;return result;}{var inner=3;for(var index=0;index<3;index=index+1){total=total+add(inner,index);}}var xs=[1,2,3];print 1 in xs;print-(-1);print 1.5--2;print total;\n",
+    );
+}
+
+#[test]
+fn locals_get_short_names_and_globals_keep_theirs() {
+    assert_eq!(
+        minified(PROGRAM, true),
+        "var total=0;fun add(a,c){var b=a+c;if(b>10)print\"big\";else print b;return b;}{var b=3;for(var a=0;a<3;a=a+1){total=total+add(b,a);}}var xs=[1,2,3];print 1 in xs;print-(-1);print 1.5--2;print total;\n",
+    );
+}
+
+#[test]
+fn minified_programs_print_the_same() {
+    let shadowing = r#"
+var a = "global a";
+var b = "global b";
+fun show(b) {
+  var shown = b + " via " + a;
+  print shown;
+}
+fun outer(value) {
+  fun inner(value) { return value * 2; }
+  return inner(value) + 1;
+}
+{
+  var a = "block a";
+  show(a);
+  print outer(20);
+}
+show(b);
+"#;
+    for source in [PROGRAM, shadowing] {
+        let expected = output(source);
+        assert_eq!(output(&minified(source, false)), expected);
+        assert_eq!(output(&minified(source, true)), expected);
+    }
+}
+
+#[test]
+fn nothing_is_renamed_when_modules_are_imported() {
+    let source = "import \"strings\";\nfun f(long_name) { return long_name; }\n";
+    assert_eq!(minified(source, true), minified(source, false));
+}
@@ -0,0 +1,28 @@
+use proptest::prelude::*;
+use crafty::scanner::scan_in_chunks;
+use crafty::scanner::Scanner;
+use crafty::scanner::token::Token;
+use crafty::scanner::token::TokenType;
+
+// Scanning in pieces has to give exactly the tokens, positions included, of scanning in one go,
+// whatever the pieces are cut through: strings across lines, comments with quotes in them, `~/`
+// next to `//`, unterminated strings and characters outside ASCII.
+fn source() -> impl Strategy<Value = String> {
+    let fragments = prop::sample::select(vec![
+        "\n", "\n", "\r\n", " ", "x", "var a = 1;", "\"", "\"text\"", "\"two\nlines\"", "//", "// a \"quoted\" comment",
+        "~/", "~//", "/", "1.", "1.5", "...", ".", "é", "ü\n", "print a;", "{", "}", "12abc",
+    ]);
+    prop::collection::vec(fragments, 0..80).prop_map(|fragments| fragments.concat())
+}
+
+fn described(tokens: Vec<Token>) -> Vec<(TokenType, String, u32, u32)> {
+    tokens.into_iter().map(|token| (token.token_type, token.lexeme.to_string(), token.line_number, token.column_number)).collect()
+}
+
+proptest! {
+    #[test]
+    fn pieces_scan_like_the_whole(source in source(), chunks in 1..8usize) {
+        let whole = described(Scanner::new(&source).collect());
+        prop_assert_eq!(described(scan_in_chunks(&source, chunks)), whole, "source: {:?}", source);
+    }
+}
@@ -0,0 +1,323 @@
+use std::sync::Arc;
+use proptest::prelude::*;
+use crafty::formatter::Formatter;
+use crafty::parser::Expr;
+use crafty::parser::ExprKind;
+use crafty::parser::FunctionDeclaration;
+use crafty::parser::Parameter;
+use crafty::parser::Statement;
+use crafty::parser::StatementKind;
+use crafty::scanner::token::Span;
+use crafty::scanner::token::Token;
+use crafty::scanner::token::TokenType;
+
+// Random syntax trees, printed by the formatter, have to parse back into the same tree, and the
+// printed program has to scan into the same tokens as the reparsed one printed again. The trees
+// are the ones the parser could have built: `return` only in functions, `var` and `fun` only
+// where a declaration goes, and no `if` without an `else` right before one.
+
+const KEYWORDS: &[&str] = &["and", "async", "await", "class", "else", "false", "for", "fun", "if", "import", "in", "is", "nil", "or", "print", "return", "super", "this", "true", "var", "while", "with"];
+
+const BINARY_OPERATORS: &[(TokenType, &str)] = &[
+    (TokenType::Plus, "+"),
+    (TokenType::Minus, "-"),
+    (TokenType::Star, "*"),
+    (TokenType::Slash, "/"),
+    (TokenType::TildeSlash, "~/"),
+    (TokenType::Percent, "%"),
+    (TokenType::EqualEqual, "=="),
+    (TokenType::BangEqual, "!="),
+    (TokenType::Less, "<"),
+    (TokenType::LessEqual, "<="),
+    (TokenType::Greater, ">"),
+    (TokenType::GreaterEqual, ">="),
+    (TokenType::In, "in"),
+    (TokenType::Is, "is"),
+];
+
+fn token(token_type: TokenType, lexeme: &str) -> Token {
+    Token::new(token_type, lexeme.to_string(), 0, 0)
+}
+
+fn expr(kind: ExprKind) -> Expr {
+    Expr{kind, span: Span::default(), resolution: Default::default()}
+}
+
+fn statement(kind: StatementKind) -> Statement {
+    Statement::new(kind, Span::default())
+}
+
+fn operator(token_type: TokenType, lexeme: &str) -> Box<Expr> {
+    Box::new(expr(ExprKind::Operator(token_type, lexeme.to_string())))
+}
+
+fn name() -> impl Strategy<Value = Token> {
+    "[a-z][a-z0-9_]{0,5}"
+        .prop_filter("keyword", |name| !KEYWORDS.contains(&name.as_str()))
+        .prop_map(|name| token(TokenType::Identifier, &name))
+}
+
+fn type_name() -> impl Strategy<Value = Token> {
+    prop_oneof![Just("Integer"), Just("Float"), Just("String"), Just("Bool"), Just("List")]
+        .prop_map(|name| token(TokenType::Identifier, name))
+}
+
+fn literal() -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        any::<bool>().prop_map(ExprKind::BoolLiteral),
+        Just(ExprKind::NilLiteral),
+        any::<u32>().prop_map(|integer| ExprKind::IntegerLiteral(integer.to_string())),
+        (0u32..1000, 0u32..1000).prop_map(|(whole, fraction)| ExprKind::FloatLiteral(format!("{}.{}", whole, fraction))),
+        "[a-zA-Z0-9 _]{0,8}".prop_map(|value| ExprKind::StringLiteral(value.into())),
+        name().prop_map(ExprKind::Variable),
+    ].prop_map(expr)
+}
+
+fn expression() -> impl Strategy<Value = Expr> {
+    literal().prop_recursive(5, 48, 4, |inner| {
+        let boxed = inner.clone().prop_map(Box::new);
+        prop_oneof![
+            boxed.clone().prop_map(ExprKind::Grouping),
+            (boxed.clone(), 0..BINARY_OPERATORS.len(), boxed.clone())
+                .prop_map(|(lhs, index, rhs)| {
+                    let (token_type, lexeme) = &BINARY_OPERATORS[index];
+                    ExprKind::Binary(lhs, operator(token_type.clone(), lexeme), rhs)
+                }),
+            (0..3, boxed.clone())
+                .prop_map(|(index, rhs)| match index {
+                    0 => ExprKind::Unary(operator(TokenType::Minus, "-"), rhs),
+                    1 => ExprKind::Unary(operator(TokenType::Bang, "!"), rhs),
+                    _ => ExprKind::Unary(operator(TokenType::Await, "await"), rhs),
+                }),
+            (boxed.clone(), any::<bool>(), boxed.clone())
+                .prop_map(|(lhs, and, rhs)| ExprKind::Logical(lhs, if and { TokenType::And } else { TokenType::Or }, rhs)),
+            (name(), boxed.clone()).prop_map(|(name, value)| ExprKind::Assign(name, value)),
+            (boxed.clone(), prop::collection::vec(element(inner.clone()), 0..3))
+                .prop_map(|(callee, arguments)| ExprKind::Call(callee, token(TokenType::RightParen, ")"), arguments)),
+            prop::collection::vec(element(inner), 0..3).prop_map(ExprKind::List),
+            (boxed.clone(), boxed)
+                .prop_map(|(list, index)| ExprKind::Index(list, token(TokenType::RightBracket, "]"), index)),
+        ].prop_map(expr)
+    })
+}
+
+// an argument or list element, the only places a spread can go
+fn element(inner: BoxedStrategy<Expr>) -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        3 => inner.clone(),
+        1 => inner.prop_map(|list| expr(ExprKind::Spread(Box::new(list)))),
+    ]
+}
+
+fn var_declaration() -> impl Strategy<Value = Statement> {
+    (name(), prop::option::of(type_name()), prop::option::of(expression().prop_map(Box::new)))
+        .prop_map(|(name, annotation, initializer)| statement(StatementKind::Var(name, annotation, initializer)))
+}
+
+// what a return or a parallel assignment gives, one value or a tuple
+fn values() -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        3 => expression(),
+        1 => prop::collection::vec(expression(), 2..4).prop_map(|values| expr(ExprKind::Tuple(values))),
+    ]
+}
+
+fn var_tuple_declaration() -> impl Strategy<Value = Statement> {
+    (prop::collection::vec(name(), 1..4), expression().prop_map(Box::new))
+        .prop_map(|(names, initializer)| statement(StatementKind::VarTuple(names, initializer)))
+}
+
+// ends in an `if` without an `else`, which would take an `else` that follows it
+fn dangles(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::If(_, _, None) => true,
+        StatementKind::If(_, _, Some(else_branch)) => dangles(else_branch),
+        StatementKind::While(_, body) | StatementKind::For(_, _, _, body) | StatementKind::With(_, _, body) => dangles(body),
+        _ => false,
+    }
+}
+
+// anything that can be the body of an if, while or for
+fn body_statement(in_function: bool) -> BoxedStrategy<Statement> {
+    let expression_statement = expression().prop_map(|value| statement(StatementKind::Expression(Box::new(value))));
+    let print = expression().prop_map(|value| statement(StatementKind::Print(Box::new(value))));
+    let assign_tuple = (prop::collection::vec(name(), 2..4), values().prop_map(Box::new))
+        .prop_map(|(names, value)| statement(StatementKind::AssignTuple(names, value)));
+    let leaf = if in_function {
+        let return_statement = prop::option::of(values().prop_map(Box::new))
+            .prop_map(|value| statement(StatementKind::Return(token(TokenType::Return, "return"), value)));
+        prop_oneof![expression_statement, print, assign_tuple, return_statement].boxed()
+    } else {
+        prop_oneof![expression_statement, print, assign_tuple].boxed()
+    };
+
+    leaf.prop_recursive(3, 24, 4, |inner| {
+        let boxed = inner.clone().prop_map(Box::new);
+        let initializer = prop_oneof![
+            var_declaration(),
+            expression().prop_map(|value| statement(StatementKind::Expression(Box::new(value)))),
+        ].prop_map(Box::new);
+        prop_oneof![
+            prop::collection::vec(prop_oneof![3 => inner.clone(), 1 => var_declaration()], 0..4)
+                .prop_map(|statements| statement(StatementKind::Block(statements))),
+            (expression().prop_map(Box::new), boxed.clone(), prop::option::of(boxed.clone()))
+                .prop_map(|(condition, then_branch, else_branch)| {
+                    let then_branch = match else_branch.is_some() && dangles(&then_branch) {
+                        true => Box::new(statement(StatementKind::Block(vec![*then_branch]))),
+                        false => then_branch,
+                    };
+                    statement(StatementKind::If(condition, then_branch, else_branch))
+                }),
+            (expression().prop_map(Box::new), boxed.clone())
+                .prop_map(|(condition, body)| statement(StatementKind::While(condition, body))),
+            (name(), expression().prop_map(Box::new), boxed.clone())
+                .prop_map(|(name, resource, body)| statement(StatementKind::With(name, resource, body))),
+            (prop::option::of(initializer), prop::option::of(expression().prop_map(Box::new)), prop::option::of(expression().prop_map(Box::new)), boxed)
+                .prop_map(|(initializer, condition, increment, body)| statement(StatementKind::For(initializer, condition, increment, body))),
+        ]
+    }).boxed()
+}
+
+fn function_declaration() -> impl Strategy<Value = Statement> {
+    let parameter = (name(), prop::option::of(type_name())).prop_map(|(name, annotation)| Parameter{name, annotation});
+    let body = prop::collection::vec(prop_oneof![3 => body_statement(true), 1 => var_declaration(), 1 => var_tuple_declaration()], 0..4);
+    let decorator = prop_oneof![
+        name().prop_map(|name| expr(ExprKind::Variable(name))),
+        (name(), prop::collection::vec(expression(), 0..3)).prop_map(|(name, arguments)| {
+            expr(ExprKind::Call(Box::new(expr(ExprKind::Variable(name))), token(TokenType::RightParen, ")"), arguments))
+        }),
+    ];
+    let decorators = prop::collection::vec(decorator, 0..3);
+    (name(), prop::collection::vec(parameter, 0..3), prop::option::of(type_name()), body, any::<bool>(), any::<bool>(), decorators)
+        .prop_map(|(name, mut parameters, return_type, body, is_async, variadic, decorators)| {
+            // a rest parameter has no annotation
+            let variadic = variadic && !parameters.is_empty();
+            if let (true, Some(rest)) = (variadic, parameters.last_mut()) {
+                rest.annotation = None;
+            }
+            statement(StatementKind::Function(Arc::new(FunctionDeclaration{name, parameters, return_type, body, is_async, variadic, decorators})))
+        })
+}
+
+fn program() -> impl Strategy<Value = Vec<Statement>> {
+    let import = "[a-z]{1,8}".prop_map(|module| {
+        statement(StatementKind::Import(token(TokenType::Import, "import"), token(TokenType::Str, &format!("\"{}\"", module))))
+    });
+    prop::collection::vec(prop_oneof![4 => body_statement(false), 2 => var_declaration(), 1 => function_declaration(), 1 => import], 0..6)
+}
+
+// The tree without spans or groupings, which is what has to survive the trip.
+fn shape(statements: &[Statement]) -> String {
+    statements.iter().map(statement_shape).collect::<Vec<String>>().join(" ")
+}
+
+fn optional<T>(value: &Option<T>, shape: impl Fn(&T) -> String) -> String {
+    value.as_ref().map_or("_".to_string(), shape)
+}
+
+fn statement_shape(statement: &Statement) -> String {
+    match &statement.kind {
+        StatementKind::Expression(value) => format!("(expression {})", expr_shape(value)),
+        StatementKind::If(condition, then_branch, else_branch) => {
+            format!("(if {} {} {})", expr_shape(condition), statement_shape(then_branch), optional(else_branch, |s| statement_shape(s)))
+        },
+        StatementKind::Print(value) => format!("(print {})", expr_shape(value)),
+        StatementKind::Var(name, annotation, initializer) => {
+            format!("(var {} {} {})", name.lexeme, optional(annotation, |t| t.lexeme.clone()), optional(initializer, |e| expr_shape(e)))
+        },
+        StatementKind::VarTuple(names, initializer) => {
+            let names: Vec<&str> = names.iter().map(|name| name.lexeme.as_str()).collect();
+            format!("(var ({}) {})", names.join(" "), expr_shape(initializer))
+        },
+        StatementKind::AssignTuple(names, value) => {
+            let names: Vec<&str> = names.iter().map(|name| name.lexeme.as_str()).collect();
+            format!("(= ({}) {})", names.join(" "), expr_shape(value))
+        },
+        StatementKind::While(condition, body) => format!("(while {} {})", expr_shape(condition), statement_shape(body)),
+        StatementKind::With(name, resource, body) => format!("(with {} {} {})", name.lexeme, expr_shape(resource), statement_shape(body)),
+        StatementKind::For(initializer, condition, increment, body) => format!(
+            "(for {} {} {} {})",
+            optional(initializer, |s| statement_shape(s)),
+            optional(condition, |e| expr_shape(e)),
+            optional(increment, |e| expr_shape(e)),
+            statement_shape(body),
+        ),
+        StatementKind::Block(statements) => format!("(block {})", shape(statements)),
+        StatementKind::Function(declaration) => {
+            let parameters: Vec<String> = declaration.parameters.iter()
+                .map(|parameter| format!("{}:{}", parameter.name.lexeme, optional(&parameter.annotation, |t| t.lexeme.clone())))
+                .collect();
+            let decorators: Vec<String> = declaration.decorators.iter().map(|decorator| format!("@{} ", expr_shape(decorator))).collect();
+            format!(
+                "({}{} {} ({}{}) {} {})",
+                decorators.concat(),
+                if declaration.is_async { "async fun" } else { "fun" },
+                declaration.name.lexeme,
+                parameters.join(" "),
+                if declaration.variadic { " ..." } else { "" },
+                optional(&declaration.return_type, |t| t.lexeme.clone()),
+                shape(&declaration.body),
+            )
+        },
+        StatementKind::Return(_, value) => format!("(return {})", optional(value, |e| expr_shape(e))),
+        StatementKind::Import(_, module) => format!("(import {})", module.lexeme),
+    }
+}
+
+fn expr_shape(e: &Expr) -> String {
+    let list = |exprs: &[Expr]| exprs.iter().map(expr_shape).collect::<Vec<String>>().join(" ");
+    match &e.kind {
+        ExprKind::Grouping(inner) => expr_shape(inner),
+        ExprKind::Binary(lhs, operator, rhs) => format!("({} {} {})", expr_shape(operator), expr_shape(lhs), expr_shape(rhs)),
+        ExprKind::Unary(operator, rhs) => format!("({} {})", expr_shape(operator), expr_shape(rhs)),
+        ExprKind::Operator(_, lexeme) => lexeme.clone(),
+        ExprKind::BoolLiteral(value) => value.to_string(),
+        ExprKind::NilLiteral => "nil".to_string(),
+        ExprKind::StringLiteral(value) => format!("{:?}", value),
+        ExprKind::IntegerLiteral(value) | ExprKind::FloatLiteral(value) => value.clone(),
+        ExprKind::Logical(lhs, token_type, rhs) => format!("({:?} {} {})", token_type, expr_shape(lhs), expr_shape(rhs)),
+        ExprKind::Variable(name) => name.lexeme.clone(),
+        ExprKind::Assign(name, value) => format!("(= {} {})", name.lexeme, expr_shape(value)),
+        ExprKind::Call(callee, _, arguments) => format!("(call {} {})", expr_shape(callee), list(arguments)),
+        ExprKind::List(elements) => format!("[{}]", list(elements)),
+        ExprKind::Index(list, _, index) => format!("(index {} {})", expr_shape(list), expr_shape(index)),
+        ExprKind::Spread(list) => format!("(... {})", expr_shape(list)),
+        ExprKind::Tuple(values) => format!("(tuple {})", list(values)),
+    }
+}
+
+fn print(statements: &[Statement]) -> String {
+    Formatter::new(&[]).format(statements)
+}
+
+fn reparse(source: &str) -> Vec<Statement> {
+    let tokens = crafty::scanner::scan_tokens(source);
+    let (statements, errors) = crafty::parser::parse(&tokens);
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert!(errors.is_empty(), "printed source doesn't parse: {:?}\n{}", messages, source);
+    statements
+}
+
+// the tokens that matter, trivia and positions left out
+fn tokens(source: &str) -> Vec<(TokenType, String)> {
+    crafty::scanner::scan_tokens(source).into_iter()
+        .filter(|token| !token.token_type.is_trivia())
+        .map(|token| (token.token_type, token.lexeme))
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn printed_trees_parse_back(statements in program()) {
+        let source = print(&statements);
+        let reparsed = reparse(&source);
+        prop_assert_eq!(shape(&reparsed), shape(&statements), "source:\n{}", source);
+    }
+
+    #[test]
+    fn printed_programs_scan_the_same(statements in program()) {
+        let source = print(&statements);
+        let reprinted = print(&reparse(&source));
+        prop_assert_eq!(tokens(&reprinted), tokens(&source));
+    }
+}
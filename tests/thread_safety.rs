@@ -0,0 +1,132 @@
+use std::convert::TryFrom;
+use std::io;
+use std::io::Write;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use crafty::runtime::Environment;
+use crafty::runtime::ExprEvaluator;
+use crafty::Interpreter;
+use crafty::parse_source;
+use crafty::Value;
+
+const THREADS: i64 = 16;
+const ROUNDS: i64 = 20;
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn interpreter_types_are_send() {
+    assert_send::<Interpreter>();
+    assert_send::<ExprEvaluator>();
+    assert_send::<Environment>();
+    assert_send::<Value>();
+}
+
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// every thread runs its own interpreter; none of them may see another's globals or output
+#[test]
+fn independent_interpreters_run_concurrently() {
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let handles: Vec<_> = (0..THREADS).map(|id| {
+        let calls = calls.clone();
+        thread::spawn(move || {
+            let printed = Arc::new(Mutex::new(Vec::new()));
+            let mut interpreter = Interpreter::new();
+            interpreter.set_output(Box::new(Captured(printed.clone())));
+            interpreter.register_fn_with_arity("id", 0, move |_args| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(Value::from(id))
+            });
+
+            interpreter.interpret("var total = 0;").unwrap();
+            for round in 0..ROUNDS {
+                let source = format!("for (var i = 0; i < 100; i = i + 1) {{ total = total + id(); }} print {};", round);
+                interpreter.interpret(&source).unwrap();
+            }
+            let total = i64::try_from(interpreter.interpret("total;").unwrap()).unwrap();
+
+            let printed = String::from_utf8(printed.lock().unwrap().clone()).unwrap();
+            (id, total, printed)
+        })
+    }).collect();
+
+    let expected_output: String = (0..ROUNDS).map(|round| format!("{}\n", round)).collect();
+    for handle in handles {
+        let (id, total, printed) = handle.join().unwrap();
+        assert_eq!(total, id * 100 * ROUNDS);
+        assert_eq!(printed, expected_output);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), (THREADS * 100 * ROUNDS) as usize);
+}
+
+// state built up on one thread carries over when the interpreter moves to another
+#[test]
+fn interpreter_moves_between_threads() {
+    let mut interpreter = Interpreter::new();
+    interpreter.interpret("var greeting = 40;").unwrap();
+
+    let value = thread::spawn(move || interpreter.interpret("greeting + 2;").unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(i64::try_from(value).unwrap(), 42);
+}
+
+// globals remember their slot in the program, which has to keep working when interpreters that
+// laid their globals out differently share it
+#[test]
+fn interpreters_share_a_parsed_program() {
+    let program = Arc::new(parse_source("fun add() { total = total + step; } for (var i = 0; i < 10; i = i + 1) add();").unwrap());
+
+    let handles: Vec<_> = (0..THREADS).map(|id| {
+        let program = program.clone();
+        thread::spawn(move || {
+            let mut interpreter = Interpreter::new();
+            let padding: String = (0..id).map(|n| format!("var padding{} = nil;", n)).collect();
+            let globals = if id % 2 == 0 { "var total = 0; var step = 0;" } else { "var step = 0; var total = 0;" };
+            interpreter.interpret(&format!("{} {} step = {};", padding, globals, id)).unwrap();
+            for _ in 0..ROUNDS {
+                interpreter.execute(&program).unwrap();
+            }
+            (id, i64::try_from(interpreter.interpret("total;").unwrap()).unwrap())
+        })
+    }).collect();
+
+    for handle in handles {
+        let (id, total) = handle.join().unwrap();
+        assert_eq!(total, id * 10 * ROUNDS);
+    }
+}
+
+// a panic on a coroutine's thread fails the resume, and the interpreter carries on with its
+// globals and output rather than whatever stood in for it meanwhile
+#[test]
+fn panicking_coroutine_hands_the_interpreter_back() {
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(Captured(printed.clone())));
+    interpreter.register_fn("explode", |_args| panic!("host function failed"));
+
+    interpreter.interpret("var kept = 1; fun body() { explode(); }").unwrap();
+    let error = interpreter.interpret("resume(coroutine(body));").unwrap_err();
+    assert!(error.to_string().contains("Coroutine panicked: host function failed"), "{}", error);
+    interpreter.interpret("print kept + 1;").unwrap();
+
+    let printed = String::from_utf8(printed.lock().unwrap().clone()).unwrap();
+    assert_eq!(printed, "2\n");
+}